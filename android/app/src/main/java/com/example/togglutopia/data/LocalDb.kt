@@ -51,6 +51,14 @@ class LocalDb(context: Context) {
             }
         }
 
+    var sessionToken: String?
+        get() = sharedPreferences.getString(SESSION_TOKEN_KEY, null)
+        set(value) {
+            sharedPreferences.edit(commit = true) {
+                putString(SESSION_TOKEN_KEY, value)
+            }
+        }
+
     private fun <T> getListAdapter(clazz: Class<T>): JsonAdapter<List<T>> {
         val type = Types.newParameterizedType(List::class.java, clazz)
         return moshi.adapter<List<T>>(type)
@@ -62,5 +70,6 @@ class LocalDb(context: Context) {
         const val TIME_ENTRIES_KEY = "time_entries"
         const val PROJECTS_KEY = "projects"
         const val LAST_SYNC_KEY = "last_sync"
+        const val SESSION_TOKEN_KEY = "session_token"
     }
 }
\ No newline at end of file
@@ -48,15 +48,16 @@ class Repository(context: Context) {
 
     fun login(username: String, password: String) {
         val credentials = Credentials.basic(username, password)
-        api.login(credentials).enqueue(SnapshotResponseHandler {
-            localDb.lastSync = it
+        api.login(credentials).enqueue(SnapshotResponseHandler { lastSync, sessionToken ->
+            localDb.lastSync = lastSync
+            localDb.sessionToken = sessionToken
             localDb.persistState()
         })
     }
 
     fun sync() {
-        TogglState.user?.api_token?.let { apiToken ->
-            val credentials = "Bearer $apiToken"
+        localDb.sessionToken?.let { sessionToken ->
+            val credentials = "Bearer $sessionToken"
             api.sync(credentials, getSyncRequest()).enqueue(SyncResponseHandler { localDb.lastSync = it })
         }
     }
@@ -89,6 +90,9 @@ class SyncResponseHandler(val onSuccess: (String) -> Unit) : Callback<SyncRespon
                     UpdateType.Created -> {
                         TogglState.editTimeEntry(entityUpdate)
                     }
+                    UpdateType.AutoStopped -> {
+                        TogglState.editTimeEntry(entityUpdate)
+                    }
                     UpdateType.Deleted -> {
                         TogglState.deleteTimeEntry(entityUpdate)
                     }
@@ -99,7 +103,7 @@ class SyncResponseHandler(val onSuccess: (String) -> Unit) : Callback<SyncRespon
     }
 }
 
-class SnapshotResponseHandler(val onSuccess: (String) -> Unit) : Callback<SnapshotResponse> {
+class SnapshotResponseHandler(val onSuccess: (String, String) -> Unit) : Callback<SnapshotResponse> {
     override fun onFailure(call: Call<SnapshotResponse>, t: Throwable) {
         Log.d("SnapshotResponseHandler", "onFailure() called with: call = $call, t = $t")
     }
@@ -119,7 +123,11 @@ class SnapshotResponseHandler(val onSuccess: (String) -> Unit) : Callback<Snapsh
                 }
             }
 
-            response.body()?.meta?.utc_server_time?.let { onSuccess(it) }
+            val sessionToken = response.body()?.payload?.session?.token
+            val lastSync = response.body()?.meta?.utc_server_time
+            if (lastSync != null && sessionToken != null) {
+                onSuccess(lastSync, sessionToken)
+            }
         }
     }
 }
\ No newline at end of file
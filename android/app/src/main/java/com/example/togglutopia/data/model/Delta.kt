@@ -1,5 +1,6 @@
 package com.example.togglutopia.data.model
 
 data class Delta(
-    val time_entries: List<TimeEntry>
+    val time_entries: List<TimeEntry>,
+    val favourites: List<Favourite> = emptyList()
 )
\ No newline at end of file
@@ -0,0 +1,7 @@
+package com.example.togglutopia.data.model
+
+data class Earnings(
+        val amount: Double,
+        val hourly_rate: Double,
+        val currency: String?
+)
@@ -3,9 +3,14 @@ package com.example.togglutopia.data.model
 data class EntityUpdate<T>(
     val type: UpdateType,
     val client_assigned_id: Int?,
-    val entity: T
+    val entity: T,
+    val reason: StopReason? = null
 )
 
 enum class UpdateType {
-    Changed, Created, Deleted
+    Changed, Created, Deleted, AutoStopped
+}
+
+enum class StopReason {
+    max_duration, time_of_day, newer_entry
 }
\ No newline at end of file
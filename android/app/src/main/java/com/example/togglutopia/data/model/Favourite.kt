@@ -0,0 +1,12 @@
+package com.example.togglutopia.data.model
+
+data class Favourite(
+        val id: Int,
+        val at: String,
+        val description: String,
+        val project_id: Int?,
+        val server_deleted_at: String?,
+        val workspace_id: Int,
+        val tags: List<String> = emptyList(),
+        val billable: Boolean = false
+)
@@ -0,0 +1,6 @@
+package com.example.togglutopia.data.model
+
+data class Occurrence(
+        val recurrence_id: Int,
+        val date: String
+)
@@ -6,5 +6,9 @@ data class Project(
     val color: String,
     val id: Int,
     val name: String,
-    val server_deleted_at: Any
+    val server_deleted_at: Any,
+    val billable: Boolean = false,
+    val rate: Double? = null,
+    val currency: String? = null,
+    val stats: ProjectStats? = null
 )
\ No newline at end of file
@@ -0,0 +1,8 @@
+package com.example.togglutopia.data.model
+
+data class ProjectStats(
+    val total_seconds: Long,
+    val this_week_seconds: Long,
+    val last_used: String?,
+    val entry_count: Int
+)
@@ -0,0 +1,6 @@
+package com.example.togglutopia.data.model
+
+data class Session(
+    val token: String,
+    val expires_at: String
+)
\ No newline at end of file
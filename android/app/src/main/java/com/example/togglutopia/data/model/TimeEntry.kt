@@ -9,5 +9,9 @@ data class TimeEntry(
         val server_deleted_at: String?,
         val start: String,
         val workspace_id: Int,
+        val tags: List<String> = emptyList(),
+        val billable: Boolean = false,
+        val earnings: Earnings? = null,
+        val recurrence: Occurrence? = null,
         val edited: Boolean = false
 )
\ No newline at end of file
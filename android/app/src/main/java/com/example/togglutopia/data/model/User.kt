@@ -1,9 +1,10 @@
 package com.example.togglutopia.data.model
 
 data class User(
-    val api_token: String,
     val at: String,
     val fullname: String,
     val id: Int,
-    val default_workspace_id: Int?
+    val default_workspace_id: Int?,
+    val timezone: String? = null,
+    val beginning_of_week: Int = 1
 )
\ No newline at end of file
@@ -1,7 +1,9 @@
 package com.example.togglutopia.data.model.response
 
+import com.example.togglutopia.data.model.Favourite
 import com.example.togglutopia.data.model.Meta
 import com.example.togglutopia.data.model.Project
+import com.example.togglutopia.data.model.Session
 import com.example.togglutopia.data.model.TimeEntry
 import com.example.togglutopia.data.model.User
 
@@ -11,7 +13,9 @@ data class SnapshotResponse(
 )
 
 data class SnapshotPayload(
+        val session: Session,
         val projects: List<Project>,
         val time_entries: List<TimeEntry>,
+        val favourites: List<Favourite> = emptyList(),
         val user: User
 )
\ No newline at end of file
@@ -10,5 +10,6 @@ data class SyncResponse(
 data class SyncPayload(
         val user: EntityUpdate<User>,
         val projects: List<EntityUpdate<Project>>,
-        val time_entries: List<EntityUpdate<TimeEntry>>
+        val time_entries: List<EntityUpdate<TimeEntry>>,
+        val favourites: List<EntityUpdate<Favourite>> = emptyList()
 )
\ No newline at end of file
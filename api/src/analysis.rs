@@ -0,0 +1,410 @@
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{Error, ValidationError};
+use crate::models::{Delta, TimeEntry};
+use crate::sync::prelude::{SyncOutcome, SyncResult};
+use crate::sync::server;
+use crate::toggl_api::{models::Id, TogglApi};
+
+/// The gaps are looked for day by day, so the analyzed range cannot be longer than this.
+const MAX_RANGE_DAYS: i64 = 366;
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct Overlap {
+    pub time_entry_ids: [Id; 2],
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub seconds: u64,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct Gap {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub seconds: u64,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Warning {
+    Overlap(Overlap),
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Analysis {
+    pub overlaps: Vec<Overlap>,
+    pub gaps: Vec<Gap>,
+}
+
+/// The part of the day in which the user is expected to track time.
+fn validate_range(from: DateTime<Utc>, to: DateTime<Utc>) -> Result<(), Error> {
+    let reason = if from >= to {
+        "The end of the range must be after its beginning.".to_string()
+    } else if to.signed_duration_since(from) > Duration::days(MAX_RANGE_DAYS) {
+        format!("The range cannot be longer than {} days.", MAX_RANGE_DAYS)
+    } else {
+        return Ok(());
+    };
+
+    Err(Error::Validation(vec![ValidationError {
+        field: Some("to".to_string()),
+        reason,
+    }]))
+}
+
+pub struct WorkingHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub include_weekends: bool,
+    pub timezone: Tz,
+}
+
+/// Finds the overlapping time entries and the untracked gaps within the working hours
+/// in the given range.
+pub fn analyze(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    working_hours: &WorkingHours,
+    min_gap: Duration,
+    api: &TogglApi,
+) -> Result<Analysis, Error> {
+    validate_range(from, to)?;
+
+    let now = Utc::now();
+    let time_entries: Vec<_> = server::fetch_time_entries_reaching_into(from, to, api)?
+        .into_iter()
+        .filter(|te| {
+            let (start, end) = interval(te, now);
+            start < to && end > from
+        })
+        .collect();
+
+    Ok(Analysis {
+        overlaps: find_overlaps(&time_entries, now),
+        gaps: find_gaps(&time_entries, from, to, working_hours, min_gap, now),
+    })
+}
+
+/// Adds a warning to the sync outcome for each overlap which involves a time entry
+/// which was changed on either side during the sync. The changes from the client which
+/// Toggl rejected aren't considered. The warnings are best effort, if Toggl cannot be
+/// reached, the outcome is returned as it is.
+pub fn with_warnings(outcome: SyncOutcome, client_delta: &Delta, api: &TogglApi) -> SyncOutcome {
+    let accepted = outcome.accepted_from(client_delta);
+    let touched: Vec<TimeEntry> = accepted
+        .time_entries
+        .iter()
+        .flatten()
+        .filter(|te| te.id > 0)
+        .chain(
+            outcome
+                .time_entries
+                .iter()
+                .filter_map(|result| match result {
+                    SyncResult::Created { entity, .. }
+                    | SyncResult::Changed { entity }
+                    | SyncResult::AutoStopped { entity, .. } => Some(entity),
+                    SyncResult::Deleted { .. } | SyncResult::Failed { .. } => None,
+                }),
+        )
+        .filter(|te| te.server_deleted_at.is_none())
+        .cloned()
+        .collect();
+
+    let now = Utc::now();
+    let intervals: Vec<_> = touched.iter().map(|te| interval(te, now)).collect();
+    let (from, to) = match (
+        intervals.iter().map(|(start, _)| *start).min(),
+        intervals.iter().map(|(_, end)| *end).max(),
+    ) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return outcome,
+    };
+
+    let existing = match server::fetch_time_entries_reaching_into(from, to, api) {
+        Ok(existing) => existing,
+        Err(_) => return outcome,
+    };
+
+    // the changed versions of the time entries take precedence over what Toggl returned
+    let mut time_entries: HashMap<Id, TimeEntry> =
+        existing.into_iter().map(|te| (te.id, te)).collect();
+    for te in touched.iter() {
+        time_entries.insert(te.id, te.clone());
+    }
+    let time_entries: Vec<_> = time_entries.into_values().collect();
+
+    let touched_ids: HashSet<Id> = touched.iter().map(|te| te.id).collect();
+    let warnings = find_overlaps(&time_entries, now)
+        .into_iter()
+        .filter(|overlap| {
+            overlap
+                .time_entry_ids
+                .iter()
+                .any(|id| touched_ids.contains(id))
+        })
+        .map(Warning::Overlap)
+        .collect();
+
+    SyncOutcome {
+        warnings,
+        ..outcome
+    }
+}
+
+/// Finds all pairs of time entries which overlap. Running time entries last until `now`.
+pub fn find_overlaps(time_entries: &[TimeEntry], now: DateTime<Utc>) -> Vec<Overlap> {
+    let mut intervals: Vec<_> = time_entries
+        .iter()
+        .filter(|te| te.server_deleted_at.is_none())
+        .map(|te| (te.id, interval(te, now)))
+        .collect();
+    intervals.sort_by_key(|(id, (start, _))| (*start, *id));
+
+    let mut overlaps = vec![];
+    for (i, (first_id, (_, first_end))) in intervals.iter().enumerate() {
+        for (second_id, (second_start, second_end)) in intervals[i + 1..].iter() {
+            if second_start >= first_end {
+                break;
+            }
+
+            let end = std::cmp::min(*first_end, *second_end);
+            if end > *second_start {
+                overlaps.push(Overlap {
+                    time_entry_ids: [*first_id, *second_id],
+                    start: *second_start,
+                    end,
+                    seconds: end.signed_duration_since(*second_start).num_seconds() as u64,
+                });
+            }
+        }
+    }
+
+    overlaps
+}
+
+/// Finds the parts of the working hours in the given range which aren't covered by any
+/// time entry and which are at least `min_gap` long. The future isn't considered.
+pub fn find_gaps(
+    time_entries: &[TimeEntry],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    working_hours: &WorkingHours,
+    min_gap: Duration,
+    now: DateTime<Utc>,
+) -> Vec<Gap> {
+    let tracked = union(
+        time_entries
+            .iter()
+            .filter(|te| te.server_deleted_at.is_none())
+            .map(|te| interval(te, now))
+            .collect(),
+    );
+    let to = std::cmp::min(to, now);
+    let timezone = &working_hours.timezone;
+
+    let mut gaps = vec![];
+    let mut date = from.with_timezone(timezone).naive_local().date();
+    let last_date = to.with_timezone(timezone).naive_local().date();
+
+    while date <= last_date {
+        let weekend = date.weekday() == Weekday::Sat || date.weekday() == Weekday::Sun;
+        let start = timezone
+            .from_local_datetime(&date.and_time(working_hours.start))
+            .earliest();
+        let end = timezone
+            .from_local_datetime(&date.and_time(working_hours.end))
+            .latest();
+
+        if let (Some(start), Some(end)) = (start, end) {
+            if !weekend || working_hours.include_weekends {
+                let start = std::cmp::max(start.with_timezone(&Utc), from);
+                let end = std::cmp::min(end.with_timezone(&Utc), to);
+                gaps.extend(
+                    uncovered(start, end, &tracked)
+                        .into_iter()
+                        .filter(|gap| gap.end.signed_duration_since(gap.start) >= min_gap),
+                );
+            }
+        }
+
+        date = date.succ();
+    }
+
+    gaps
+}
+
+fn interval(te: &TimeEntry, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let duration = te.duration.unwrap_or_else(|| te.elapsed_seconds_until(now));
+    (te.start, te.start + Duration::seconds(duration as i64))
+}
+
+/// Merges the overlapping intervals into a sorted list of disjoint intervals.
+fn union(
+    mut intervals: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    intervals.sort();
+
+    let mut merged: Vec<(DateTime<Utc>, DateTime<Utc>)> = vec![];
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end => {
+                *last_end = std::cmp::max(*last_end, end);
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+fn uncovered(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    tracked: &[(DateTime<Utc>, DateTime<Utc>)],
+) -> Vec<Gap> {
+    let mut gaps = vec![];
+    if start >= end {
+        return gaps;
+    }
+
+    let mut cursor = start;
+
+    for (tracked_start, tracked_end) in tracked {
+        if *tracked_end <= cursor {
+            continue;
+        }
+        if *tracked_start >= end {
+            break;
+        }
+        if *tracked_start > cursor {
+            gaps.push(gap(cursor, *tracked_start));
+        }
+        cursor = std::cmp::max(cursor, *tracked_end);
+    }
+
+    if cursor < end {
+        gaps.push(gap(cursor, end));
+    }
+
+    gaps
+}
+
+fn gap(start: DateTime<Utc>, end: DateTime<Utc>) -> Gap {
+    Gap {
+        start,
+        end,
+        seconds: end.signed_duration_since(start).num_seconds() as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_gaps, find_overlaps, validate_range, WorkingHours};
+    use crate::models::{fixtures, TimeEntry};
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2019, 12, day).and_hms(hour, minute, 0)
+    }
+
+    fn time_entry(id: Id, start: DateTime<Utc>, minutes: Option<i64>) -> TimeEntry {
+        fixtures::time_entry(
+            id,
+            start,
+            minutes.map(|minutes| Duration::minutes(minutes).num_seconds() as u64),
+        )
+    }
+
+    fn working_hours() -> WorkingHours {
+        WorkingHours {
+            start: NaiveTime::from_hms(9, 0, 0),
+            end: NaiveTime::from_hms(17, 0, 0),
+            include_weekends: false,
+            timezone: Tz::UTC,
+        }
+    }
+
+    #[test]
+    fn finds_overlapping_pairs() {
+        let time_entries = vec![
+            time_entry(1, at(10, 9, 0), Some(60)),
+            time_entry(2, at(10, 9, 30), Some(60)),
+            time_entry(3, at(10, 10, 15), Some(30)),
+            time_entry(4, at(10, 11, 0), Some(30)),
+        ];
+
+        let overlaps = find_overlaps(&time_entries, at(10, 12, 0));
+
+        assert_eq!(overlaps.len(), 2);
+        assert_eq!(overlaps[0].time_entry_ids, [1, 2]);
+        assert_eq!(overlaps[0].seconds, 1800);
+        assert_eq!(overlaps[1].time_entry_ids, [2, 3]);
+        assert_eq!(overlaps[1].end, at(10, 10, 30));
+    }
+
+    #[test]
+    fn running_entries_overlap_until_now() {
+        let time_entries = vec![
+            time_entry(1, at(10, 9, 0), None),
+            time_entry(2, at(10, 10, 0), Some(30)),
+        ];
+
+        let overlaps = find_overlaps(&time_entries, at(10, 12, 0));
+
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].time_entry_ids, [1, 2]);
+    }
+
+    #[test]
+    fn finds_gaps_within_working_hours() {
+        // Tuesday
+        let time_entries = vec![
+            time_entry(1, at(10, 8, 0), Some(120)),
+            time_entry(2, at(10, 10, 30), Some(30)),
+            time_entry(3, at(10, 11, 0), Some(360)),
+        ];
+
+        let gaps = find_gaps(
+            &time_entries,
+            at(10, 0, 0),
+            at(11, 0, 0),
+            &working_hours(),
+            Duration::minutes(15),
+            at(20, 0, 0),
+        );
+
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].start, at(10, 10, 0));
+        assert_eq!(gaps[0].end, at(10, 10, 30));
+    }
+
+    #[test]
+    fn skips_weekends_and_the_future() {
+        // Saturday and Sunday, then Monday until noon
+        let gaps = find_gaps(
+            &[],
+            at(14, 0, 0),
+            at(17, 0, 0),
+            &working_hours(),
+            Duration::minutes(15),
+            at(16, 12, 0),
+        );
+
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].start, at(16, 9, 0));
+        assert_eq!(gaps[0].end, at(16, 12, 0));
+    }
+
+    #[test]
+    fn rejects_empty_and_too_long_ranges() {
+        assert!(validate_range(at(10, 9, 0), at(11, 9, 0)).is_ok());
+        assert!(validate_range(at(10, 9, 0), at(10, 9, 0)).is_err());
+        assert!(validate_range(at(10, 9, 0), at(10, 9, 0) + Duration::days(400)).is_err());
+    }
+}
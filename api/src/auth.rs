@@ -1,4 +1,9 @@
-#[derive(Debug, PartialEq)]
+pub mod middleware;
+
+use actix_web::http::StatusCode;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Credentials {
     UsernamePassword(String, String),
     Token(String),
@@ -6,51 +11,181 @@ pub enum Credentials {
 
 use Credentials::{Token, UsernamePassword};
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AuthError {
+    MissingCredentials,
+    MalformedCredentials,
+    UnsupportedScheme,
+    InvalidSession,
+    RejectedUpstream,
+    Forbidden,
+}
+
+impl AuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::MissingCredentials => "missing_credentials",
+            AuthError::MalformedCredentials => "malformed_credentials",
+            AuthError::UnsupportedScheme => "unsupported_auth_scheme",
+            AuthError::InvalidSession => "invalid_session",
+            AuthError::RejectedUpstream => "rejected_credentials",
+            AuthError::Forbidden => "forbidden",
+        }
+    }
+
+    /// The client isn't authenticated unless Toggl refuses to let an authenticated user
+    /// access something.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AuthError::Forbidden => StatusCode::FORBIDDEN,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            AuthError::MissingCredentials => "The request doesn't contain any credentials.",
+            AuthError::MalformedCredentials => "The Authorization header is malformed.",
+            AuthError::UnsupportedScheme => {
+                "The authentication scheme isn't supported by this endpoint."
+            }
+            AuthError::InvalidSession => "The session is invalid, expired or revoked.",
+            AuthError::RejectedUpstream => "The credentials you provided are invalid.",
+            AuthError::Forbidden => "You are not allowed to access this resource.",
+        };
+
+        write!(f, "{}", msg)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Scheme {
+    Basic,
+    Bearer,
+}
+
+const REALM: &str = "toggl-utopia";
+
+impl Scheme {
+    /// The value of the `WWW-Authenticate` header (RFC 7617, RFC 6750) for the given failure.
+    pub fn challenge(self, err: AuthError) -> String {
+        match self {
+            Scheme::Basic => format!("Basic realm=\"{}\", charset=\"UTF-8\"", REALM),
+            Scheme::Bearer => match err {
+                AuthError::MissingCredentials | AuthError::UnsupportedScheme => {
+                    format!("Bearer realm=\"{}\"", REALM)
+                }
+                AuthError::MalformedCredentials => {
+                    format!("Bearer realm=\"{}\", error=\"invalid_request\"", REALM)
+                }
+                _ => format!("Bearer realm=\"{}\", error=\"invalid_token\"", REALM),
+            },
+        }
+    }
+}
+
 impl Credentials {
-    pub fn into_basic(self: Self) -> (String, String) {
+    pub fn into_basic(self) -> (String, String) {
         match self {
             UsernamePassword(username, password) => (username, password),
             Token(token) => (token, "api_token".to_string()),
         }
     }
 
-    fn decode_username_and_password(encoded_data: &str) -> Option<Credentials> {
+    pub fn scheme(&self) -> Scheme {
+        match self {
+            UsernamePassword(_, _) => Scheme::Basic,
+            Token(_) => Scheme::Bearer,
+        }
+    }
+
+    fn decode_username_and_password(encoded_data: &str) -> Result<Credentials, AuthError> {
         let decoded = base64::decode(encoded_data)
             .ok()
-            .and_then(|bytes| String::from_utf8(bytes).ok())?;
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or(AuthError::MalformedCredentials)?;
 
-        match decoded.split(':').collect::<Vec<_>>().as_slice() {
-            [username, password] => {
-                Some(UsernamePassword(username.to_string(), password.to_string()))
-            }
-            _ => None,
+        // The user-id cannot contain a colon, but the password can (RFC 7617, section 2)
+        match decoded.find(':') {
+            Some(index) if index > 0 => Ok(UsernamePassword(
+                decoded[..index].to_string(),
+                decoded[index + 1..].to_string(),
+            )),
+            _ => Err(AuthError::MalformedCredentials),
         }
     }
 
-    pub fn decode(auth_header: &str) -> Option<Credentials> {
-        if auth_header.starts_with("Basic ") {
-            let encoded_data = &auth_header["Basic ".len()..];
-            return Credentials::decode_username_and_password(&encoded_data);
+    pub fn decode(auth_header: &str) -> Result<Credentials, AuthError> {
+        let auth_header = auth_header.trim();
+        let (scheme, data) = match auth_header.find(' ') {
+            Some(index) => (&auth_header[..index], auth_header[index..].trim_start()),
+            None => (auth_header, ""),
+        };
+
+        if scheme.eq_ignore_ascii_case("Basic") {
+            if data.is_empty() {
+                return Err(AuthError::MalformedCredentials);
+            }
+            return Credentials::decode_username_and_password(data);
         }
 
-        if auth_header.starts_with("Bearer ") {
-            let token = &auth_header["Bearer ".len()..];
-            return Some(Token(token.to_string()));
+        if scheme.eq_ignore_ascii_case("Bearer") {
+            if data.is_empty() || data.contains(char::is_whitespace) {
+                return Err(AuthError::MalformedCredentials);
+            }
+            return Ok(Token(data.to_string()));
         }
 
-        None
+        if data.is_empty() {
+            Err(AuthError::MalformedCredentials)
+        } else {
+            Err(AuthError::UnsupportedScheme)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Credentials;
     use super::Credentials::{Token, UsernamePassword};
+    use super::{AuthError, Credentials};
 
     #[test]
     fn rejects_incorrectly_formatted_geader() {
         let credentials = Credentials::decode("garbage");
-        assert_eq!(credentials, None);
+        assert_eq!(credentials, Err(AuthError::MalformedCredentials));
+    }
+
+    #[test]
+    fn rejects_unknown_scheme() {
+        let credentials = Credentials::decode("Digest username=\"abc\"");
+        assert_eq!(credentials, Err(AuthError::UnsupportedScheme));
+    }
+
+    #[test]
+    fn rejects_empty_credentials() {
+        assert_eq!(
+            Credentials::decode("Basic "),
+            Err(AuthError::MalformedCredentials)
+        );
+        assert_eq!(
+            Credentials::decode("Bearer"),
+            Err(AuthError::MalformedCredentials)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_base64() {
+        let credentials = Credentials::decode("Basic not*base64");
+        assert_eq!(credentials, Err(AuthError::MalformedCredentials));
+    }
+
+    #[test]
+    fn rejects_missing_colon() {
+        let header = format!("Basic {}", base64::encode("username"));
+        let credentials = Credentials::decode(&header);
+        assert_eq!(credentials, Err(AuthError::MalformedCredentials));
     }
 
     #[test]
@@ -59,17 +194,53 @@ mod tests {
         let credentials = Credentials::decode(&header);
         assert_eq!(
             credentials,
-            Some(UsernamePassword(
+            Ok(UsernamePassword(
                 String::from("some@username.com"),
                 String::from("pass123")
             ))
         );
     }
 
+    #[test]
+    fn allows_colons_in_password() {
+        let header = format!("Basic {}", base64::encode("user:pa:ss:"));
+        let credentials = Credentials::decode(&header);
+        assert_eq!(
+            credentials,
+            Ok(UsernamePassword(
+                String::from("user"),
+                String::from("pa:ss:")
+            ))
+        );
+    }
+
+    #[test]
+    fn allows_empty_password() {
+        let header = format!("Basic {}", base64::encode("user:"));
+        let credentials = Credentials::decode(&header);
+        assert_eq!(
+            credentials,
+            Ok(UsernamePassword(String::from("user"), String::new()))
+        );
+    }
+
+    #[test]
+    fn scheme_is_case_insensitive() {
+        let header = format!("bAsIc   {}", base64::encode("user:pass"));
+        let credentials = Credentials::decode(&header);
+        assert_eq!(
+            credentials,
+            Ok(UsernamePassword(String::from("user"), String::from("pass")))
+        );
+
+        let credentials = Credentials::decode("BEARER some_token");
+        assert_eq!(credentials, Ok(Token(String::from("some_token"))));
+    }
+
     #[test]
     fn extracts_correct_token() {
         let header = "Bearer some_token";
         let credentials = Credentials::decode(&header);
-        assert_eq!(credentials, Some(Token(String::from("some_token"))));
+        assert_eq!(credentials, Ok(Token(String::from("some_token"))));
     }
 }
@@ -0,0 +1,248 @@
+use actix_service::{Service, Transform};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::{http::header, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use chrono::Utc;
+use futures::future::{ok, Either, FutureResult};
+use futures::Poll;
+
+use super::{AuthError, Credentials, Scheme};
+use crate::responses::authentication_failed;
+use crate::session::{Session, Sessions};
+
+/// Rejects requests which aren't authenticated with the given scheme before they reach
+/// the handler. Handlers then extract the `Credentials` (Basic) or the `Session` (Bearer).
+pub struct Authentication(Scheme);
+
+impl Authentication {
+    /// Raw Toggl credentials, these are accepted only when logging in. Besides the username
+    /// and the password, a Toggl API token can still be sent with the Bearer scheme.
+    pub fn basic() -> Authentication {
+        Authentication(Scheme::Basic)
+    }
+
+    /// Session tokens issued by the proxy.
+    pub fn bearer() -> Authentication {
+        Authentication(Scheme::Bearer)
+    }
+}
+
+impl<S, B> Transform<S> for Authentication
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthenticationMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AuthenticationMiddleware {
+            service,
+            scheme: self.0,
+        })
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: S,
+    scheme: Scheme,
+}
+
+impl<S, B> Service for AuthenticationMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Either<S::Future, FutureResult<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        match authenticate(&req, self.scheme) {
+            Ok(()) => Either::A(self.service.call(req)),
+            Err(err) => {
+                let res = authentication_failed(err, Some(self.scheme), Utc::now());
+                Either::B(ok(req.into_response(res.into_body())))
+            }
+        }
+    }
+}
+
+fn authenticate(req: &ServiceRequest, scheme: Scheme) -> Result<(), AuthError> {
+    let credentials = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .ok_or(AuthError::MissingCredentials)?
+        .to_str()
+        .map_err(|_| AuthError::MalformedCredentials)
+        .and_then(Credentials::decode)?;
+
+    // Toggl API tokens are raw credentials too, only sessions require the Bearer scheme
+    if scheme == Scheme::Bearer && credentials.scheme() != scheme {
+        return Err(AuthError::UnsupportedScheme);
+    }
+
+    match credentials {
+        Credentials::Token(token) if scheme == Scheme::Bearer => {
+            let session = req
+                .app_data::<Sessions>()
+                .and_then(|sessions| sessions.verify(&token))
+                .ok_or(AuthError::InvalidSession)?;
+            req.extensions_mut().insert(session);
+        }
+        credentials => {
+            req.extensions_mut().insert(credentials);
+        }
+    }
+
+    Ok(())
+}
+
+impl actix_web::ResponseError for AuthError {
+    fn error_response(&self) -> HttpResponse {
+        authentication_failed(*self, None, Utc::now())
+    }
+
+    fn render_response(&self) -> HttpResponse {
+        self.error_response()
+    }
+}
+
+impl FromRequest for Credentials {
+    type Config = ();
+    type Error = AuthError;
+    type Future = Result<Self, Self::Error>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        req.extensions()
+            .get::<Credentials>()
+            .cloned()
+            .ok_or(AuthError::MissingCredentials)
+    }
+}
+
+impl FromRequest for Session {
+    type Config = ();
+    type Error = AuthError;
+    type Future = Result<Self, Self::Error>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        req.extensions()
+            .get::<Session>()
+            .cloned()
+            .ok_or(AuthError::MissingCredentials)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Authentication;
+    use crate::auth::Credentials;
+    use crate::session::{Session, Sessions};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App, HttpResponse};
+    use chrono::Duration;
+
+    fn sessions() -> web::Data<Sessions> {
+        web::Data::new(Sessions::with_secret(b"secret".to_vec(), Duration::days(1)))
+    }
+
+    fn session_user(session: Session) -> HttpResponse {
+        HttpResponse::Ok().body(session.user_id.to_string())
+    }
+
+    fn login_scheme(credentials: Credentials) -> HttpResponse {
+        HttpResponse::Ok().body(format!("{:?}", credentials.scheme()))
+    }
+
+    fn status_and_challenge(
+        sessions: web::Data<Sessions>,
+        auth: Option<&str>,
+    ) -> (StatusCode, Option<String>) {
+        let mut app = test::init_service(
+            App::new().register_data(sessions).service(
+                web::resource("/")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(session_user)),
+            ),
+        );
+
+        let req = match auth {
+            Some(auth) => test::TestRequest::get().header(header::AUTHORIZATION, auth),
+            None => test::TestRequest::get(),
+        };
+        let res = test::call_service(&mut app, req.to_request());
+        let challenge = res
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .map(|value| value.to_str().unwrap().to_string());
+
+        (res.status(), challenge)
+    }
+
+    #[test]
+    fn rejects_request_without_credentials() {
+        let (status, challenge) = status_and_challenge(sessions(), None);
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(challenge, Some("Bearer realm=\"toggl-utopia\"".to_string()));
+    }
+
+    #[test]
+    fn rejects_raw_credentials_where_session_is_required() {
+        let basic = format!("Basic {}", base64::encode("user:pass"));
+        let (status, challenge) = status_and_challenge(sessions(), Some(&basic));
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(challenge.is_some());
+    }
+
+    #[test]
+    fn rejects_unknown_session() {
+        let (status, challenge) = status_and_challenge(sessions(), Some("Bearer abc.123.def"));
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            challenge,
+            Some("Bearer realm=\"toggl-utopia\", error=\"invalid_token\"".to_string())
+        );
+    }
+
+    #[test]
+    fn accepts_valid_session() {
+        let sessions = sessions();
+        let token = sessions.issue(1, "api_token".to_string(), None);
+
+        let (status, challenge) =
+            status_and_challenge(sessions, Some(&format!("bearer {}", token.token)));
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(challenge, None);
+    }
+
+    #[test]
+    fn accepts_api_token_when_logging_in() {
+        let mut app = test::init_service(
+            App::new().service(
+                web::resource("/")
+                    .wrap(Authentication::basic())
+                    .route(web::get().to(login_scheme)),
+            ),
+        );
+
+        let req = test::TestRequest::get()
+            .header(header::AUTHORIZATION, "Bearer api_token")
+            .to_request();
+        let body = test::read_response(&mut app, req);
+
+        assert_eq!(body, web::Bytes::from_static(b"Bearer"));
+    }
+}
@@ -0,0 +1,459 @@
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::auth::Credentials;
+use crate::calendar;
+use crate::error::{Error, ValidationError};
+use crate::models::TimeEntry;
+use crate::rounding::{RoundingRules, Roundings};
+use crate::sync::prelude::{StopReason, SyncOutcome, SyncResult};
+use crate::sync::server;
+use crate::toggl_api::{
+    models::{ApiToken, Id},
+    TogglApi,
+};
+
+/// How often the background job checks the running time entries of the users.
+pub const CHECK_INTERVAL_MINUTES: u64 = 5;
+/// The time entries stopped by the background job are reported as auto-stopped to the
+/// devices which sync within this many days.
+const REPORT_DAYS: i64 = 7;
+/// Toggl doesn't accept longer time entries.
+const MAX_DURATION_MINUTES: u64 = 999 * 60;
+
+/// The conditions under which a forgotten running time entry is stopped. Any of the rules
+/// can stop the time entry, the one which would stop it first wins.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Rules {
+    /// Stop the time entry once it has been running for this long.
+    pub max_duration_minutes: Option<u64>,
+    /// Stop the time entry at this local time of the day.
+    pub stop_at: Option<NaiveTime>,
+    /// The IANA name of the timezone of the local time, e.g. "Europe/Prague". The timezone
+    /// of the user in Toggl is used when the rules are set without it.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Stop the time entry when another time entry which started after it appears.
+    #[serde(default)]
+    pub stop_when_newer_starts: bool,
+}
+
+struct Stopped {
+    reason: StopReason,
+    at: DateTime<Utc>,
+}
+
+struct Subscription {
+    rules: Rules,
+    api_token: ApiToken,
+    stopped: HashMap<Id, Stopped>,
+}
+
+/// The auto-stop rules of the users together with the time entries which were stopped
+/// by the background job.
+#[derive(Default)]
+pub struct AutoStop {
+    users: Mutex<HashMap<Id, Subscription>>,
+}
+
+impl Rules {
+    fn is_empty(&self) -> bool {
+        self.max_duration_minutes.is_none()
+            && self.stop_at.is_none()
+            && !self.stop_when_newer_starts
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let mut errors = vec![];
+        if self
+            .max_duration_minutes
+            .is_some_and(|minutes| minutes == 0 || minutes > MAX_DURATION_MINUTES)
+        {
+            errors.push(ValidationError {
+                field: Some("max_duration_minutes".to_string()),
+                reason: format!(
+                    "The maximum duration must be between 1 and {} minutes.",
+                    MAX_DURATION_MINUTES
+                ),
+            });
+        }
+        if let Err(Error::Validation(timezone_errors)) = self.timezone() {
+            errors.extend(timezone_errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(errors))
+        }
+    }
+
+    fn timezone(&self) -> Result<Tz, Error> {
+        match &self.timezone {
+            Some(name) => calendar::parse_timezone(name),
+            None => Ok(Tz::UTC),
+        }
+    }
+}
+
+impl AutoStop {
+    pub fn rules(&self, user_id: Id) -> Rules {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .map(|subscription| subscription.rules.clone())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the rules of the user. The API token is kept so the background job can
+    /// check the running time entry even when none of the devices of the user is syncing.
+    pub fn set_rules(
+        &self,
+        user_id: Id,
+        api_token: ApiToken,
+        rules: Rules,
+    ) -> Result<Rules, Error> {
+        rules.validate()?;
+
+        let mut users = self.users.lock().unwrap();
+        if rules.is_empty() {
+            users.remove(&user_id);
+        } else {
+            let subscription = users.entry(user_id).or_insert_with(|| Subscription {
+                rules: Rules::default(),
+                api_token: api_token.clone(),
+                stopped: HashMap::new(),
+            });
+            subscription.rules = rules.clone();
+            subscription.api_token = api_token;
+        }
+
+        Ok(rules)
+    }
+
+    /// Evaluates the rules of the user after a sync and reports the time entries which were
+    /// stopped automatically, either right now or earlier by the background job.
+    pub fn apply(
+        &self,
+        user_id: Id,
+        outcome: SyncOutcome,
+        rounding: &RoundingRules,
+        api: &TogglApi,
+    ) -> SyncOutcome {
+        let rules = match self.users.lock().unwrap().get(&user_id) {
+            Some(subscription) => subscription.rules.clone(),
+            None => return outcome,
+        };
+
+        let mut time_entries: Vec<_> = outcome
+            .time_entries
+            .into_iter()
+            .map(|result| self.reported(user_id, result))
+            .collect();
+
+        // Auto-stopping is best effort, the sync itself has already succeeded.
+        if let Ok(Some((stopped, reason))) = stop_if_needed(&rules, rounding, api, Utc::now()) {
+            self.remember(user_id, &stopped, reason);
+            time_entries.retain(|result| match result {
+                SyncResult::Changed { entity } => entity.id != stopped.id,
+                _ => true,
+            });
+            time_entries.push(SyncResult::AutoStopped {
+                entity: stopped,
+                reason,
+            });
+        }
+
+        SyncOutcome {
+            time_entries,
+            ..outcome
+        }
+    }
+
+    /// Evaluates the rules of all the users. This is what the background job does.
+    pub fn check_all(&self, roundings: &Roundings) {
+        let subscriptions: Vec<(Id, Rules, ApiToken)> = self
+            .users
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(user_id, subscription)| {
+                (
+                    *user_id,
+                    subscription.rules.clone(),
+                    subscription.api_token.clone(),
+                )
+            })
+            .collect();
+
+        for (user_id, rules, api_token) in subscriptions {
+            let result = TogglApi::new(Credentials::Token(api_token))
+                .ok_or_else(|| Error::Internal("Cannot create a Toggl API client.".to_string()))
+                .and_then(|api| {
+                    stop_if_needed(&rules, &roundings.rules(user_id), &api, Utc::now())
+                });
+
+            match result {
+                Ok(Some((stopped, reason))) => self.remember(user_id, &stopped, reason),
+                Ok(None) => {}
+                Err(err) => log::warn!("Auto-stop for user {} failed: {}", user_id, err),
+            }
+        }
+    }
+
+    fn remember(&self, user_id: Id, stopped: &TimeEntry, reason: StopReason) {
+        if let Some(subscription) = self.users.lock().unwrap().get_mut(&user_id) {
+            let expired = Utc::now() - Duration::days(REPORT_DAYS);
+            subscription
+                .stopped
+                .retain(|_, stopped| stopped.at > expired);
+            subscription.stopped.insert(
+                stopped.id,
+                Stopped {
+                    reason,
+                    at: stopped.at,
+                },
+            );
+        }
+    }
+
+    /// The change of a time entry which was stopped by the background job is reported as
+    /// auto-stopped unless the time entry has been edited since.
+    fn reported(&self, user_id: Id, result: SyncResult<TimeEntry>) -> SyncResult<TimeEntry> {
+        let users = self.users.lock().unwrap();
+        let stopped = users
+            .get(&user_id)
+            .map(|subscription| &subscription.stopped);
+
+        match result {
+            SyncResult::Changed { entity } => {
+                match stopped.and_then(|stopped| stopped.get(&entity.id)) {
+                    Some(stopped) if stopped.at == entity.at => SyncResult::AutoStopped {
+                        entity,
+                        reason: stopped.reason,
+                    },
+                    _ => SyncResult::Changed { entity },
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+fn stop_if_needed(
+    rules: &Rules,
+    rounding: &RoundingRules,
+    api: &TogglApi,
+    now: DateTime<Utc>,
+) -> Result<Option<(TimeEntry, StopReason)>, Error> {
+    let running = match server::currently_running_time_entry(api)? {
+        Some(running) => running,
+        None => return Ok(None),
+    };
+
+    let newer = if rules.stop_when_newer_starts {
+        server::fetch_time_entries_between(running.start, now, api)?
+    } else {
+        vec![]
+    };
+
+    match evaluate(rules, &running, &newer, now)? {
+        Some((end, reason)) => {
+            let stopped = server::update_time_entry(
+                running.stop_at(end, rounding.on_stop(running.workspace_id)),
+                api,
+            )?;
+            Ok(Some((stopped, reason)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Finds the moment when the running time entry should have been stopped, if any of the
+/// rules applies to it already.
+pub fn evaluate(
+    rules: &Rules,
+    running: &TimeEntry,
+    time_entries: &[TimeEntry],
+    now: DateTime<Utc>,
+) -> Result<Option<(DateTime<Utc>, StopReason)>, Error> {
+    if !running.is_running() {
+        return Ok(None);
+    }
+
+    let timezone = rules.timezone()?;
+
+    let max_duration = rules
+        .max_duration_minutes
+        .filter(|minutes| *minutes <= MAX_DURATION_MINUTES)
+        .map(|minutes| {
+            (
+                running.start + Duration::minutes(minutes as i64),
+                StopReason::MaxDuration,
+            )
+        });
+    let time_of_day = rules
+        .stop_at
+        .and_then(|time| next_occurrence(time, running.start, &timezone))
+        .map(|end| (end, StopReason::TimeOfDay));
+    let newer_entry = time_entries
+        .iter()
+        .filter(|_| rules.stop_when_newer_starts)
+        .filter(|te| te.id != running.id && te.server_deleted_at.is_none())
+        .filter(|te| te.start > running.start)
+        .map(|te| te.start)
+        .min()
+        .map(|end| (end, StopReason::NewerEntry));
+
+    Ok(vec![max_duration, time_of_day, newer_entry]
+        .into_iter()
+        .flatten()
+        .filter(|(end, _)| *end <= now)
+        .min_by_key(|(end, _)| *end))
+}
+
+/// The first moment after the given one when the local clock shows the given time.
+fn next_occurrence<Z: TimeZone>(
+    time: NaiveTime,
+    after: DateTime<Utc>,
+    timezone: &Z,
+) -> Option<DateTime<Utc>> {
+    let date = after.with_timezone(timezone).naive_local().date();
+
+    [date, date + Duration::days(1)]
+        .iter()
+        .filter_map(|date| {
+            timezone
+                .from_local_datetime(&date.and_time(time))
+                .earliest()
+        })
+        .map(|next| next.with_timezone(&Utc))
+        .find(|next| *next > after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, Rules};
+    use crate::error::Error;
+    use crate::models::{fixtures, TimeEntry};
+    use crate::sync::prelude::StopReason;
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2019, 12, day).and_hms(hour, minute, 0)
+    }
+
+    fn time_entry(id: Id, start: DateTime<Utc>, duration: Option<u64>) -> TimeEntry {
+        fixtures::time_entry(id, start, duration)
+    }
+
+    #[test]
+    fn stops_after_maximum_duration() {
+        let rules = Rules {
+            max_duration_minutes: Some(8 * 60),
+            ..Rules::default()
+        };
+        let running = time_entry(1, at(10, 9, 0), None);
+
+        assert_eq!(
+            evaluate(&rules, &running, &[], at(10, 16, 0)).unwrap(),
+            None
+        );
+        assert_eq!(
+            evaluate(&rules, &running, &[], at(11, 7, 0)).unwrap(),
+            Some((at(10, 17, 0), StopReason::MaxDuration))
+        );
+    }
+
+    #[test]
+    fn stops_at_local_time_of_day() {
+        let rules = Rules {
+            stop_at: Some(NaiveTime::from_hms(19, 0, 0)),
+            timezone: Some("Europe/Prague".to_string()),
+            ..Rules::default()
+        };
+
+        let afternoon = time_entry(1, at(10, 14, 0), None);
+        assert_eq!(
+            evaluate(&rules, &afternoon, &[], at(11, 7, 0)).unwrap(),
+            Some((at(10, 18, 0), StopReason::TimeOfDay))
+        );
+
+        let late_evening = time_entry(1, at(10, 20, 0), None);
+        assert_eq!(
+            evaluate(&rules, &late_evening, &[], at(11, 7, 0)).unwrap(),
+            None
+        );
+
+        // summer time in Prague is two hours ahead of UTC
+        let summer = Utc.ymd(2019, 7, 10);
+        assert_eq!(
+            evaluate(
+                &rules,
+                &time_entry(1, summer.and_hms(14, 0, 0), None),
+                &[],
+                summer.and_hms(20, 0, 0)
+            )
+            .unwrap(),
+            Some((summer.and_hms(17, 0, 0), StopReason::TimeOfDay))
+        );
+    }
+
+    #[test]
+    fn stops_when_newer_entry_starts_and_picks_the_earliest_rule() {
+        let rules = Rules {
+            max_duration_minutes: Some(10 * 60),
+            stop_when_newer_starts: true,
+            ..Rules::default()
+        };
+        let running = time_entry(1, at(10, 9, 0), None);
+        let time_entries = vec![
+            running.clone(),
+            time_entry(2, at(10, 8, 0), Some(1800)),
+            time_entry(3, at(10, 11, 30), Some(1800)),
+        ];
+
+        assert_eq!(
+            evaluate(&rules, &running, &time_entries, at(10, 12, 0)).unwrap(),
+            Some((at(10, 11, 30), StopReason::NewerEntry))
+        );
+
+        let stopped = running.stop_at(at(10, 11, 30), None);
+        assert_eq!(
+            stopped.duration,
+            Some(Duration::minutes(150).num_seconds() as u64)
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_rules() {
+        let rules = Rules {
+            max_duration_minutes: Some(u64::MAX),
+            timezone: Some("Mars/Olympus_Mons".to_string()),
+            ..Rules::default()
+        };
+
+        match rules.validate() {
+            Err(Error::Validation(errors)) => assert_eq!(errors.len(), 2),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        let running = time_entry(1, at(10, 9, 0), None);
+        assert!(evaluate(&rules, &running, &[], at(11, 7, 0)).is_err());
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rules: Rules =
+            serde_json::from_str(r#"{ "stop_at": "18:30:00", "timezone": "America/New_York" }"#)
+                .unwrap();
+
+        assert_eq!(rules.stop_at, Some(NaiveTime::from_hms(18, 30, 0)));
+        assert_eq!(rules.max_duration_minutes, None);
+        assert!(!rules.stop_when_newer_starts);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+use crate::error::Error;
+use crate::models::{Delta, Earnings, Project, TimeEntry, Workspace};
+use crate::sync::prelude::{SyncOutcome, SyncResult};
+use crate::sync::server;
+use crate::toggl_api::models::Id;
+use crate::toggl_api::TogglApi;
+
+/// The hourly rates which apply to the time entries of the user.
+pub struct Rates<'a> {
+    projects: &'a [Project],
+    workspaces: &'a [Workspace],
+}
+
+impl<'a> Rates<'a> {
+    pub fn new(projects: &'a [Project], workspaces: &'a [Workspace]) -> Rates<'a> {
+        Rates {
+            projects,
+            workspaces,
+        }
+    }
+
+    /// The rate of the project takes precedence over the default rate of the workspace.
+    /// Time entries which aren't billable don't earn anything.
+    pub fn earnings(&self, te: &TimeEntry, now: DateTime<Utc>) -> Option<Earnings> {
+        if !te.billable {
+            return None;
+        }
+
+        let workspace = self
+            .workspaces
+            .iter()
+            .find(|workspace| workspace.id == te.workspace_id);
+        let project = te
+            .project_id
+            .and_then(|id| self.projects.iter().find(|project| project.id == id));
+
+        let (hourly_rate, currency) = match project.and_then(|project| project.rate) {
+            Some(rate) => (
+                rate,
+                project
+                    .and_then(|project| project.currency.clone())
+                    .or_else(|| workspace.and_then(|workspace| workspace.default_currency.clone())),
+            ),
+            None => {
+                let workspace = workspace?;
+                (
+                    workspace.default_hourly_rate?,
+                    workspace.default_currency.clone(),
+                )
+            }
+        };
+
+        let seconds = te.duration.unwrap_or_else(|| te.elapsed_seconds_until(now));
+        let amount = hourly_rate * seconds as f64 / 3600.0;
+
+        Some(Earnings {
+            amount: (amount * 100.0).round() / 100.0,
+            hourly_rate,
+            currency,
+        })
+    }
+
+    fn fill(&self, te: &mut TimeEntry, now: DateTime<Utc>) {
+        te.earnings = self.earnings(te, now);
+    }
+}
+
+/// The workspaces of the users, their default rates are needed for the earnings of every
+/// sync but they rarely change.
+#[derive(Default)]
+pub struct Billing {
+    workspaces: Mutex<HashMap<Id, Vec<Workspace>>>,
+}
+
+impl Billing {
+    /// Calculates the earnings of the time entries in the snapshot. The workspaces are fetched
+    /// again so that the changes to the default rates show up after logging in.
+    pub fn delta_with_earnings(&self, user_id: Id, mut delta: Delta, api: &TogglApi) -> Delta {
+        if !any_billable(delta.time_entries.iter().flatten()) {
+            return delta;
+        }
+
+        let workspaces = match server::fetch_workspaces(api) {
+            Ok(workspaces) => workspaces,
+            Err(_) => return delta,
+        };
+        self.workspaces
+            .lock()
+            .unwrap()
+            .insert(user_id, workspaces.clone());
+
+        let projects = delta.projects.clone().unwrap_or_default();
+        let rates = Rates::new(&projects, &workspaces);
+        let now = Utc::now();
+        for te in delta.time_entries.iter_mut().flatten() {
+            rates.fill(te, now);
+        }
+
+        delta
+    }
+
+    /// Calculates the earnings of the time entries which are sent to the client. The projects
+    /// come from the sync itself, all of them are fetched only when some are missing. This is
+    /// best effort, the outcome is returned without the earnings when the rates cannot be
+    /// fetched.
+    pub fn with_earnings(
+        &self,
+        user_id: Id,
+        client_delta: &Delta,
+        mut outcome: SyncOutcome,
+        api: &TogglApi,
+    ) -> SyncOutcome {
+        if !any_billable(outcome.time_entries.iter().filter_map(SyncResult::entity)) {
+            return outcome;
+        }
+
+        let entities = outcome.entities();
+        let mut projects = outcome
+            .accepted_from(client_delta)
+            .projects
+            .unwrap_or_default();
+        projects.extend(entities.projects.clone().unwrap_or_default());
+        if missing_projects(&entities, &projects) {
+            projects = match server::fetch_all_projects(api) {
+                Ok(projects) => projects,
+                Err(_) => return outcome,
+            };
+        }
+
+        let workspaces = match self.workspaces(user_id, api) {
+            Ok(workspaces) => workspaces,
+            Err(_) => return outcome,
+        };
+
+        let rates = Rates::new(&projects, &workspaces);
+        let now = Utc::now();
+        for result in outcome.time_entries.iter_mut() {
+            if let Some(te) = result.entity_mut() {
+                rates.fill(te, now);
+            }
+        }
+
+        outcome
+    }
+
+    fn workspaces(&self, user_id: Id, api: &TogglApi) -> Result<Vec<Workspace>, Error> {
+        if let Some(workspaces) = self.workspaces.lock().unwrap().get(&user_id) {
+            return Ok(workspaces.clone());
+        }
+
+        let workspaces = server::fetch_workspaces(api)?;
+        self.workspaces
+            .lock()
+            .unwrap()
+            .insert(user_id, workspaces.clone());
+        Ok(workspaces)
+    }
+}
+
+fn any_billable<'a>(mut time_entries: impl Iterator<Item = &'a TimeEntry>) -> bool {
+    time_entries.any(|te| te.billable)
+}
+
+/// Whether a billable time entry belongs to a project whose rate isn't known.
+fn missing_projects(delta: &Delta, projects: &[Project]) -> bool {
+    delta
+        .time_entries
+        .iter()
+        .flatten()
+        .filter(|te| te.billable)
+        .filter_map(|te| te.project_id)
+        .any(|id| !projects.iter().any(|project| project.id == id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{missing_projects, Rates};
+    use crate::models::{fixtures, Delta, Project, TimeEntry, Workspace};
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2019, 12, 10).and_hms(hour, minute, 0)
+    }
+
+    fn workspace() -> Workspace {
+        Workspace {
+            id: 1,
+            name: "Agency".to_string(),
+            default_hourly_rate: Some(50.0),
+            default_currency: Some("EUR".to_string()),
+            at: at(0, 0),
+        }
+    }
+
+    fn project(id: Id, rate: Option<f64>) -> Project {
+        Project {
+            billable: true,
+            rate,
+            at: at(0, 0),
+            ..fixtures::project(id, "Client")
+        }
+    }
+
+    fn time_entry(project_id: Option<Id>, minutes: Option<i64>, billable: bool) -> TimeEntry {
+        TimeEntry {
+            project_id,
+            billable,
+            ..fixtures::time_entry(
+                1,
+                at(9, 0),
+                minutes.map(|minutes| Duration::minutes(minutes).num_seconds() as u64),
+            )
+        }
+    }
+
+    #[test]
+    fn prefers_project_rate_over_workspace_rate() {
+        let projects = vec![project(2, Some(80.0)), project(3, None)];
+        let workspaces = vec![workspace()];
+        let rates = Rates::new(&projects, &workspaces);
+
+        let with_project_rate = rates
+            .earnings(&time_entry(Some(2), Some(90), true), at(12, 0))
+            .unwrap();
+        assert_eq!(with_project_rate.amount, 120.0);
+        assert_eq!(with_project_rate.hourly_rate, 80.0);
+        assert_eq!(with_project_rate.currency, Some("EUR".to_string()));
+
+        let with_workspace_rate = rates
+            .earnings(&time_entry(Some(3), Some(20), true), at(12, 0))
+            .unwrap();
+        assert_eq!(with_workspace_rate.amount, 16.67);
+        assert_eq!(with_workspace_rate.hourly_rate, 50.0);
+    }
+
+    #[test]
+    fn calculates_running_entries_until_now_and_skips_non_billable() {
+        let projects = vec![];
+        let workspaces = vec![workspace()];
+        let rates = Rates::new(&projects, &workspaces);
+
+        let running = rates
+            .earnings(&time_entry(None, None, true), at(11, 0))
+            .unwrap();
+        assert_eq!(running.amount, 100.0);
+
+        assert_eq!(
+            rates.earnings(&time_entry(None, Some(60), false), at(11, 0)),
+            None
+        );
+        assert_eq!(
+            Rates::new(&projects, &[]).earnings(&time_entry(None, Some(60), true), at(11, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn fetches_projects_only_for_billable_time_entries_of_unknown_projects() {
+        let projects = vec![project(2, Some(80.0))];
+        let delta = |time_entries| Delta {
+            time_entries: Some(time_entries),
+            ..Delta::default()
+        };
+
+        assert!(!missing_projects(
+            &delta(vec![
+                time_entry(Some(2), Some(60), true),
+                time_entry(Some(3), Some(60), false),
+                time_entry(None, Some(60), true),
+            ]),
+            &projects
+        ));
+        assert!(missing_projects(
+            &delta(vec![time_entry(Some(3), Some(60), true)]),
+            &projects
+        ));
+    }
+}
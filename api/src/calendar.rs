@@ -0,0 +1,244 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, ValidationError};
+use crate::models::User;
+use crate::sync::server;
+use crate::toggl_api::TogglApi;
+
+/// Listing more buckets at once doesn't make sense for any client.
+const MAX_BUCKETS: i64 = 400;
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Day,
+    Week,
+}
+
+/// A day or a week in the calendar of the user. The bucket doesn't have to be 24 hours
+/// (or 7 days) long when the clocks change during it.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct Bucket {
+    /// The local date on which the bucket starts.
+    pub date: NaiveDate,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct Buckets {
+    pub timezone: String,
+    pub beginning_of_week: u32,
+    pub period: Period,
+    pub buckets: Vec<Bucket>,
+}
+
+/// The calendar of the user: the timezone in which the days start and the first day
+/// of the week (0 = Sunday, 1 = Monday, ...).
+pub struct Calendar<Z: TimeZone> {
+    pub timezone: Z,
+    pub beginning_of_week: u32,
+}
+
+impl Calendar<Tz> {
+    /// The calendar from the settings of the user in Toggl. Users without a timezone
+    /// get UTC days.
+    pub fn of_user(user: &User) -> Result<Calendar<Tz>, Error> {
+        let timezone = match &user.timezone {
+            Some(name) if !name.is_empty() => parse_timezone(name)?,
+            _ => Tz::UTC,
+        };
+
+        Ok(Calendar {
+            timezone,
+            beginning_of_week: user.beginning_of_week % 7,
+        })
+    }
+}
+
+/// Lists the days or weeks in the range in the calendar of the user. The timezone and
+/// the first day of the week from the Toggl settings of the user can be overridden.
+pub fn fetch_buckets(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    period: Period,
+    timezone: Option<&str>,
+    beginning_of_week: Option<u32>,
+    api: &TogglApi,
+) -> Result<Buckets, Error> {
+    let days = match period {
+        Period::Day => 1,
+        Period::Week => 7,
+    };
+    if from >= to {
+        return Err(invalid(
+            "to",
+            "The end of the range must be after its beginning.",
+        ));
+    }
+    if to - from > Duration::days(days * MAX_BUCKETS) {
+        return Err(invalid(
+            "to",
+            &format!("The range can contain at most {} buckets.", MAX_BUCKETS),
+        ));
+    }
+
+    let calendar = Calendar::of_user(&server::fetch_user(api)?)?;
+    let calendar = Calendar {
+        timezone: match timezone {
+            Some(name) => parse_timezone(name)?,
+            None => calendar.timezone,
+        },
+        beginning_of_week: beginning_of_week.unwrap_or(calendar.beginning_of_week) % 7,
+    };
+
+    Ok(Buckets {
+        timezone: calendar.timezone.name().to_string(),
+        beginning_of_week: calendar.beginning_of_week,
+        period,
+        buckets: calendar.buckets(from, to, period),
+    })
+}
+
+impl<Z: TimeZone> Calendar<Z> {
+    /// The local date on which the day or the week which contains the instant starts.
+    pub fn bucket_of(&self, instant: DateTime<Utc>, period: Period) -> NaiveDate {
+        let date = instant.with_timezone(&self.timezone).naive_local().date();
+        match period {
+            Period::Week => {
+                let days_since_week_start =
+                    (date.weekday().num_days_from_sunday() + 7 - self.beginning_of_week % 7) % 7;
+                date - Duration::days(days_since_week_start as i64)
+            }
+            Period::Day => date,
+        }
+    }
+
+    /// The instant when the local date begins. When the clocks skip the midnight, the day
+    /// begins at the first moment which exists.
+    pub fn start_of(&self, date: NaiveDate) -> DateTime<Utc> {
+        let midnight = date.and_hms(0, 0, 0);
+        (0..=24 * 60)
+            .step_by(15)
+            .filter_map(|minutes| {
+                self.timezone
+                    .from_local_datetime(&(midnight + Duration::minutes(minutes)))
+                    .earliest()
+            })
+            .map(|start| start.with_timezone(&Utc))
+            .next()
+            .unwrap_or_else(|| Utc.from_utc_datetime(&midnight))
+    }
+
+    pub fn next_bucket_start(&self, bucket: NaiveDate, period: Period) -> DateTime<Utc> {
+        let days = match period {
+            Period::Week => 7,
+            Period::Day => 1,
+        };
+
+        self.start_of(bucket + Duration::days(days))
+    }
+
+    /// All the days or weeks which overlap with the range.
+    pub fn buckets(&self, from: DateTime<Utc>, to: DateTime<Utc>, period: Period) -> Vec<Bucket> {
+        let mut buckets = vec![];
+        let mut cursor = from;
+
+        while cursor < to {
+            let date = self.bucket_of(cursor, period);
+            let end = self.next_bucket_start(date, period);
+            buckets.push(Bucket {
+                date,
+                start: self.start_of(date),
+                end,
+            });
+            cursor = end;
+        }
+
+        buckets
+    }
+}
+
+pub fn parse_timezone(name: &str) -> Result<Tz, Error> {
+    name.parse()
+        .map_err(|_| invalid("timezone", &format!("Unknown timezone {}.", name)))
+}
+
+fn invalid(field: &str, reason: &str) -> Error {
+    Error::Validation(vec![ValidationError {
+        field: Some(field.to_string()),
+        reason: reason.to_string(),
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_timezone, Calendar, Period};
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use chrono_tz::Europe::Prague;
+
+    fn prague() -> Calendar<chrono_tz::Tz> {
+        Calendar {
+            timezone: Prague,
+            beginning_of_week: 1,
+        }
+    }
+
+    #[test]
+    fn days_start_at_local_midnight() {
+        // 23:30 UTC is already the next day in Prague
+        let instant = Utc.ymd(2019, 12, 10).and_hms(23, 30, 0);
+
+        assert_eq!(
+            prague().bucket_of(instant, Period::Day),
+            NaiveDate::from_ymd(2019, 12, 11)
+        );
+        assert_eq!(
+            prague().start_of(NaiveDate::from_ymd(2019, 12, 11)),
+            Utc.ymd(2019, 12, 10).and_hms(23, 0, 0)
+        );
+        assert_eq!(
+            prague().bucket_of(instant, Period::Week),
+            NaiveDate::from_ymd(2019, 12, 9)
+        );
+    }
+
+    #[test]
+    fn days_are_shorter_or_longer_across_dst_transitions() {
+        let buckets = prague().buckets(
+            Utc.ymd(2019, 3, 30).and_hms(12, 0, 0),
+            Utc.ymd(2019, 3, 31).and_hms(12, 0, 0),
+            Period::Day,
+        );
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[1].date, NaiveDate::from_ymd(2019, 3, 31));
+        assert_eq!((buckets[1].end - buckets[1].start).num_hours(), 23);
+
+        let autumn = prague().buckets(
+            Utc.ymd(2019, 10, 27).and_hms(12, 0, 0),
+            Utc.ymd(2019, 10, 27).and_hms(13, 0, 0),
+            Period::Day,
+        );
+        assert_eq!((autumn[0].end - autumn[0].start).num_hours(), 25);
+    }
+
+    #[test]
+    fn week_buckets_respect_beginning_of_week() {
+        let sunday_weeks = Calendar {
+            timezone: Prague,
+            beginning_of_week: 0,
+        };
+        let buckets = sunday_weeks.buckets(
+            Utc.ymd(2019, 12, 10).and_hms(12, 0, 0),
+            Utc.ymd(2019, 12, 16).and_hms(12, 0, 0),
+            Period::Week,
+        );
+
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].date, NaiveDate::from_ymd(2019, 12, 8));
+        assert_eq!(buckets[1].date, NaiveDate::from_ymd(2019, 12, 15));
+        assert!(parse_timezone("Mars/Olympus_Mons").is_err());
+    }
+}
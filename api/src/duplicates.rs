@@ -0,0 +1,226 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{Error, ValidationError};
+use crate::models::{Delta, TimeEntry};
+use crate::sync::prelude::{deleted, failed, SyncOutcome, SyncResult};
+use crate::sync::server;
+use crate::toggl_api::{models::Id, TogglApi};
+
+const MAX_DELETED_AT_ONCE: usize = 100;
+
+/// Time entries which look the same. The one which was created first is kept, the others
+/// are likely left over from retried syncs or imports.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct DuplicateGroup {
+    pub original: TimeEntry,
+    pub duplicates: Vec<TimeEntry>,
+}
+
+type Key = (Id, DateTime<Utc>, Option<u64>, String, Option<Id>);
+
+/// Time entries are duplicates when they are in the same workspace and project, start at
+/// the same moment, last equally long and have the same description.
+fn key(te: &TimeEntry) -> Key {
+    (
+        te.workspace_id,
+        te.start,
+        te.duration,
+        te.description.trim().to_lowercase(),
+        te.project_id,
+    )
+}
+
+/// Finds the duplicates among the time entries which started in the range.
+pub fn fetch_duplicates(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    api: &TogglApi,
+) -> Result<Vec<DuplicateGroup>, Error> {
+    if from >= to {
+        return Err(Error::Validation(vec![ValidationError {
+            field: Some("to".to_string()),
+            reason: "The end of the range must be after its beginning.".to_string(),
+        }]));
+    }
+
+    let time_entries = server::fetch_time_entries_between(from, to, api)?;
+    Ok(find_duplicates(&time_entries))
+}
+
+pub fn find_duplicates(time_entries: &[TimeEntry]) -> Vec<DuplicateGroup> {
+    let mut groups: HashMap<Key, Vec<TimeEntry>> = HashMap::new();
+    for te in time_entries
+        .iter()
+        .filter(|te| te.server_deleted_at.is_none() && !te.is_running())
+    {
+        groups.entry(key(te)).or_default().push(te.clone());
+    }
+
+    let mut duplicates: Vec<_> = groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort_by_key(|te| te.id);
+            let original = group.remove(0);
+            DuplicateGroup {
+                original,
+                duplicates: group,
+            }
+        })
+        .collect();
+    duplicates.sort_by_key(|group| group.original.start);
+
+    duplicates
+}
+
+/// Deletes the chosen duplicates from Toggl. A time entry is deleted only if at least one
+/// of its copies stays, so no tracked time is lost.
+pub fn delete_duplicates(ids: &[Id], api: &TogglApi) -> Result<SyncOutcome, Error> {
+    if ids.is_empty() || ids.len() > MAX_DELETED_AT_ONCE {
+        return Err(Error::Validation(vec![ValidationError {
+            field: Some("ids".to_string()),
+            reason: format!(
+                "Between 1 and {} time entries can be deleted at once.",
+                MAX_DELETED_AT_ONCE
+            ),
+        }]));
+    }
+
+    let mut chosen = vec![];
+    let mut results = vec![];
+    for id in ids {
+        match server::fetch_time_entry(*id, api) {
+            Ok(te) => chosen.push(te),
+            Err(err) => results.push(failed(*id, err)),
+        }
+    }
+
+    // Toggl filters the time entries by their start, the copies start at the same moment
+    let candidates = match (
+        chosen.iter().map(|te| te.start).min(),
+        chosen.iter().map(|te| te.start).max(),
+    ) {
+        (Some(first), Some(last)) => {
+            server::fetch_time_entries_between(first, last + Duration::seconds(1), api)?
+        }
+        _ => vec![],
+    };
+
+    let (to_delete, rejected) = plan_deletion(chosen, &candidates);
+    results.extend(rejected);
+    for te in to_delete {
+        results.push(match server::delete_time_entry(&te, api) {
+            Ok(()) => deleted(TimeEntry {
+                server_deleted_at: Some(Utc::now()),
+                ..te
+            }),
+            Err(err) => failed(te.id, err),
+        });
+    }
+
+    let mut outcome = SyncOutcome::convert(Delta::default());
+    outcome.time_entries = results;
+
+    Ok(outcome)
+}
+
+/// Splits the chosen time entries into those which can be deleted because a copy of them
+/// stays, and those which cannot.
+fn plan_deletion(
+    chosen: Vec<TimeEntry>,
+    candidates: &[TimeEntry],
+) -> (Vec<TimeEntry>, Vec<SyncResult<TimeEntry>>) {
+    let chosen_ids: HashSet<Id> = chosen.iter().map(|te| te.id).collect();
+    let kept: HashSet<Key> = candidates
+        .iter()
+        .filter(|te| te.server_deleted_at.is_none() && !chosen_ids.contains(&te.id))
+        .map(key)
+        .collect();
+
+    let mut to_delete = vec![];
+    let mut rejected = vec![];
+    for te in chosen {
+        if te.server_deleted_at.is_none() && !te.is_running() && kept.contains(&key(&te)) {
+            to_delete.push(te);
+        } else {
+            rejected.push(failed(
+                te.id,
+                Error::Validation(vec![ValidationError {
+                    field: Some("ids".to_string()),
+                    reason: "The time entry isn't a duplicate of any time entry which is kept."
+                        .to_string(),
+                }]),
+            ));
+        }
+    }
+
+    (to_delete, rejected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_duplicates, plan_deletion};
+    use crate::models::{fixtures, TimeEntry};
+    use crate::sync::prelude::SyncResult;
+    use crate::toggl_api::models::Id;
+    use chrono::{TimeZone, Utc};
+
+    fn time_entry(id: Id, description: &str, hour: u32) -> TimeEntry {
+        let start = Utc.ymd(2019, 12, 10).and_hms(hour, 0, 0);
+        TimeEntry {
+            description: description.to_string(),
+            project_id: Some(5),
+            ..fixtures::time_entry(id, start, Some(3600))
+        }
+    }
+
+    #[test]
+    fn groups_identical_time_entries() {
+        let time_entries = vec![
+            time_entry(3, "Meeting ", 9),
+            time_entry(1, "meeting", 9),
+            time_entry(2, "Meeting", 9),
+            time_entry(4, "Meeting", 10),
+            TimeEntry {
+                duration: Some(1800),
+                ..time_entry(5, "Meeting", 10)
+            },
+        ];
+
+        let groups = find_duplicates(&time_entries);
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].original.id, 1);
+        let duplicate_ids: Vec<_> = groups[0].duplicates.iter().map(|te| te.id).collect();
+        assert_eq!(duplicate_ids, vec![2, 3]);
+    }
+
+    #[test]
+    fn never_deletes_the_last_copy() {
+        let candidates = vec![
+            time_entry(1, "Meeting", 9),
+            time_entry(2, "Meeting", 9),
+            time_entry(3, "Review", 9),
+        ];
+
+        let (to_delete, rejected) = plan_deletion(
+            vec![time_entry(2, "Meeting", 9), time_entry(3, "Review", 9)],
+            &candidates,
+        );
+        assert_eq!(to_delete.len(), 1);
+        assert_eq!(to_delete[0].id, 2);
+        match &rejected[..] {
+            [SyncResult::Failed { entity_id, .. }] => assert_eq!(*entity_id, 3),
+            other => panic!("Unexpected results {:?}", other),
+        }
+
+        let (to_delete, rejected) = plan_deletion(
+            vec![time_entry(1, "Meeting", 9), time_entry(2, "Meeting", 9)],
+            &candidates,
+        );
+        assert!(to_delete.is_empty());
+        assert_eq!(rejected.len(), 2);
+    }
+}
@@ -1,55 +1,1058 @@
-use actix_web::{web, HttpRequest, HttpResponse};
-use chrono::{DateTime, Utc};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, FixedOffset, NaiveTime, Utc};
+use chrono_tz::Tz;
+use futures::stream;
 use serde::Deserialize;
 
-use crate::responses::{invalid_credentials, snapshot_success, something_went_wrong, sync_success};
+use crate::export::{self, Column, Format};
+use crate::import;
+use crate::reports::{self, Grouping};
+use crate::responses::{
+    analysis_success, authentication_failed, auto_stop_success, bulk_edit_success,
+    calendar_success, duplicates_success, goals_success, history_success, import_success,
+    progress_success, recurrence_deleted, recurrence_success, recurrences_success, report_success,
+    rounding_success, search_success, session_revoked, session_success, sessions_success,
+    snapshot_success, something_went_wrong, suggestions_success, sync_success,
+};
+use crate::stats::Statistics;
+use crate::suggestions;
 use crate::sync;
+use crate::sync::bulk::TimeEntryPatch;
+use crate::sync::prelude::SyncOutcome;
+use crate::sync::projects::{ProjectPatch, RunningEntryRule};
 
-use crate::auth::Credentials;
+use crate::analysis::{self, WorkingHours};
+use crate::auth::{AuthError, Credentials, Scheme};
+use crate::auto_stop::{AutoStop, Rules};
+use crate::billing::Billing;
+use crate::calendar::{self, Calendar, Period};
+use crate::duplicates;
+use crate::error::{Error, ValidationError};
+use crate::favourites::Favourites;
+use crate::goals::{self, Goal, Goals};
+use crate::history::{self, History};
 use crate::models::Delta;
-use crate::toggl_api::TogglApi;
+use crate::recurrence::{Recurrence, Recurrences};
+use crate::rounding::{RoundingRules, Roundings};
+use crate::search::{self, Search};
+use crate::session::{Session, Sessions};
+use crate::toggl_api::{models::Id, TogglApi};
 
 #[derive(Deserialize)]
 pub struct SyncRequestBody {
     last_sync: DateTime<Utc>,
     delta: Option<Delta>,
+    /// Attach warnings about overlapping time entries to the outcome.
+    #[serde(default)]
+    check_overlaps: bool,
 }
 
-fn create_api(req: HttpRequest) -> Option<TogglApi> {
-    let credentials = req
-        .headers()
-        .get("Authorization")
+#[derive(Deserialize)]
+pub struct StartTimerRequestBody {
+    description: String,
+    project_id: Option<Id>,
+    workspace_id: Option<Id>,
+}
+
+#[derive(Deserialize)]
+pub struct SplitRequestBody {
+    at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct MergeRequestBody {
+    first_id: Id,
+    second_id: Id,
+}
+
+#[derive(Deserialize)]
+pub struct BulkEditRequestBody {
+    ids: Vec<Id>,
+    patch: TimeEntryPatch,
+    workspace_id: Option<Id>,
+}
+
+#[derive(Deserialize)]
+pub struct ProjectsEditRequestBody {
+    ids: Vec<Id>,
+    patch: ProjectPatch,
+    /// What happens to the running time entry when its project is archived.
+    #[serde(default)]
+    running_entry: RunningEntryRule,
+}
+
+#[derive(Deserialize)]
+pub struct SnapshotQuery {
+    /// The archived projects are left out unless a time entry in the snapshot belongs to them.
+    #[serde(default = "include_archived_by_default")]
+    include_archived: bool,
+}
+
+fn include_archived_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct ReportQuery {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    group_by: Grouping,
+    /// The IANA name of the timezone, e.g. "Europe/Prague".
+    timezone: Option<String>,
+    /// The offset of the timezone in minutes when the name of the timezone isn't known.
+    utc_offset: Option<i32>,
+    /// The first day of the week (0 = Sunday, 1 = Monday, ...).
+    beginning_of_week: Option<u32>,
+    /// Report the tracked durations without the rounding rules of the user.
+    #[serde(default)]
+    raw: bool,
+}
+
+/// The days are computed in the calendar of the user from their Toggl settings unless
+/// the timezone or the first day of the week is specified.
+#[derive(Deserialize)]
+pub struct CalendarQuery {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    period: Period,
+    timezone: Option<String>,
+    beginning_of_week: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    format: Format,
+    /// A comma separated list of the CSV columns.
+    columns: Option<String>,
+    /// Export the tracked durations without the rounding rules of the user.
+    #[serde(default)]
+    raw: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    format: import::Format,
+    /// The default workspace of the user is used when it's not specified.
+    workspace_id: Option<Id>,
+    /// The IANA name of the timezone of the times which don't specify theirs. The timezone
+    /// of the user in Toggl is used by default.
+    timezone: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AnalysisQuery {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    /// The IANA name of the timezone of the working hours, the timezone of the user in Toggl
+    /// by default.
+    timezone: Option<String>,
+    /// The working hours in the `hh:mm` format, 09:00 - 17:00 by default.
+    working_hours_start: Option<String>,
+    working_hours_end: Option<String>,
+    include_weekends: Option<bool>,
+    /// Shorter gaps aren't reported, 15 minutes by default and a day at most.
+    min_gap_minutes: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct SuggestionsQuery {
+    prefix: Option<String>,
+    /// 10 suggestions by default.
+    limit: Option<usize>,
+    /// The IANA name of the timezone of the current time, the timezone of the user in Toggl
+    /// by default.
+    timezone: Option<String>,
+    /// Prefer what the user usually tracks at the current time of the day and day of the week.
+    #[serde(default)]
+    for_now: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DuplicatesQuery {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteDuplicatesRequestBody {
+    ids: Vec<Id>,
+}
+
+/// Words from the descriptions and the project names, filtered by the start and the duration
+/// of the time entries. The durations are in seconds.
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    project_id: Option<Id>,
+    min_duration: Option<u64>,
+    max_duration: Option<u64>,
+    running: Option<bool>,
+    /// The first page by default.
+    page: Option<usize>,
+    /// 20 results by default.
+    per_page: Option<usize>,
+}
+
+fn device(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("User-Agent")
         .and_then(|h| h.to_str().ok())
-        .and_then(Credentials::decode)?;
+        .map(|user_agent| user_agent.to_string())
+}
 
-    TogglApi::new(credentials)
+fn create_api(session: &Session) -> Result<TogglApi, Error> {
+    TogglApi::new(Credentials::Token(session.api_token.clone()))
+        .ok_or_else(|| Error::Internal("Cannot create a Toggl API client.".to_string()))
 }
 
-pub fn login(req: HttpRequest) -> HttpResponse {
+fn output_rounding(session: &Session, roundings: &Roundings, raw: bool) -> RoundingRules {
+    if raw {
+        RoundingRules::default()
+    } else {
+        roundings.rules(session.user_id)
+    }
+}
+
+fn timezone(utc_offset: Option<i32>) -> Result<FixedOffset, Error> {
+    let seconds = utc_offset.unwrap_or(0).checked_mul(60);
+    seconds.and_then(FixedOffset::east_opt).ok_or_else(|| {
+        Error::Validation(vec![ValidationError {
+            field: Some("utc_offset".to_string()),
+            reason: "The offset must be less than 24 hours.".to_string(),
+        }])
+    })
+}
+
+/// The timezone with the given IANA name, or the timezone of the user in Toggl.
+fn user_timezone(name: Option<&str>, api: &TogglApi) -> Result<Tz, Error> {
+    match name {
+        Some(name) => calendar::parse_timezone(name),
+        None => Ok(Calendar::of_user(&sync::server::fetch_user(api)?)?.timezone),
+    }
+}
+
+fn min_gap(minutes: Option<i64>) -> Result<Duration, Error> {
+    match minutes.unwrap_or(15) {
+        minutes @ 0..=1440 => Ok(Duration::minutes(minutes)),
+        _ => Err(Error::Validation(vec![ValidationError {
+            field: Some("min_gap_minutes".to_string()),
+            reason: "The gap must be between 0 and 1440 minutes.".to_string(),
+        }])),
+    }
+}
+
+fn time_of_day(field: &str, value: Option<&str>, default: NaiveTime) -> Result<NaiveTime, Error> {
+    match value {
+        Some(value) => NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| {
+            Error::Validation(vec![ValidationError {
+                field: Some(field.to_string()),
+                reason: "The time must be in the hh:mm format.".to_string(),
+            }])
+        }),
+        None => Ok(default),
+    }
+}
+
+/// The state kept by the proxy which the sync applies on top of the changes from Toggl.
+type SyncState = (
+    web::Data<History>,
+    web::Data<AutoStop>,
+    web::Data<Roundings>,
+    web::Data<Goals>,
+    web::Data<Favourites>,
+    web::Data<Recurrences>,
+    web::Data<Search>,
+    web::Data<Statistics>,
+    web::Data<Billing>,
+);
+
+/// The state kept by the proxy which is part of the snapshot.
+type SnapshotState = (
+    web::Data<Sessions>,
+    web::Data<History>,
+    web::Data<Favourites>,
+    web::Data<Recurrences>,
+    web::Data<Search>,
+    web::Data<Statistics>,
+    web::Data<Billing>,
+);
+
+pub fn login(
+    (
+        req,
+        credentials,
+        query,
+        (sessions, history, favourites, recurrences, search, statistics, billing),
+    ): (
+        HttpRequest,
+        Credentials,
+        web::Query<SnapshotQuery>,
+        SnapshotState,
+    ),
+) -> HttpResponse {
     let start = Utc::now();
 
-    let api = match create_api(req) {
+    let api = match TogglApi::new(credentials) {
         Some(api) => api,
-        None => return invalid_credentials(start),
+        None => {
+            return authentication_failed(
+                AuthError::MalformedCredentials,
+                Some(Scheme::Basic),
+                start,
+            )
+        }
+    };
+
+    let snapshot = if query.include_archived {
+        sync::fetch_snapshot(&api)
+    } else {
+        sync::fetch_snapshot_without_archived(&api)
     };
 
-    match sync::fetch_snapshot(&api) {
-        Ok(delta) => snapshot_success(delta, start),
+    match snapshot {
+        Ok(delta) => {
+            let session = match &delta.user {
+                Some(user) => {
+                    history.recorder(user.id, device(&req)).observed(&delta);
+                    sessions.issue(user.id, user.api_token.clone(), device(&req))
+                }
+                None => {
+                    return authentication_failed(
+                        AuthError::RejectedUpstream,
+                        Some(Scheme::Basic),
+                        start,
+                    )
+                }
+            };
+            let delta = match &delta.user {
+                Some(user) => {
+                    // Indexes of only the recent time entries would be wrong for good, they are
+                    // built lazily once the history can be fetched
+                    match sync::with_history(delta.clone(), &api) {
+                        Ok(history) => {
+                            search.build(user.id, &history);
+                            statistics.build(user.id, &history);
+                        }
+                        Err(_) => {
+                            search.forget(user.id);
+                            statistics.forget(user.id);
+                        }
+                    }
+                    let delta = statistics.delta_with_stats(user.id, delta.clone());
+                    let delta = billing.delta_with_earnings(user.id, delta, &api);
+                    Delta {
+                        favourites: Some(favourites.all(user.id)),
+                        ..recurrences.mark_delta(user.id, delta)
+                    }
+                }
+                None => delta,
+            };
+            snapshot_success(delta, session, start)
+        }
+        Err(Error::Auth(_)) => {
+            authentication_failed(AuthError::RejectedUpstream, Some(Scheme::Basic), start)
+        }
         Err(err) => something_went_wrong(err, start),
     }
 }
 
-pub fn sync((req, sync_req): (HttpRequest, web::Json<SyncRequestBody>)) -> HttpResponse {
+pub fn sync(
+    (
+        session,
+        sync_req,
+        (
+            history,
+            auto_stop,
+            roundings,
+            goals,
+            favourites,
+            recurrences,
+            search,
+            statistics,
+            billing,
+        ),
+    ): (Session, web::Json<SyncRequestBody>, SyncState),
+) -> HttpResponse {
     let start = Utc::now();
-    let SyncRequestBody { last_sync, delta } = sync_req.into_inner();
+    let SyncRequestBody {
+        last_sync,
+        delta,
+        check_overlaps,
+    } = sync_req.into_inner();
 
-    let api = match create_api(req) {
-        Some(api) => api,
-        None => return invalid_credentials(start),
+    let api = match create_api(&session) {
+        Ok(api) => api,
+        Err(err) => return something_went_wrong(err, start),
+    };
+
+    let client_delta = delta.clone().unwrap_or_default();
+    let client_favourites = client_delta.favourites.clone().unwrap_or_default();
+    let recorder = history.recorder(session.user_id, session.device.clone());
+    let rounding = roundings.rules(session.user_id);
+    let result = sync::update_server_and_calculate_delta_for_client(
+        last_sync, delta, &recorder, &rounding, &api,
+    )
+    .map(|result| auto_stop.apply(session.user_id, result, &rounding, &api))
+    .map(|result| billing.with_earnings(session.user_id, &client_delta, result, &api))
+    .map(|result| recurrences.mark(session.user_id, result))
+    .map(|result| statistics.apply(session.user_id, &client_delta, result, &api))
+    .map(|result| goals.with_progress(session.user_id, &client_delta, result, &api))
+    .map(|result| SyncOutcome {
+        favourites: favourites.sync(
+            session.user_id,
+            Some(last_sync),
+            client_favourites,
+            Utc::now(),
+        ),
+        ..result
+    });
+
+    if let Ok(result) = &result {
+        search.index_outcome(session.user_id, &client_delta, result);
+    }
+
+    match result {
+        Ok(result) if check_overlaps => {
+            sync_success(analysis::with_warnings(result, &client_delta, &api), start)
+        }
+        Ok(result) => sync_success(result, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn start_timer(
+    (session, start_req, history, roundings): (
+        Session,
+        web::Json<StartTimerRequestBody>,
+        web::Data<History>,
+        web::Data<Roundings>,
+    ),
+) -> HttpResponse {
+    let start = Utc::now();
+    let StartTimerRequestBody {
+        description,
+        project_id,
+        workspace_id,
+    } = start_req.into_inner();
+
+    let rounding = roundings.rules(session.user_id);
+    let result = create_api(&session)
+        .and_then(|api| sync::timer::start(description, project_id, workspace_id, &rounding, &api));
+
+    match result {
+        Ok(result) => pushed(result, &session, &history, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn stop_timer(
+    (session, history, roundings): (Session, web::Data<History>, web::Data<Roundings>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let rounding = roundings.rules(session.user_id);
+
+    match create_api(&session).and_then(|api| sync::timer::stop(&rounding, &api)) {
+        Ok(result) => pushed(result, &session, &history, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn continue_time_entry(
+    (session, id, history, roundings): (
+        Session,
+        web::Path<Id>,
+        web::Data<History>,
+        web::Data<Roundings>,
+    ),
+) -> HttpResponse {
+    let start = Utc::now();
+    let rounding = roundings.rules(session.user_id);
+
+    match create_api(&session)
+        .and_then(|api| sync::timer::continue_time_entry(*id, &rounding, &api))
+    {
+        Ok(result) => pushed(result, &session, &history, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn start_favourite(
+    (session, id, history, roundings, favourites): (
+        Session,
+        web::Path<Id>,
+        web::Data<History>,
+        web::Data<Roundings>,
+        web::Data<Favourites>,
+    ),
+) -> HttpResponse {
+    let start = Utc::now();
+    let rounding = roundings.rules(session.user_id);
+
+    match favourites.get(session.user_id, *id).and_then(|favourite| {
+        create_api(&session)
+            .and_then(|api| sync::timer::start_favourite(favourite, &rounding, &api))
+    }) {
+        Ok(result) => pushed(result, &session, &history, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn split_time_entry(
+    (session, id, split_req, history): (
+        Session,
+        web::Path<Id>,
+        web::Json<SplitRequestBody>,
+        web::Data<History>,
+    ),
+) -> HttpResponse {
+    let start = Utc::now();
+
+    match create_api(&session).and_then(|api| sync::editing::split(*id, split_req.at, &api)) {
+        Ok(result) => pushed(result, &session, &history, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn merge_time_entries(
+    (session, merge_req, history): (Session, web::Json<MergeRequestBody>, web::Data<History>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let MergeRequestBody {
+        first_id,
+        second_id,
+    } = merge_req.into_inner();
+
+    match create_api(&session).and_then(|api| sync::editing::merge(first_id, second_id, &api)) {
+        Ok(result) => pushed(result, &session, &history, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+/// Records the versions which the device has just pushed to Toggl before returning them.
+fn pushed(
+    result: SyncOutcome,
+    session: &Session,
+    history: &History,
+    start: DateTime<Utc>,
+) -> HttpResponse {
+    history
+        .recorder(session.user_id, session.device.clone())
+        .pushed(&result);
+    sync_success(result, start)
+}
+
+pub fn bulk_edit_time_entries(
+    (session, edit_req, history): (Session, web::Json<BulkEditRequestBody>, web::Data<History>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let BulkEditRequestBody {
+        ids,
+        patch,
+        workspace_id,
+    } = edit_req.into_inner();
+
+    let recorder = history.recorder(session.user_id, session.device.clone());
+    match create_api(&session)
+        .and_then(|api| sync::bulk::edit(ids, patch, workspace_id, &recorder, &api))
+    {
+        Ok(results) => bulk_edit_success(results, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn bulk_edit_projects(
+    (session, edit_req, history, roundings): (
+        Session,
+        web::Json<ProjectsEditRequestBody>,
+        web::Data<History>,
+        web::Data<Roundings>,
+    ),
+) -> HttpResponse {
+    let start = Utc::now();
+    let ProjectsEditRequestBody {
+        ids,
+        patch,
+        running_entry,
+    } = edit_req.into_inner();
+
+    let rounding = roundings.rules(session.user_id);
+    match create_api(&session)
+        .and_then(|api| sync::projects::edit(ids, patch, running_entry, &rounding, &api))
+    {
+        Ok(result) => pushed(result, &session, &history, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn refresh_session((session, sessions): (Session, web::Data<Sessions>)) -> HttpResponse {
+    let start = Utc::now();
+
+    match sessions.refresh(&session) {
+        Some(session) => session_success(session, start),
+        None => authentication_failed(AuthError::InvalidSession, Some(Scheme::Bearer), start),
+    }
+}
+
+pub fn revoke_session((session, sessions): (Session, web::Data<Sessions>)) -> HttpResponse {
+    let start = Utc::now();
+
+    if sessions.revoke(session.user_id, &session.id) {
+        session_revoked(start)
+    } else {
+        authentication_failed(AuthError::InvalidSession, Some(Scheme::Bearer), start)
+    }
+}
+
+pub fn list_sessions((session, sessions): (Session, web::Data<Sessions>)) -> HttpResponse {
+    let start = Utc::now();
+    sessions_success(sessions.list(session.user_id), start)
+}
+
+pub fn revoke_other_session(
+    (session, session_id, sessions): (Session, web::Path<String>, web::Data<Sessions>),
+) -> HttpResponse {
+    let start = Utc::now();
+
+    if sessions.revoke(session.user_id, &session_id) {
+        session_revoked(start)
+    } else {
+        something_went_wrong(
+            Error::NotFound("There is no such session.".to_string()),
+            start,
+        )
+    }
+}
+
+pub fn report(
+    (session, query, roundings): (Session, web::Query<ReportQuery>, web::Data<Roundings>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let ReportQuery {
+        from,
+        to,
+        group_by,
+        timezone: timezone_name,
+        utc_offset,
+        beginning_of_week,
+        raw,
+    } = query.into_inner();
+    let rounding = output_rounding(&session, &roundings, raw);
+
+    // The calendar of the user is used unless the client asks for a different one
+    let result = create_api(&session).and_then(|api| {
+        let user_calendar = Calendar::of_user(&sync::server::fetch_user(&api)?)?;
+        let beginning_of_week = beginning_of_week.unwrap_or(user_calendar.beginning_of_week) % 7;
+
+        match (timezone_name, utc_offset) {
+            (None, Some(utc_offset)) => {
+                let calendar = Calendar {
+                    timezone: timezone(Some(utc_offset))?,
+                    beginning_of_week,
+                };
+                reports::fetch_summary(from, to, group_by, &calendar, &rounding, &api)
+            }
+            (timezone_name, _) => {
+                let calendar = Calendar {
+                    timezone: match timezone_name {
+                        Some(name) => calendar::parse_timezone(&name)?,
+                        None => user_calendar.timezone,
+                    },
+                    beginning_of_week,
+                };
+                reports::fetch_summary(from, to, group_by, &calendar, &rounding, &api)
+            }
+        }
+    });
+
+    match result {
+        Ok(report) => report_success(report, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn export_time_entries(
+    (session, query, roundings): (Session, web::Query<ExportQuery>, web::Data<Roundings>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let ExportQuery {
+        from,
+        to,
+        format,
+        columns,
+        raw,
+    } = query.into_inner();
+    let rounding = output_rounding(&session, &roundings, raw);
+
+    let columns = match Column::parse_list(columns.as_deref()) {
+        Ok(columns) => columns,
+        Err(err) => return something_went_wrong(err, start),
     };
 
-    match sync::update_server_and_calculate_delta_for_client(last_sync, delta, &api) {
+    match create_api(&session).and_then(|api| export::fetch_time_entries(from, to, api)) {
+        Ok((time_entries, projects)) => {
+            let time_entries = time_entries.map(move |chunk| {
+                chunk.map(|time_entries| {
+                    time_entries
+                        .into_iter()
+                        .map(|te| rounding.in_output(te))
+                        .collect()
+                })
+            });
+            // The response has already started when a later chunk fails, so the download
+            // can only be interrupted
+            let chunks =
+                export::render(time_entries, &projects, format, columns, start).map(|chunk| {
+                    chunk.map(web::Bytes::from).map_err(|err| {
+                        log::warn!("Exporting time entries failed: {}", err);
+                        actix_web::error::ErrorBadGateway(err)
+                    })
+                });
+
+            HttpResponse::Ok()
+                .content_type(format.content_type())
+                .header(
+                    header::CONTENT_DISPOSITION,
+                    format!(
+                        "attachment; filename=\"time-entries.{}\"",
+                        format.extension()
+                    ),
+                )
+                .streaming(stream::iter_result(chunks))
+        }
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn import_time_entries(
+    (session, query, body, history): (Session, web::Query<ImportQuery>, String, web::Data<History>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let ImportQuery {
+        format,
+        workspace_id,
+        timezone,
+    } = query.into_inner();
+
+    let recorder = history.recorder(session.user_id, session.device.clone());
+    let result = create_api(&session).and_then(|api| {
+        let timezone = user_timezone(timezone.as_deref(), &api)?;
+        import::import(&body, format, workspace_id, &timezone, &recorder, &api)
+    });
+
+    match result {
+        Ok(report) => import_success(report, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn analyze_time_entries(
+    (session, query): (Session, web::Query<AnalysisQuery>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let query = query.into_inner();
+
+    let result = create_api(&session).and_then(|api| {
+        let min_gap = min_gap(query.min_gap_minutes)?;
+        let working_hours = WorkingHours {
+            start: time_of_day(
+                "working_hours_start",
+                query.working_hours_start.as_deref(),
+                NaiveTime::from_hms(9, 0, 0),
+            )?,
+            end: time_of_day(
+                "working_hours_end",
+                query.working_hours_end.as_deref(),
+                NaiveTime::from_hms(17, 0, 0),
+            )?,
+            include_weekends: query.include_weekends.unwrap_or(false),
+            timezone: user_timezone(query.timezone.as_deref(), &api)?,
+        };
+        analysis::analyze(query.from, query.to, &working_hours, min_gap, &api)
+    });
+
+    match result {
+        Ok(analysis) => analysis_success(analysis, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn suggest_time_entries(
+    (session, query): (Session, web::Query<SuggestionsQuery>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let query = query.into_inner();
+
+    let result = create_api(&session).and_then(|api| {
+        let context = suggestions::Context {
+            prefix: query.prefix,
+            local_time: if query.for_now {
+                Some(start.with_timezone(&user_timezone(query.timezone.as_deref(), &api)?))
+            } else {
+                None
+            },
+            limit: query.limit.unwrap_or(10),
+        };
+        suggestions::fetch_suggestions(&context, &api)
+    });
+
+    match result {
+        Ok(suggestions) => suggestions_success(suggestions, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn time_entry_history(
+    (session, id, history): (Session, web::Path<Id>, web::Data<History>),
+) -> HttpResponse {
+    let start = Utc::now();
+    history_success(history.time_entry(session.user_id, *id), start)
+}
+
+pub fn project_history(
+    (session, id, history): (Session, web::Path<Id>, web::Data<History>),
+) -> HttpResponse {
+    let start = Utc::now();
+    history_success(history.project(session.user_id, *id), start)
+}
+
+pub fn restore_time_entry(
+    (session, path, history): (Session, web::Path<(Id, u64)>, web::Data<History>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let (id, revision) = path.into_inner();
+    let recorder = history.recorder(session.user_id, session.device.clone());
+
+    match create_api(&session)
+        .and_then(|api| history::restore_time_entry(id, revision, &recorder, &api))
+    {
         Ok(result) => sync_success(result, start),
         Err(err) => something_went_wrong(err, start),
     }
 }
+
+pub fn restore_project(
+    (session, path, history): (Session, web::Path<(Id, u64)>, web::Data<History>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let (id, revision) = path.into_inner();
+    let recorder = history.recorder(session.user_id, session.device.clone());
+
+    match create_api(&session)
+        .and_then(|api| history::restore_project(id, revision, &recorder, &api))
+    {
+        Ok(result) => sync_success(result, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn auto_stop_rules((session, auto_stop): (Session, web::Data<AutoStop>)) -> HttpResponse {
+    let start = Utc::now();
+    auto_stop_success(auto_stop.rules(session.user_id), start)
+}
+
+pub fn set_auto_stop_rules(
+    (session, rules, auto_stop): (Session, web::Json<Rules>, web::Data<AutoStop>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let rules = rules.into_inner();
+
+    // The local time is in the timezone of the user in Toggl unless a different one is given
+    let result = match rules.timezone {
+        Some(_) => Ok(rules),
+        None => create_api(&session)
+            .and_then(|api| sync::server::fetch_user(&api))
+            .map(|user| Rules {
+                timezone: user.timezone.filter(|name| !name.is_empty()),
+                ..rules
+            }),
+    }
+    .and_then(|rules| auto_stop.set_rules(session.user_id, session.api_token.clone(), rules));
+
+    match result {
+        Ok(rules) => auto_stop_success(rules, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn rounding_rules((session, roundings): (Session, web::Data<Roundings>)) -> HttpResponse {
+    let start = Utc::now();
+    rounding_success(roundings.rules(session.user_id), start)
+}
+
+pub fn set_rounding_rules(
+    (session, rules, roundings): (Session, web::Json<RoundingRules>, web::Data<Roundings>),
+) -> HttpResponse {
+    let start = Utc::now();
+
+    match roundings.set_rules(session.user_id, rules.into_inner()) {
+        Ok(rules) => rounding_success(rules, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn calendar((session, query): (Session, web::Query<CalendarQuery>)) -> HttpResponse {
+    let start = Utc::now();
+    let CalendarQuery {
+        from,
+        to,
+        period,
+        timezone,
+        beginning_of_week,
+    } = query.into_inner();
+
+    match create_api(&session).and_then(|api| {
+        calendar::fetch_buckets(
+            from,
+            to,
+            period,
+            timezone.as_deref(),
+            beginning_of_week,
+            &api,
+        )
+    }) {
+        Ok(buckets) => calendar_success(buckets, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn goals((session, goals): (Session, web::Data<Goals>)) -> HttpResponse {
+    let start = Utc::now();
+    goals_success(goals.goals(session.user_id), start)
+}
+
+pub fn set_goals(
+    (session, new_goals, goals): (Session, web::Json<Vec<Goal>>, web::Data<Goals>),
+) -> HttpResponse {
+    let start = Utc::now();
+
+    match goals.set_goals(session.user_id, new_goals.into_inner()) {
+        Ok(goals) => goals_success(goals, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn goal_progress((session, goals): (Session, web::Data<Goals>)) -> HttpResponse {
+    let start = Utc::now();
+
+    match create_api(&session)
+        .and_then(|api| goals::fetch_progress(&goals.goals(session.user_id), &api))
+    {
+        Ok(progress) => progress_success(progress, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn recurrences((session, recurrences): (Session, web::Data<Recurrences>)) -> HttpResponse {
+    let start = Utc::now();
+    recurrences_success(recurrences.list(session.user_id), start)
+}
+
+pub fn create_recurrence(
+    (session, recurrence, recurrences): (Session, web::Json<Recurrence>, web::Data<Recurrences>),
+) -> HttpResponse {
+    let start = Utc::now();
+
+    match recurrences.create(
+        session.user_id,
+        session.api_token.clone(),
+        recurrence.into_inner(),
+    ) {
+        Ok(recurrence) => recurrence_success(recurrence, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn update_recurrence(
+    (session, id, recurrence, recurrences): (
+        Session,
+        web::Path<Id>,
+        web::Json<Recurrence>,
+        web::Data<Recurrences>,
+    ),
+) -> HttpResponse {
+    let start = Utc::now();
+
+    match recurrences.update(session.user_id, *id, recurrence.into_inner()) {
+        Ok(recurrence) => recurrence_success(recurrence, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn delete_recurrence(
+    (session, id, recurrences): (Session, web::Path<Id>, web::Data<Recurrences>),
+) -> HttpResponse {
+    let start = Utc::now();
+
+    match recurrences.delete(session.user_id, *id) {
+        Ok(()) => recurrence_deleted(start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+/// Creates the time entries of the recurrences right away instead of waiting for
+/// the background job.
+pub fn expand_recurrences(
+    (session, history, recurrences): (Session, web::Data<History>, web::Data<Recurrences>),
+) -> HttpResponse {
+    let start = Utc::now();
+
+    match create_api(&session).and_then(|api| recurrences.expand(session.user_id, &api)) {
+        Ok(result) => pushed(result, &session, &history, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn search_time_entries(
+    (session, query, search): (Session, web::Query<SearchQuery>, web::Data<Search>),
+) -> HttpResponse {
+    let start = Utc::now();
+    let query = query.into_inner();
+    let query = search::Query {
+        text: query.q,
+        from: query.from,
+        to: query.to,
+        project_id: query.project_id,
+        min_duration: query.min_duration,
+        max_duration: query.max_duration,
+        running: query.running,
+        page: query.page.unwrap_or(1),
+        per_page: query.per_page.unwrap_or(20),
+    };
+
+    match create_api(&session).and_then(|api| search.search(session.user_id, &query, &api)) {
+        Ok(results) => search_success(results, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn find_duplicates((session, query): (Session, web::Query<DuplicatesQuery>)) -> HttpResponse {
+    let start = Utc::now();
+
+    match create_api(&session)
+        .and_then(|api| duplicates::fetch_duplicates(query.from, query.to, &api))
+    {
+        Ok(groups) => duplicates_success(groups, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
+
+pub fn delete_duplicates(
+    (session, delete_req, history): (
+        Session,
+        web::Json<DeleteDuplicatesRequestBody>,
+        web::Data<History>,
+    ),
+) -> HttpResponse {
+    let start = Utc::now();
+
+    match create_api(&session).and_then(|api| duplicates::delete_duplicates(&delete_req.ids, &api))
+    {
+        Ok(result) => pushed(result, &session, &history, start),
+        Err(err) => something_went_wrong(err, start),
+    }
+}
@@ -1,19 +1,107 @@
+use actix_web::http::StatusCode;
 use failure::Fail;
+use serde::Serialize;
+
+use crate::auth::AuthError;
+use crate::toggl_api::errors;
+
+/// A problem with a specific field of an entity which the client can highlight.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct ValidationError {
+    pub field: Option<String>,
+    pub reason: String,
+}
 
 #[derive(Debug, Fail)]
 pub enum Error {
-    #[fail(display = "API error {}: {}", _0, _1)]
-    ApiError(u16, String),
+    #[fail(display = "{}", _0)]
+    Auth(AuthError),
+
+    #[fail(display = "Toggl refused the data: {}", message)]
+    UpstreamValidation {
+        message: String,
+        errors: Vec<ValidationError>,
+    },
+
+    #[fail(display = "The entity is invalid.")]
+    Validation(Vec<ValidationError>),
+
+    #[fail(display = "Too many requests to Toggl, try again later.")]
+    RateLimited { retry_after: Option<u64> },
+
+    #[fail(display = "Toggl didn't respond in time.")]
+    Timeout,
+
+    #[fail(display = "Not found: {}", _0)]
+    NotFound(String),
+
+    #[fail(display = "Toggl API error: {}", _0)]
+    Upstream(String),
 
     #[fail(display = "Network error: {:?}", _0)]
-    NetworkError(reqwest::Error),
+    Network(reqwest::Error),
+
+    #[fail(display = "Internal error: {}", _0)]
+    Internal(String),
 }
 
 impl Error {
-    pub fn code(&self) -> u16 {
+    /// A stable machine-readable identifier of the error which clients can branch on.
+    pub fn code(&self) -> &'static str {
         match self {
-            Error::ApiError(status, _) => *status,
-            Error::NetworkError(_) => 1,
+            Error::Auth(err) => err.code(),
+            Error::Validation(_) => "validation_failed",
+            Error::UpstreamValidation { .. } => "upstream_validation_failed",
+            Error::RateLimited { .. } => "rate_limited",
+            Error::Timeout => "upstream_timeout",
+            Error::NotFound(_) => "not_found",
+            Error::Upstream(_) => "upstream_error",
+            Error::Network(_) => "upstream_unreachable",
+            Error::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::Auth(err) => err.status(),
+            Error::Validation(_) | Error::UpstreamValidation { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            Error::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Error::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Upstream(_) | Error::Network(_) => StatusCode::BAD_GATEWAY,
+            Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn validation_errors(&self) -> Vec<ValidationError> {
+        match self {
+            Error::Validation(errors) | Error::UpstreamValidation { errors, .. } => errors.clone(),
+            _ => vec![],
+        }
+    }
+
+    /// Maps an unsuccessful response from Toggl to the corresponding error.
+    pub fn from_upstream(status: u16, body: String, retry_after: Option<u64>) -> Error {
+        match status {
+            401 => Error::Auth(AuthError::RejectedUpstream),
+            403 => Error::Auth(AuthError::Forbidden),
+            404 => Error::NotFound(body),
+            408 | 504 => Error::Timeout,
+            429 => Error::RateLimited { retry_after },
+            400..=499 => {
+                let errors = errors::parse(&body);
+                let message = if errors.is_empty() {
+                    body
+                } else {
+                    let reasons: Vec<_> = errors.iter().map(|err| err.reason.clone()).collect();
+                    reasons.join("; ")
+                };
+
+                Error::UpstreamValidation { message, errors }
+            }
+            _ => Error::Upstream(format!("{} {}", status, body)),
         }
     }
 }
@@ -21,6 +109,66 @@ impl Error {
 impl std::convert::From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Error {
         println!("From reqwest {:?}", err);
-        Error::NetworkError(err)
+        if err.is_timeout() {
+            Error::Timeout
+        } else if err.is_serialization() {
+            Error::Upstream(format!("Unexpected response: {}", err))
+        } else {
+            Error::Network(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use crate::auth::AuthError;
+    use actix_web::http::StatusCode;
+
+    fn upstream(status: u16) -> Error {
+        Error::from_upstream(status, "message".to_string(), Some(30))
+    }
+
+    #[test]
+    fn maps_upstream_auth_failures() {
+        assert_eq!(upstream(401).code(), AuthError::RejectedUpstream.code());
+        assert_eq!(upstream(401).status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(upstream(403).status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn maps_client_errors_to_validation_errors() {
+        assert_eq!(upstream(400).code(), "upstream_validation_failed");
+        assert_eq!(upstream(422).status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn parses_validation_errors() {
+        let err = Error::from_upstream(400, "\"project name already exists\"".to_string(), None);
+        let errors = err.validation_errors();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, Some("name".to_string()));
+    }
+
+    #[test]
+    fn maps_rate_limiting() {
+        match upstream(429) {
+            Error::RateLimited { retry_after } => assert_eq!(retry_after, Some(30)),
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn maps_server_errors_to_bad_gateway() {
+        assert_eq!(upstream(500).status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(upstream(503).code(), "upstream_error");
+        assert_eq!(upstream(504).status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[test]
+    fn maps_not_found() {
+        assert_eq!(upstream(404).code(), "not_found");
+        assert_eq!(upstream(404).status(), StatusCode::NOT_FOUND);
     }
 }
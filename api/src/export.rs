@@ -0,0 +1,447 @@
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::error::{Error, ValidationError};
+use crate::models::{Project, TimeEntry};
+use crate::sync::server;
+use crate::toggl_api::{models::Id, TogglApi};
+
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Csv,
+    Jsonl,
+    Ics,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Jsonl => "application/x-ndjson",
+            Format::Ics => "text/calendar; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Jsonl => "jsonl",
+            Format::Ics => "ics",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Column {
+    Id,
+    WorkspaceId,
+    ProjectId,
+    Project,
+    Description,
+    Start,
+    Stop,
+    Duration,
+}
+
+const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Start,
+    Column::Stop,
+    Column::Duration,
+    Column::Project,
+    Column::Description,
+];
+
+/// The time entries are fetched from Toggl and streamed to the client one week at a time.
+const CHUNK_DAYS: i64 = 7;
+
+/// Lines of iCalendar files shouldn't be longer than 75 octets (RFC 5545, section 3.1).
+const ICS_LINE_LENGTH: usize = 75;
+
+impl Column {
+    fn name(self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::WorkspaceId => "workspace_id",
+            Column::ProjectId => "project_id",
+            Column::Project => "project",
+            Column::Description => "description",
+            Column::Start => "start",
+            Column::Stop => "stop",
+            Column::Duration => "duration",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Column> {
+        [
+            Column::Id,
+            Column::WorkspaceId,
+            Column::ProjectId,
+            Column::Project,
+            Column::Description,
+            Column::Start,
+            Column::Stop,
+            Column::Duration,
+        ]
+        .iter()
+        .find(|column| column.name() == name)
+        .cloned()
+    }
+
+    /// Parses a comma separated list of column names. The default columns are used
+    /// when the list isn't specified.
+    pub fn parse_list(columns: Option<&str>) -> Result<Vec<Column>, Error> {
+        let columns = match columns {
+            Some(columns) if !columns.trim().is_empty() => columns,
+            _ => return Ok(DEFAULT_COLUMNS.to_vec()),
+        };
+
+        columns
+            .split(',')
+            .map(|name| {
+                Column::parse(name.trim()).ok_or_else(|| {
+                    Error::Validation(vec![ValidationError {
+                        field: Some("columns".to_string()),
+                        reason: format!("Unknown column '{}'.", name.trim()),
+                    }])
+                })
+            })
+            .collect()
+    }
+}
+
+/// A time entry joined with the name of its project.
+#[derive(Serialize, Debug, PartialEq)]
+struct ExportedTimeEntry {
+    id: Id,
+    workspace_id: Id,
+    project_id: Option<Id>,
+    project: Option<String>,
+    description: String,
+    start: DateTime<Utc>,
+    stop: DateTime<Utc>,
+    duration: u64,
+    running: bool,
+}
+
+impl ExportedTimeEntry {
+    fn new(te: TimeEntry, project_names: &HashMap<Id, String>, now: DateTime<Utc>) -> Self {
+        let duration = te.duration.unwrap_or_else(|| te.elapsed_seconds_until(now));
+
+        ExportedTimeEntry {
+            id: te.id,
+            workspace_id: te.workspace_id,
+            project: te.project_id.and_then(|id| project_names.get(&id).cloned()),
+            project_id: te.project_id,
+            stop: te.start + Duration::seconds(duration as i64),
+            running: te.is_running(),
+            start: te.start,
+            duration,
+            description: te.description,
+        }
+    }
+
+    fn column(&self, column: Column) -> String {
+        match column {
+            Column::Id => self.id.to_string(),
+            Column::WorkspaceId => self.workspace_id.to_string(),
+            Column::ProjectId => self.project_id.map(|id| id.to_string()).unwrap_or_default(),
+            Column::Project => self.project.clone().unwrap_or_default(),
+            Column::Description => self.description.clone(),
+            Column::Start => self.start.to_rfc3339_opts(SecondsFormat::Secs, true),
+            Column::Stop if self.running => String::new(),
+            Column::Stop => self.stop.to_rfc3339_opts(SecondsFormat::Secs, true),
+            Column::Duration => self.duration.to_string(),
+        }
+    }
+}
+
+/// The time entries of one week of the exported range.
+pub type Chunk = Result<Vec<TimeEntry>, Error>;
+
+/// Fetches the projects and returns the time entries which started in the given range.
+/// The time entries are fetched lazily week by week, sorted by their start.
+pub fn fetch_time_entries(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    api: TogglApi,
+) -> Result<(impl Iterator<Item = Chunk>, Vec<Project>), Error> {
+    if from >= to {
+        return Err(Error::Validation(vec![ValidationError {
+            field: Some("to".to_string()),
+            reason: "The end of the range must be after its beginning.".to_string(),
+        }]));
+    }
+
+    let projects = server::fetch_all_projects(&api)?;
+    let time_entries = chunks(from, to).map(move |(from, to)| {
+        let mut time_entries: Vec<_> = server::fetch_time_entries_between(from, to, &api)?
+            .into_iter()
+            .filter(|te| te.server_deleted_at.is_none() && from <= te.start && te.start < to)
+            .collect();
+        time_entries.sort_by_key(|te| te.start);
+        Ok(time_entries)
+    });
+
+    Ok((time_entries, projects))
+}
+
+/// Splits the range into consecutive weeks, the last one can be shorter.
+fn chunks(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> impl Iterator<Item = (DateTime<Utc>, DateTime<Utc>)> {
+    let mut start = from;
+    std::iter::from_fn(move || {
+        if start >= to {
+            return None;
+        }
+        let end = start
+            .checked_add_signed(Duration::days(CHUNK_DAYS))
+            .map_or(to, |end| end.min(to));
+        let chunk = (start, end);
+        start = end;
+        Some(chunk)
+    })
+}
+
+/// Renders the chunks of time entries in the given format. The output is produced lazily
+/// chunk by chunk, so it can be streamed to the client while the next chunk is fetched.
+pub fn render<I>(
+    time_entries: I,
+    projects: &[Project],
+    format: Format,
+    columns: Vec<Column>,
+    now: DateTime<Utc>,
+) -> Box<dyn Iterator<Item = Result<String, Error>>>
+where
+    I: Iterator<Item = Chunk> + 'static,
+{
+    let project_names: HashMap<Id, String> = projects
+        .iter()
+        .map(|project| (project.id, project.name.clone()))
+        .collect();
+    let entries = time_entries.map(move |chunk| {
+        chunk.map(|time_entries| {
+            time_entries
+                .into_iter()
+                .map(|te| ExportedTimeEntry::new(te, &project_names, now))
+                .collect::<Vec<_>>()
+        })
+    });
+
+    match format {
+        Format::Csv => {
+            let header = csv_row(columns.iter().map(|column| column.name().to_string()));
+            let rows = entries.map(move |chunk| {
+                chunk.map(|entries| {
+                    entries
+                        .iter()
+                        .map(|te| csv_row(columns.iter().map(|column| te.column(*column))))
+                        .collect()
+                })
+            });
+            Box::new(std::iter::once(Ok(header)).chain(rows))
+        }
+        Format::Jsonl => Box::new(entries.map(|chunk| {
+            chunk.map(|entries| {
+                entries
+                    .iter()
+                    .map(|te| {
+                        let mut line = serde_json::to_string(te).unwrap_or_default();
+                        line.push('\n');
+                        line
+                    })
+                    .collect()
+            })
+        })),
+        Format::Ics => {
+            let header = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//toggl-utopia//export//EN\r\n";
+            let footer = "END:VCALENDAR\r\n";
+            let events = entries.map(move |chunk| {
+                chunk.map(|entries| entries.iter().map(|te| ics_event(te, now)).collect())
+            });
+            Box::new(
+                std::iter::once(Ok(header.to_string()))
+                    .chain(events)
+                    .chain(std::iter::once(Ok(footer.to_string()))),
+            )
+        }
+    }
+}
+
+fn csv_row<I: Iterator<Item = String>>(values: I) -> String {
+    let mut row = values
+        .map(|value| csv_escape(&value))
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push_str("\r\n");
+    row
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn ics_event(te: &ExportedTimeEntry, now: DateTime<Utc>) -> String {
+    let summary = match &te.project {
+        Some(project) if te.description.is_empty() => project.clone(),
+        Some(project) => format!("{} ({})", te.description, project),
+        None => te.description.clone(),
+    };
+
+    [
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@toggl-utopia", te.id),
+        format!("DTSTAMP:{}", ics_date(now)),
+        format!("DTSTART:{}", ics_date(te.start)),
+        format!("DTEND:{}", ics_date(te.stop)),
+        format!("SUMMARY:{}", ics_escape(&summary)),
+        "END:VEVENT".to_string(),
+    ]
+    .iter()
+    .map(|line| ics_fold(line))
+    .collect()
+}
+
+fn ics_date(date: DateTime<Utc>) -> String {
+    date.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Splits long lines into multiple lines where the continuation lines start with a space.
+fn ics_fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut line_length = 0;
+
+    for c in line.chars() {
+        if line_length + c.len_utf8() > ICS_LINE_LENGTH {
+            folded.push_str("\r\n ");
+            line_length = 1;
+        }
+        folded.push(c);
+        line_length += c.len_utf8();
+    }
+
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunks, ics_escape, ics_fold, render, Column, Format};
+    use crate::models::{fixtures, Project, TimeEntry};
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2019, 12, 10).and_hms(12, 0, 0)
+    }
+
+    fn time_entry(description: &str, duration: Option<u64>) -> TimeEntry {
+        TimeEntry {
+            description: description.to_string(),
+            project_id: Some(2),
+            at: now(),
+            ..fixtures::time_entry(1, Utc.ymd(2019, 12, 10).and_hms(9, 0, 0), duration)
+        }
+    }
+
+    fn projects() -> Vec<Project> {
+        vec![Project {
+            at: now(),
+            ..fixtures::project(2, "Utopia")
+        }]
+    }
+
+    fn export(te: TimeEntry, format: Format, columns: Option<&str>) -> String {
+        let columns = Column::parse_list(columns).unwrap();
+        render(
+            vec![Ok(vec![te])].into_iter(),
+            &projects(),
+            format,
+            columns,
+            now(),
+        )
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    #[test]
+    fn renders_csv_with_selected_columns() {
+        let csv = export(
+            time_entry("Meeting, \"planning\"", Some(3600)),
+            Format::Csv,
+            Some("project, description,duration"),
+        );
+
+        assert_eq!(
+            csv,
+            "project,description,duration\r\nUtopia,\"Meeting, \"\"planning\"\"\",3600\r\n"
+        );
+    }
+
+    #[test]
+    fn splits_the_range_into_weeks() {
+        let from = Utc.ymd(2019, 12, 1).and_hms(0, 0, 0);
+        let to = Utc.ymd(2019, 12, 10).and_hms(12, 0, 0);
+
+        let weeks: Vec<_> = chunks(from, to).collect();
+
+        assert_eq!(
+            weeks,
+            vec![
+                (from, Utc.ymd(2019, 12, 8).and_hms(0, 0, 0)),
+                (Utc.ymd(2019, 12, 8).and_hms(0, 0, 0), to),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_columns() {
+        assert!(Column::parse_list(Some("start,billable")).is_err());
+        assert_eq!(Column::parse_list(None).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn renders_running_entries_in_json_lines() {
+        let jsonl = export(time_entry("Work", None), Format::Jsonl, None);
+        let value: serde_json::Value = serde_json::from_str(jsonl.trim_end()).unwrap();
+
+        assert!(jsonl.ends_with('\n'));
+        assert_eq!(value["project"], "Utopia");
+        assert_eq!(value["duration"], 3 * 3600);
+        assert_eq!(value["running"], true);
+    }
+
+    #[test]
+    fn renders_ics_events() {
+        let ics = export(time_entry("Work", Some(3600)), Format::Ics, None);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains("DTSTART:20191210T090000Z\r\nDTEND:20191210T100000Z\r\n"));
+        assert!(ics.contains("SUMMARY:Work (Utopia)\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn escapes_and_folds_ics_lines() {
+        assert_eq!(ics_escape("a;b,c\nd"), "a\\;b\\,c\\nd");
+
+        let folded = ics_fold(&format!("SUMMARY:{}", "x".repeat(100)));
+        let lines: Vec<_> = folded.split("\r\n").collect();
+        assert_eq!(lines[0].len(), 75);
+        assert!(lines[1].starts_with(' '));
+    }
+}
@@ -0,0 +1,232 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::error::{Error, ValidationError};
+use crate::models::{Entity, Favourite};
+use crate::sync::prelude::{changed, created, failed, SyncResult};
+use crate::toggl_api::models::Id;
+
+const MAX_DESCRIPTION_LENGTH: usize = 3000;
+
+/// The favourites of all the users. The deleted favourites are kept so the other devices
+/// of the user learn about the deletion during their next sync.
+pub struct Favourites {
+    users: Mutex<HashMap<Id, HashMap<Id, Favourite>>>,
+    next_id: Mutex<Id>,
+}
+
+impl Default for Favourites {
+    fn default() -> Favourites {
+        Favourites {
+            users: Mutex::new(HashMap::new()),
+            next_id: Mutex::new(1),
+        }
+    }
+}
+
+impl Favourites {
+    /// All the favourites of the user which haven't been deleted.
+    pub fn all(&self, user_id: Id) -> Vec<Favourite> {
+        let mut favourites: Vec<_> = self
+            .users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .map(|favourites| {
+                favourites
+                    .values()
+                    .filter(|favourite| !favourite.is_deleted())
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        favourites.sort_by_key(|favourite| favourite.id);
+
+        favourites
+    }
+
+    pub fn get(&self, user_id: Id, id: Id) -> Result<Favourite, Error> {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .and_then(|favourites| favourites.get(&id))
+            .filter(|favourite| !favourite.is_deleted())
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("There is no favourite {}.", id)))
+    }
+
+    /// Applies the changes of the favourites from the client and returns the favourites which
+    /// the client should update: the ones created by the client with the ids assigned by the
+    /// proxy, and the ones changed by other devices since the last sync.
+    pub fn sync(
+        &self,
+        user_id: Id,
+        last_sync: Option<DateTime<Utc>>,
+        client_changes: Vec<Favourite>,
+        now: DateTime<Utc>,
+    ) -> Vec<SyncResult<Favourite>> {
+        let mut users = self.users.lock().unwrap();
+        let favourites = users.entry(user_id).or_default();
+
+        let changed_ids: Vec<Id> = client_changes.iter().map(|change| change.id).collect();
+        let mut results: Vec<_> = favourites
+            .values()
+            .filter(|favourite| last_sync.is_none_or(|last_sync| favourite.at > last_sync))
+            .filter(|favourite| !changed_ids.contains(&favourite.id))
+            .cloned()
+            .map(changed)
+            .collect();
+
+        for change in client_changes {
+            let errors = if change.is_deleted() {
+                vec![]
+            } else {
+                validate(&change)
+            };
+            if !errors.is_empty() {
+                results.push(failed(change.id, Error::Validation(errors)));
+                continue;
+            }
+
+            if !change.exists_on_server() {
+                if change.is_deleted() {
+                    continue; // it was never synced, there's nothing to delete
+                }
+                let favourite = Favourite {
+                    id: self.assign_id(),
+                    at: now,
+                    ..change.clone()
+                };
+                favourites.insert(favourite.id, favourite.clone());
+                results.push(created(change.id, favourite));
+                continue;
+            }
+
+            match favourites.get_mut(&change.id) {
+                // The newer version wins, just like with the entities synced with Toggl
+                Some(existing) if existing.at > change.at => {
+                    results.push(changed(existing.clone()))
+                }
+                Some(existing) => {
+                    *existing = Favourite { at: now, ..change };
+                }
+                None => results.push(failed(
+                    change.id,
+                    Error::NotFound(format!("There is no favourite {}.", change.id)),
+                )),
+            }
+        }
+
+        results
+    }
+
+    fn assign_id(&self) -> Id {
+        let mut next_id = self.next_id.lock().unwrap();
+        let id = *next_id;
+        *next_id += 1;
+
+        id
+    }
+}
+
+fn validate(favourite: &Favourite) -> Vec<ValidationError> {
+    let mut errors = vec![];
+    if favourite.description.trim().is_empty() && favourite.project_id.is_none() {
+        errors.push(ValidationError {
+            field: Some("description".to_string()),
+            reason: "A favourite needs a description or a project.".to_string(),
+        });
+    }
+    if favourite.description.chars().count() > MAX_DESCRIPTION_LENGTH {
+        errors.push(ValidationError {
+            field: Some("description".to_string()),
+            reason: format!(
+                "The description can be at most {} characters long.",
+                MAX_DESCRIPTION_LENGTH
+            ),
+        });
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Favourites;
+    use crate::models::Favourite;
+    use crate::sync::prelude::SyncResult;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2019, 12, 10).and_hms(9, 0, 0)
+    }
+
+    fn favourite(id: i64, description: &str) -> Favourite {
+        Favourite {
+            id,
+            workspace_id: 1,
+            description: description.to_string(),
+            project_id: None,
+            tags: vec!["meeting".to_string()],
+            billable: false,
+            at: now(),
+            server_deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn assigns_ids_and_sends_changes_to_the_other_devices() {
+        let favourites = Favourites::default();
+
+        let results = favourites.sync(7, None, vec![favourite(-1, "Stand-up")], now());
+        let id = match &results[..] {
+            [SyncResult::Created {
+                client_assigned_id,
+                entity,
+            }] => {
+                assert_eq!(*client_assigned_id, -1);
+                entity.id
+            }
+            other => panic!("Unexpected results {:?}", other),
+        };
+
+        let later = now() + Duration::minutes(5);
+        let results = favourites.sync(7, Some(now() - Duration::minutes(1)), vec![], later);
+        assert_eq!(results.len(), 1);
+        assert!(favourites.sync(7, Some(now()), vec![], later).is_empty());
+
+        let deleted = Favourite {
+            server_deleted_at: Some(later),
+            ..favourite(id, "")
+        };
+        assert!(favourites
+            .sync(7, Some(now()), vec![deleted], later)
+            .is_empty());
+        assert!(favourites.all(7).is_empty());
+        assert!(favourites.get(7, id).is_err());
+        assert_eq!(favourites.sync(7, Some(now()), vec![], later).len(), 1);
+    }
+
+    #[test]
+    fn keeps_the_newer_version_and_rejects_invalid_favourites() {
+        let favourites = Favourites::default();
+        favourites.sync(7, None, vec![favourite(-1, "Stand-up")], now());
+
+        let outdated = Favourite {
+            at: now() - Duration::hours(1),
+            ..favourite(1, "Daily")
+        };
+        let results = favourites.sync(7, Some(now()), vec![outdated, favourite(-2, " ")], now());
+
+        match &results[..] {
+            [SyncResult::Changed { entity }, SyncResult::Failed { entity_id, .. }] => {
+                assert_eq!(entity.description, "Stand-up");
+                assert_eq!(*entity_id, -2);
+            }
+            other => panic!("Unexpected results {:?}", other),
+        }
+        assert!(favourites.sync(8, None, vec![], now()).is_empty());
+    }
+}
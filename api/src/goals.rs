@@ -0,0 +1,374 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::calendar::{Bucket, Calendar, Period};
+use crate::error::{Error, ValidationError};
+use crate::models::{Delta, Entity, TimeEntry};
+use crate::sync::prelude::{SyncOutcome, SyncResult};
+use crate::sync::server;
+use crate::toggl_api::{models::Id, TogglApi};
+
+/// A target such as "8 hours every weekday" or "4 hours on a project this week".
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Goal {
+    pub name: String,
+    pub period: Period,
+    pub minutes: u64,
+    /// Only the time tracked on this project counts towards the goal.
+    #[serde(default)]
+    pub project_id: Option<Id>,
+    /// The days of the week (0 = Sunday, 1 = Monday, ...) on which a daily goal applies,
+    /// every day when empty.
+    #[serde(default)]
+    pub weekdays: Vec<u32>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub goal: Goal,
+    pub bucket: Bucket,
+    pub tracked_seconds: u64,
+    pub target_seconds: u64,
+    pub completed: bool,
+}
+
+/// The time entries of the current week of one user, kept up to date with every sync.
+struct Week {
+    calendar: Calendar<chrono_tz::Tz>,
+    date: NaiveDate,
+    time_entries: HashMap<Id, TimeEntry>,
+}
+
+#[derive(Default)]
+pub struct Goals {
+    users: Mutex<HashMap<Id, Vec<Goal>>>,
+    weeks: Mutex<HashMap<Id, Week>>,
+}
+
+impl Goal {
+    fn applies_on(&self, bucket: &Bucket) -> bool {
+        match self.period {
+            Period::Day => {
+                self.weekdays.is_empty()
+                    || self
+                        .weekdays
+                        .contains(&bucket.date.weekday().num_days_from_sunday())
+            }
+            Period::Week => true,
+        }
+    }
+
+    fn counts(&self, te: &TimeEntry) -> bool {
+        te.server_deleted_at.is_none()
+            && self
+                .project_id
+                .is_none_or(|project_id| te.project_id == Some(project_id))
+    }
+}
+
+impl Goals {
+    pub fn goals(&self, user_id: Id) -> Vec<Goal> {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Replaces all the goals of the user.
+    pub fn set_goals(&self, user_id: Id, goals: Vec<Goal>) -> Result<Vec<Goal>, Error> {
+        validate(&goals)?;
+
+        let mut users = self.users.lock().unwrap();
+        if goals.is_empty() {
+            users.remove(&user_id);
+        } else {
+            users.insert(user_id, goals.clone());
+        }
+
+        Ok(goals)
+    }
+
+    /// Attaches the progress of the goals of the user to the outcome of the sync. The time
+    /// entries of the current week are fetched only once a week and then updated with the
+    /// changes from the client which Toggl accepted and with the changes which are sent back
+    /// to the client. This is best effort, the outcome is returned without the progress when
+    /// the week cannot be fetched.
+    pub fn with_progress(
+        &self,
+        user_id: Id,
+        client_delta: &Delta,
+        outcome: SyncOutcome,
+        api: &TogglApi,
+    ) -> SyncOutcome {
+        let goals = self.goals(user_id);
+        if goals.is_empty() {
+            self.weeks.lock().unwrap().remove(&user_id);
+            return outcome;
+        }
+
+        // A different timezone or beginning of the week moves the boundaries of the week
+        let now = Utc::now();
+        let is_current = self
+            .weeks
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .is_some_and(|week| {
+                outcome.user.is_none() && week.date == week.calendar.bucket_of(now, Period::Week)
+            });
+        if !is_current {
+            match fetch_week(now, api) {
+                Ok(week) => self.weeks.lock().unwrap().insert(user_id, week),
+                Err(_) => return outcome,
+            };
+        }
+
+        let mut weeks = self.weeks.lock().unwrap();
+        let week = match weeks.get_mut(&user_id) {
+            Some(week) => week,
+            None => return outcome,
+        };
+        week.update(
+            outcome
+                .accepted_from(client_delta)
+                .time_entries
+                .iter()
+                .flatten(),
+        );
+        week.update(outcome.time_entries.iter().filter_map(SyncResult::entity));
+
+        let time_entries: Vec<TimeEntry> = week.time_entries.values().cloned().collect();
+        SyncOutcome {
+            goals: progress(&goals, &time_entries, &week.calendar, now),
+            ..outcome
+        }
+    }
+}
+
+impl Week {
+    fn update<'a>(&mut self, time_entries: impl Iterator<Item = &'a TimeEntry>) {
+        for te in time_entries {
+            if te.is_deleted() {
+                self.time_entries.remove(&te.id);
+            } else if te.exists_on_server() {
+                self.time_entries.insert(te.id, te.clone());
+            }
+        }
+    }
+}
+
+fn fetch_week(now: DateTime<Utc>, api: &TogglApi) -> Result<Week, Error> {
+    let calendar = Calendar::of_user(&server::fetch_user(api)?)?;
+    let date = calendar.bucket_of(now, Period::Week);
+    let time_entries = server::fetch_time_entries_reaching_into(calendar.start_of(date), now, api)?
+        .into_iter()
+        .map(|te| (te.id, te))
+        .collect();
+
+    Ok(Week {
+        calendar,
+        date,
+        time_entries,
+    })
+}
+
+/// Calculates the progress of the goals in the current day or week of the user.
+pub fn fetch_progress(goals: &[Goal], api: &TogglApi) -> Result<Vec<Progress>, Error> {
+    if goals.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let now = Utc::now();
+    let week = fetch_week(now, api)?;
+    let time_entries: Vec<TimeEntry> = week.time_entries.into_values().collect();
+
+    Ok(progress(goals, &time_entries, &week.calendar, now))
+}
+
+/// Sums up the time tracked towards each of the goals in the day or week which contains
+/// `now`. Running time entries are counted until `now`. The goals which don't apply
+/// on the current day are left out.
+pub fn progress<Tz: TimeZone>(
+    goals: &[Goal],
+    time_entries: &[TimeEntry],
+    calendar: &Calendar<Tz>,
+    now: DateTime<Utc>,
+) -> Vec<Progress> {
+    goals
+        .iter()
+        .filter_map(|goal| {
+            let date = calendar.bucket_of(now, goal.period);
+            let bucket = Bucket {
+                date,
+                start: calendar.start_of(date),
+                end: calendar.next_bucket_start(date, goal.period),
+            };
+            if !goal.applies_on(&bucket) {
+                return None;
+            }
+
+            let tracked_seconds = time_entries
+                .iter()
+                .filter(|te| goal.counts(te))
+                .map(|te| seconds_within(te, &bucket, now))
+                .sum();
+            let target_seconds = goal.minutes * 60;
+
+            Some(Progress {
+                goal: goal.clone(),
+                bucket,
+                tracked_seconds,
+                target_seconds,
+                completed: tracked_seconds >= target_seconds,
+            })
+        })
+        .collect()
+}
+
+fn seconds_within(te: &TimeEntry, bucket: &Bucket, now: DateTime<Utc>) -> u64 {
+    let seconds = te.duration.unwrap_or_else(|| te.elapsed_seconds_until(now));
+    let end = te.start + Duration::seconds(seconds as i64);
+    let start = std::cmp::max(te.start, bucket.start);
+    let end = std::cmp::min(end, bucket.end);
+
+    std::cmp::max(end.signed_duration_since(start).num_seconds(), 0) as u64
+}
+
+fn validate(goals: &[Goal]) -> Result<(), Error> {
+    let mut errors = vec![];
+    for (i, goal) in goals.iter().enumerate() {
+        let max_minutes = match goal.period {
+            Period::Day => 24 * 60,
+            Period::Week => 7 * 24 * 60,
+        };
+        if goal.minutes == 0 || goal.minutes > max_minutes {
+            errors.push(ValidationError {
+                field: Some(format!("{}.minutes", i)),
+                reason: "The target must be longer than zero and fit into the period.".to_string(),
+            });
+        }
+        if goal.weekdays.iter().any(|day| *day > 6) {
+            errors.push(ValidationError {
+                field: Some(format!("{}.weekdays", i)),
+                reason: "The days of the week must be between 0 (Sunday) and 6.".to_string(),
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{progress, Goal, Goals, Week};
+    use crate::calendar::{Calendar, Period};
+    use crate::models::{fixtures, TimeEntry};
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::collections::HashMap;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.ymd(2019, 12, day).and_hms(hour, 0, 0)
+    }
+
+    fn time_entry(project_id: Option<Id>, start: DateTime<Utc>, hours: Option<u64>) -> TimeEntry {
+        TimeEntry {
+            project_id,
+            ..fixtures::time_entry(1, start, hours.map(|hours| hours * 3600))
+        }
+    }
+
+    fn goal(period: Period, minutes: u64, project_id: Option<Id>, weekdays: Vec<u32>) -> Goal {
+        Goal {
+            name: "Goal".to_string(),
+            period,
+            minutes,
+            project_id,
+            weekdays,
+        }
+    }
+
+    #[test]
+    fn counts_time_in_the_current_day_and_week_including_the_running_entry() {
+        let calendar = Calendar {
+            timezone: Utc,
+            beginning_of_week: 1,
+        };
+        let time_entries = vec![
+            // Monday
+            time_entry(Some(2), at(9, 8), Some(3)),
+            // Tuesday, reaches over the midnight
+            time_entry(None, at(9, 22), Some(4)),
+            // Running since the morning
+            time_entry(Some(2), at(10, 9), None),
+        ];
+        let goals = vec![
+            goal(Period::Day, 8 * 60, None, vec![1, 2, 3, 4, 5]),
+            goal(Period::Week, 4 * 60, Some(2), vec![]),
+            goal(Period::Day, 60, None, vec![0, 6]),
+        ];
+
+        let progress = progress(&goals, &time_entries, &calendar, at(10, 12));
+
+        assert_eq!(progress.len(), 2);
+        assert_eq!(progress[0].bucket.start, at(10, 0));
+        assert_eq!(progress[0].tracked_seconds, 5 * 3600);
+        assert!(!progress[0].completed);
+        assert_eq!(progress[1].bucket.start, at(9, 0));
+        assert_eq!(progress[1].tracked_seconds, 6 * 3600);
+        assert!(progress[1].completed);
+    }
+
+    #[test]
+    fn rejects_targets_which_do_not_fit_into_the_period() {
+        let goals = Goals::default();
+
+        assert!(goals
+            .set_goals(1, vec![goal(Period::Day, 25 * 60, None, vec![])])
+            .is_err());
+        assert!(goals
+            .set_goals(1, vec![goal(Period::Day, 60, None, vec![7])])
+            .is_err());
+        assert!(goals
+            .set_goals(1, vec![goal(Period::Week, 25 * 60, None, vec![])])
+            .is_ok());
+        assert_eq!(goals.goals(1).len(), 1);
+    }
+
+    #[test]
+    fn keeps_the_week_up_to_date_with_the_synced_changes() {
+        let mut week = Week {
+            calendar: Calendar {
+                timezone: chrono_tz::Tz::UTC,
+                beginning_of_week: 1,
+            },
+            date: at(9, 0).naive_utc().date(),
+            time_entries: HashMap::new(),
+        };
+        let stopped = time_entry(None, at(9, 8), Some(3));
+        week.update(std::iter::once(&stopped));
+
+        let changes = [
+            // created on the client, the outcome brings it with the id from Toggl
+            TimeEntry {
+                id: -1,
+                ..time_entry(None, at(10, 8), Some(1))
+            },
+            TimeEntry {
+                server_deleted_at: Some(at(10, 9)),
+                ..stopped
+            },
+        ];
+        week.update(changes.iter());
+
+        assert!(week.time_entries.is_empty());
+    }
+}
@@ -0,0 +1,405 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::error::{Error, ValidationError};
+use crate::models::{Delta, Entity, Project, TimeEntry};
+use crate::sync::prelude::{SyncOutcome, SyncResult};
+use crate::sync::{server, validation};
+use crate::toggl_api::{models::Id, TogglApi};
+
+/// Only this many of the latest versions of each entity are kept.
+const MAX_REVISIONS: usize = 50;
+
+/// Where a version of an entity came from.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// The version was found on Toggl, it might have been made by any other Toggl client.
+    Toggl,
+    /// The version was pushed to Toggl by one of the devices of the user through the proxy.
+    Device,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Revision<T> {
+    pub revision: u64,
+    pub source: Source,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    pub recorded_at: DateTime<Utc>,
+    pub entity: T,
+}
+
+type Revisions<T> = HashMap<Id, Vec<Revision<T>>>;
+
+#[derive(Default)]
+struct UserHistory {
+    projects: Revisions<Project>,
+    time_entries: Revisions<TimeEntry>,
+}
+
+/// Every version of the projects and time entries which the proxy has seen, so the versions
+/// overwritten during the conflict resolution aren't lost.
+#[derive(Default)]
+pub struct History {
+    users: Mutex<HashMap<Id, UserHistory>>,
+}
+
+/// Records the versions on behalf of one user and one of their devices.
+pub struct Recorder<'a> {
+    history: &'a History,
+    user_id: Id,
+    device: Option<String>,
+}
+
+impl History {
+    pub fn recorder(&self, user_id: Id, device: Option<String>) -> Recorder<'_> {
+        Recorder {
+            history: self,
+            user_id,
+            device,
+        }
+    }
+
+    pub fn time_entry(&self, user_id: Id, id: Id) -> Vec<Revision<TimeEntry>> {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .and_then(|history| history.time_entries.get(&id).cloned())
+            .unwrap_or_default()
+    }
+
+    pub fn project(&self, user_id: Id, id: Id) -> Vec<Revision<Project>> {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .and_then(|history| history.projects.get(&id).cloned())
+            .unwrap_or_default()
+    }
+
+    fn record(&self, user_id: Id, delta: Delta, source: Source, device: &Option<String>) {
+        let now = Utc::now();
+        let mut users = self.users.lock().unwrap();
+        let history = users.entry(user_id).or_default();
+
+        for project in delta.projects.unwrap_or_default() {
+            record(&mut history.projects, project, source, device, now);
+        }
+        for te in delta.time_entries.unwrap_or_default() {
+            record(&mut history.time_entries, te, source, device, now);
+        }
+    }
+}
+
+impl Recorder<'_> {
+    /// Records the versions which were found on Toggl.
+    pub fn observed(&self, delta: &Delta) {
+        self.history
+            .record(self.user_id, delta.clone(), Source::Toggl, &None);
+    }
+
+    /// Records the versions from the device which weren't pushed to Toggl because they lost
+    /// to newer versions during the conflict resolution.
+    pub fn overwritten(&self, client_delta: &Delta, server_resolution: &Delta) {
+        fn lost<T: Entity>(client: &Option<Vec<T>>, pushed: &Option<Vec<T>>) -> Vec<T> {
+            client
+                .iter()
+                .flatten()
+                .filter(|entity| {
+                    !pushed
+                        .iter()
+                        .flatten()
+                        .any(|pushed| pushed.id() == entity.id())
+                })
+                .cloned()
+                .collect()
+        }
+
+        let delta = Delta {
+            user: None,
+            projects: Some(lost(&client_delta.projects, &server_resolution.projects)),
+            time_entries: Some(lost(
+                &client_delta.time_entries,
+                &server_resolution.time_entries,
+            )),
+            favourites: None,
+        };
+
+        self.history
+            .record(self.user_id, delta, Source::Device, &self.device);
+    }
+
+    /// Records the versions which Toggl accepted from the device.
+    pub fn pushed(&self, outcome: &SyncOutcome) {
+        let delta = Delta {
+            user: None,
+            projects: Some(accepted(&outcome.projects)),
+            time_entries: Some(accepted(&outcome.time_entries)),
+            favourites: None,
+        };
+
+        self.history
+            .record(self.user_id, delta, Source::Device, &self.device);
+    }
+}
+
+/// Pushes an older version of the time entry to Toggl as a new edit.
+pub fn restore_time_entry(
+    id: Id,
+    revision: u64,
+    recorder: &Recorder,
+    api: &TogglApi,
+) -> Result<SyncOutcome, Error> {
+    let old = find(recorder.history.time_entry(recorder.user_id, id), revision)?;
+    let current = server::fetch_time_entry(id, api)?;
+    check_not_deleted(&current)?;
+
+    // A stopped time entry cannot be started again by restoring one of its running versions.
+    let duration = match (old.duration, current.duration) {
+        (None, Some(duration)) => Some(duration),
+        (old, _) => old,
+    };
+
+    let restored = TimeEntry {
+        duration,
+        at: Utc::now(),
+        server_deleted_at: None,
+        ..old
+    };
+    let known_projects = match restored.project_id {
+        Some(_) => server::fetch_all_projects(api)?,
+        None => vec![],
+    };
+
+    push(
+        Delta {
+            user: None,
+            projects: None,
+            time_entries: Some(vec![restored]),
+            favourites: None,
+        },
+        &known_projects,
+        recorder,
+        api,
+    )
+}
+
+/// Pushes an older version of the project to Toggl as a new edit.
+pub fn restore_project(
+    id: Id,
+    revision: u64,
+    recorder: &Recorder,
+    api: &TogglApi,
+) -> Result<SyncOutcome, Error> {
+    let old = find(recorder.history.project(recorder.user_id, id), revision)?;
+    let known_projects = server::fetch_all_projects(api)?;
+    let current = known_projects
+        .iter()
+        .find(|project| project.id == id)
+        .ok_or_else(|| Error::NotFound(format!("project {}", id)))?;
+    check_not_deleted(current)?;
+
+    let restored = Project {
+        at: Utc::now(),
+        server_deleted_at: None,
+        ..old
+    };
+
+    push(
+        Delta {
+            user: None,
+            projects: Some(vec![restored]),
+            time_entries: None,
+            favourites: None,
+        },
+        &known_projects,
+        recorder,
+        api,
+    )
+}
+
+fn push(
+    delta: Delta,
+    known_projects: &[Project],
+    recorder: &Recorder,
+    api: &TogglApi,
+) -> Result<SyncOutcome, Error> {
+    let (delta, rejected) = validation::validate(delta, known_projects, Utc::now());
+    let outcome = SyncOutcome::merge(server::apply_changes(delta, api), rejected);
+    recorder.pushed(&outcome);
+
+    Ok(outcome)
+}
+
+fn record<T: Entity>(
+    revisions: &mut Revisions<T>,
+    entity: T,
+    source: Source,
+    device: &Option<String>,
+    now: DateTime<Utc>,
+) {
+    if !entity.exists_on_server() {
+        return;
+    }
+
+    let versions = revisions.entry(entity.id()).or_default();
+    let last = versions.last();
+    if last.map(|last| &last.entity) == Some(&entity) {
+        return;
+    }
+
+    let revision = last.map_or(1, |last| last.revision + 1);
+    versions.push(Revision {
+        revision,
+        source,
+        device: match source {
+            Source::Device => device.clone(),
+            Source::Toggl => None,
+        },
+        recorded_at: now,
+        entity,
+    });
+
+    if versions.len() > MAX_REVISIONS {
+        let excess = versions.len() - MAX_REVISIONS;
+        versions.drain(..excess);
+    }
+}
+
+fn accepted<T: Entity>(results: &[SyncResult<T>]) -> Vec<T> {
+    results
+        .iter()
+        .filter_map(SyncResult::entity)
+        .cloned()
+        .collect()
+}
+
+fn find<T>(revisions: Vec<Revision<T>>, revision: u64) -> Result<T, Error> {
+    revisions
+        .into_iter()
+        .find(|candidate| candidate.revision == revision)
+        .map(|found| found.entity)
+        .ok_or_else(|| Error::NotFound(format!("revision {}", revision)))
+}
+
+fn check_not_deleted<T: Entity>(current: &T) -> Result<(), Error> {
+    if current.is_deleted() {
+        Err(Error::Validation(vec![ValidationError {
+            field: None,
+            reason: "A deleted entity cannot be restored.".to_string(),
+        }]))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{History, Source, MAX_REVISIONS};
+    use crate::error::Error;
+    use crate::models::{fixtures, Delta, TimeEntry};
+    use crate::sync::prelude::{changed, failed, SyncOutcome};
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn time_entry(description: &str) -> TimeEntry {
+        let start = Utc.ymd(2019, 12, 10).and_hms(9, 0, 0);
+        TimeEntry {
+            description: description.to_string(),
+            ..fixtures::time_entry(1, start, Some(3600))
+        }
+    }
+
+    fn observed(te: TimeEntry) -> Delta {
+        Delta {
+            user: None,
+            projects: None,
+            time_entries: Some(vec![te]),
+            favourites: None,
+        }
+    }
+
+    fn outcome(te: TimeEntry) -> SyncOutcome {
+        SyncOutcome {
+            user: None,
+            projects: vec![],
+            time_entries: vec![changed(te), failed(2, Error::Timeout)],
+            favourites: vec![],
+            warnings: vec![],
+            goals: vec![],
+        }
+    }
+
+    #[test]
+    fn records_versions_from_toggl_and_devices() {
+        let history = History::default();
+        let recorder = history.recorder(7, Some("Pixel".to_string()));
+
+        recorder.observed(&observed(time_entry("Work")));
+        recorder.observed(&observed(time_entry("Work")));
+        recorder.pushed(&outcome(time_entry("Meeting")));
+
+        let revisions = history.time_entry(7, 1);
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].revision, 1);
+        assert_eq!(revisions[0].source, Source::Toggl);
+        assert_eq!(revisions[0].device, None);
+        assert_eq!(revisions[1].revision, 2);
+        assert_eq!(revisions[1].source, Source::Device);
+        assert_eq!(revisions[1].device, Some("Pixel".to_string()));
+        assert_eq!(revisions[1].entity.description, "Meeting");
+        assert!(history.time_entry(7, 2).is_empty());
+        assert!(history.time_entry(8, 1).is_empty());
+    }
+
+    #[test]
+    fn records_the_versions_which_lost_the_conflict_resolution() {
+        let history = History::default();
+        let recorder = history.recorder(7, Some("Pixel".to_string()));
+
+        let lost = TimeEntry {
+            at: Utc.ymd(2019, 12, 10).and_hms(9, 30, 0),
+            ..time_entry("Meeting")
+        };
+        let pushed = TimeEntry {
+            id: 2,
+            ..time_entry("Review")
+        };
+        let client_delta = Delta {
+            time_entries: Some(vec![lost, pushed.clone()]),
+            ..Delta::default()
+        };
+        recorder.overwritten(&client_delta, &observed(pushed));
+        recorder.observed(&observed(TimeEntry {
+            at: Utc.ymd(2019, 12, 10).and_hms(10, 0, 0),
+            ..time_entry("Work")
+        }));
+
+        let revisions = history.time_entry(7, 1);
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].source, Source::Device);
+        assert_eq!(revisions[0].entity.description, "Meeting");
+        assert_eq!(revisions[1].source, Source::Toggl);
+        assert!(history.time_entry(7, 2).is_empty());
+    }
+
+    #[test]
+    fn keeps_only_the_latest_revisions() {
+        let history = History::default();
+        let recorder = history.recorder(7, None);
+
+        for i in 0..(MAX_REVISIONS as i64 + 5) {
+            recorder.observed(&observed(TimeEntry {
+                at: Utc::now() + Duration::seconds(i),
+                ..time_entry("Work")
+            }));
+        }
+
+        let revisions = history.time_entry(7, 1);
+        assert_eq!(revisions.len(), MAX_REVISIONS);
+        assert_eq!(revisions[0].revision, 6);
+    }
+}
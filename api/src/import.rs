@@ -0,0 +1,418 @@
+mod csv;
+mod ics;
+
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::error::{Error, ValidationError};
+use crate::history::Recorder;
+use crate::models::{Delta, Project, TimeEntry};
+use crate::sync::prelude::{SyncOutcome, SyncResult};
+use crate::sync::{server, validation};
+use crate::toggl_api::{models::Id, TogglApi};
+
+/// The color of the projects which are created during the import.
+const DEFAULT_PROJECT_COLOR: &str = "#06aaf5";
+
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Csv,
+    Ics,
+}
+
+/// A time entry as it was read from the imported file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImportedTimeEntry {
+    pub start: DateTime<Utc>,
+    pub duration: u64,
+    pub description: String,
+    pub project: Option<String>,
+}
+
+/// A row of the imported file, numbered from 1. For iCalendar files every event is a row.
+#[derive(Debug)]
+pub struct ParsedRow {
+    pub row: usize,
+    pub result: Result<ImportedTimeEntry, String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RowResult {
+    Created {
+        row: usize,
+        entity: TimeEntry,
+    },
+    SkippedDuplicate {
+        row: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        existing_id: Option<Id>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duplicate_of_row: Option<usize>,
+    },
+    Failed {
+        row: usize,
+        code: &'static str,
+        message: String,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        errors: Vec<ValidationError>,
+    },
+}
+
+impl RowResult {
+    fn row(&self) -> usize {
+        match self {
+            RowResult::Created { row, .. }
+            | RowResult::SkippedDuplicate { row, .. }
+            | RowResult::Failed { row, .. } => *row,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportReport {
+    pub rows: Vec<RowResult>,
+    pub projects: Vec<SyncResult<Project>>,
+}
+
+/// The changes which should be pushed to Toggl and the rows they come from.
+struct Plan {
+    delta: Delta,
+    rows: HashMap<Id, usize>,
+    skipped: Vec<RowResult>,
+}
+
+/// Imports the time entries from a CSV or iCalendar file into the given workspace (or the
+/// default workspace of the user). The projects are matched by their names and the missing
+/// ones are created. Time entries which already exist in Toggl are skipped.
+pub fn import(
+    text: &str,
+    format: Format,
+    workspace_id: Option<Id>,
+    timezone: &Tz,
+    recorder: &Recorder,
+    api: &TogglApi,
+) -> Result<ImportReport, Error> {
+    let parsed = match format {
+        Format::Csv => csv::parse(text, timezone),
+        Format::Ics => ics::parse(text, timezone),
+    }
+    .map_err(|reason| {
+        Error::Validation(vec![ValidationError {
+            field: Some("file".to_string()),
+            reason,
+        }])
+    })?;
+
+    let workspace_id = match workspace_id {
+        Some(workspace_id) => workspace_id,
+        None => server::fetch_user(api)?.default_workspace_id,
+    };
+
+    let starts: Vec<_> = parsed
+        .iter()
+        .filter_map(|row| row.result.as_ref().ok())
+        .map(|te| te.start)
+        .collect();
+    let existing_time_entries = match (starts.iter().min(), starts.iter().max()) {
+        (Some(from), Some(to)) => {
+            server::fetch_time_entries_between(*from, *to + Duration::seconds(1), api)?
+        }
+        _ => vec![],
+    };
+    let existing_projects = server::fetch_all_projects(api)?;
+
+    let now = Utc::now();
+    let plan = plan(
+        parsed,
+        workspace_id,
+        &existing_projects,
+        &existing_time_entries,
+        now,
+    );
+
+    let (delta, rejected) = validation::validate(plan.delta, &existing_projects, now);
+    let outcome = SyncOutcome::merge(server::apply_changes(delta, api), rejected);
+    recorder.pushed(&outcome);
+
+    Ok(report(outcome, &plan.rows, plan.skipped))
+}
+
+fn plan(
+    parsed: Vec<ParsedRow>,
+    workspace_id: Id,
+    existing_projects: &[Project],
+    existing_time_entries: &[TimeEntry],
+    now: DateTime<Utc>,
+) -> Plan {
+    let mut next_id = -1;
+    let mut new_id = || {
+        let id = next_id;
+        next_id -= 1;
+        id
+    };
+
+    let mut project_ids: HashMap<String, Id> = existing_projects
+        .iter()
+        .filter(|p| p.server_deleted_at.is_none() && p.workspace_id == workspace_id)
+        .map(|project| (project.name.trim().to_lowercase(), project.id))
+        .collect();
+
+    // the existing time entries and the rows which were already imported
+    let mut known: HashMap<DuplicateKey, (Option<Id>, Option<usize>)> = existing_time_entries
+        .iter()
+        .filter(|te| te.server_deleted_at.is_none())
+        .filter_map(|te| {
+            te.duration.map(|duration| {
+                (
+                    duplicate_key(te.start, duration, &te.description),
+                    (Some(te.id), None),
+                )
+            })
+        })
+        .collect();
+
+    let mut projects = vec![];
+    let mut time_entries = vec![];
+    let mut rows = HashMap::new();
+    let mut skipped = vec![];
+
+    for ParsedRow { row, result } in parsed {
+        let imported = match result {
+            Ok(imported) => imported,
+            Err(reason) => {
+                skipped.push(failed_row(
+                    row,
+                    Error::Validation(vec![ValidationError {
+                        field: None,
+                        reason,
+                    }]),
+                ));
+                continue;
+            }
+        };
+
+        let key = duplicate_key(imported.start, imported.duration, &imported.description);
+        if let Some((existing_id, duplicate_of_row)) = known.get(&key) {
+            skipped.push(RowResult::SkippedDuplicate {
+                row,
+                existing_id: *existing_id,
+                duplicate_of_row: *duplicate_of_row,
+            });
+            continue;
+        }
+        known.insert(key, (None, Some(row)));
+
+        let project_id = imported.project.map(|name| {
+            *project_ids
+                .entry(name.trim().to_lowercase())
+                .or_insert_with(|| {
+                    let id = new_id();
+                    projects.push(Project {
+                        id,
+                        workspace_id,
+                        name: name.trim().to_string(),
+                        color: DEFAULT_PROJECT_COLOR.to_string(),
+                        active: true,
+                        billable: false,
+                        rate: None,
+                        currency: None,
+                        stats: None,
+                        at: now,
+                        server_deleted_at: None,
+                    });
+                    id
+                })
+        });
+
+        let id = new_id();
+        rows.insert(id, row);
+        time_entries.push(TimeEntry {
+            id,
+            workspace_id,
+            description: imported.description,
+            project_id,
+            start: imported.start,
+            duration: Some(imported.duration),
+            tags: vec![],
+            billable: false,
+            earnings: None,
+            recurrence: None,
+            at: now,
+            server_deleted_at: None,
+        });
+    }
+
+    Plan {
+        delta: Delta {
+            user: None,
+            projects: Some(projects),
+            time_entries: Some(time_entries),
+            favourites: None,
+        },
+        rows,
+        skipped,
+    }
+}
+
+/// Time entries with the same start, duration and description are considered duplicates.
+type DuplicateKey = (i64, u64, String);
+
+fn duplicate_key(start: DateTime<Utc>, duration: u64, description: &str) -> DuplicateKey {
+    (
+        start.timestamp(),
+        duration,
+        description.trim().to_lowercase(),
+    )
+}
+
+fn failed_row(row: usize, err: Error) -> RowResult {
+    RowResult::Failed {
+        row,
+        code: err.code(),
+        message: err.to_string(),
+        errors: err.validation_errors(),
+    }
+}
+
+fn report(
+    outcome: SyncOutcome,
+    rows: &HashMap<Id, usize>,
+    skipped: Vec<RowResult>,
+) -> ImportReport {
+    let mut results: Vec<_> = outcome
+        .time_entries
+        .into_iter()
+        .filter_map(|result| match result {
+            SyncResult::Created {
+                client_assigned_id,
+                entity,
+            } => rows
+                .get(&client_assigned_id)
+                .map(|row| RowResult::Created { row: *row, entity }),
+            SyncResult::Failed {
+                entity_id,
+                code,
+                message,
+                errors,
+            } => rows.get(&entity_id).map(|row| RowResult::Failed {
+                row: *row,
+                code,
+                message,
+                errors,
+            }),
+            SyncResult::Changed { .. }
+            | SyncResult::Deleted { .. }
+            | SyncResult::AutoStopped { .. } => None,
+        })
+        .chain(skipped)
+        .collect();
+    results.sort_by_key(RowResult::row);
+
+    ImportReport {
+        rows: results,
+        projects: outcome.projects,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan, ImportedTimeEntry, ParsedRow};
+    use crate::models::{fixtures, Project, TimeEntry};
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2019, 12, 10).and_hms(12, 0, 0)
+    }
+
+    fn row(row: usize, project: Option<&str>, hour: u32) -> ParsedRow {
+        ParsedRow {
+            row,
+            result: Ok(ImportedTimeEntry {
+                start: Utc.ymd(2019, 12, 10).and_hms(hour, 0, 0),
+                duration: 3600,
+                description: "Work".to_string(),
+                project: project.map(|p| p.to_string()),
+            }),
+        }
+    }
+
+    fn project(id: Id, workspace_id: Id, name: &str) -> Project {
+        Project {
+            workspace_id,
+            at: now(),
+            ..fixtures::project(id, name)
+        }
+    }
+
+    #[test]
+    fn matches_existing_projects_and_creates_missing_ones() {
+        let existing = vec![
+            project(10, 2, "Utopia"),
+            project(11, 1, "utopia"),
+            project(12, 2, "Elsewhere"),
+        ];
+        let rows = vec![
+            row(1, Some("UTOPIA "), 8),
+            row(2, Some("Dystopia"), 9),
+            row(3, Some("dystopia"), 10),
+            row(4, Some("Elsewhere"), 11),
+        ];
+
+        let plan = plan(rows, 1, &existing, &[], now());
+        let projects = plan.delta.projects.unwrap();
+        let time_entries = plan.delta.time_entries.unwrap();
+
+        assert_eq!(projects.len(), 2);
+        assert_eq!(projects[0].name, "Dystopia");
+        assert!(projects[0].id < 0);
+        assert_eq!(time_entries[0].project_id, Some(11));
+        assert_eq!(time_entries[1].project_id, Some(projects[0].id));
+        assert_eq!(time_entries[2].project_id, Some(projects[0].id));
+        assert!(time_entries.iter().all(|te| te.id < 0));
+        assert_eq!(plan.rows[&time_entries[2].id], 3);
+
+        // a project of the same name in another workspace isn't used
+        assert_eq!(projects[1].name, "Elsewhere");
+        assert_eq!(projects[1].workspace_id, 1);
+        assert_eq!(time_entries[3].project_id, Some(projects[1].id));
+    }
+
+    #[test]
+    fn skips_duplicates() {
+        let existing = TimeEntry {
+            description: "work".to_string(),
+            at: now(),
+            ..fixtures::time_entry(42, Utc.ymd(2019, 12, 10).and_hms(8, 0, 0), Some(3600))
+        };
+        let rows = vec![row(1, None, 8), row(2, None, 9), row(3, None, 9)];
+
+        let plan = plan(rows, 1, &[], &[existing], now());
+
+        assert_eq!(plan.delta.time_entries.unwrap().len(), 1);
+        assert_eq!(plan.skipped.len(), 2);
+        let json = serde_json::to_value(&plan.skipped).unwrap();
+        assert_eq!(json[0]["status"], "skipped_duplicate");
+        assert_eq!(json[0]["existing_id"], 42);
+        assert_eq!(json[1]["duplicate_of_row"], 2);
+    }
+
+    #[test]
+    fn reports_unparseable_rows() {
+        let rows = vec![ParsedRow {
+            row: 1,
+            result: Err("The start is missing.".to_string()),
+        }];
+
+        let plan = plan(rows, 1, &[], &[], now());
+        let json = serde_json::to_value(&plan.skipped).unwrap();
+
+        assert_eq!(json[0]["status"], "failed");
+        assert_eq!(json[0]["code"], "validation_failed");
+        assert_eq!(json[0]["errors"][0]["reason"], "The start is missing.");
+    }
+}
@@ -0,0 +1,234 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use super::{ImportedTimeEntry, ParsedRow};
+
+const DATE_TIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+/// Parses a CSV file with a header row. The columns are recognized by their names, so both
+/// the exports of this API (`start`, `stop`, `duration`, ...) and the exports of Toggl and
+/// similar trackers (`Start date`, `Start time`, `End date`, `End time`, ...) are accepted.
+/// Times without an offset are interpreted in the given timezone.
+pub fn parse(text: &str, timezone: &Tz) -> Result<Vec<ParsedRow>, String> {
+    let mut records = records(text).into_iter();
+    let header: Vec<String> = match records.next() {
+        Some(header) => header.iter().map(|name| normalize(name)).collect(),
+        None => return Err("The file is empty.".to_string()),
+    };
+
+    if !header
+        .iter()
+        .any(|name| name == "start" || name == "start_date")
+    {
+        return Err("The file doesn't have a start column.".to_string());
+    }
+
+    Ok(records
+        .enumerate()
+        .filter(|(_, record)| record.iter().any(|value| !value.trim().is_empty()))
+        .map(|(index, record)| ParsedRow {
+            row: index + 1,
+            result: parse_record(&header, &record, timezone),
+        })
+        .collect())
+}
+
+fn normalize(name: &str) -> String {
+    name.trim()
+        .trim_start_matches('\u{feff}')
+        .to_lowercase()
+        .replace(' ', "_")
+}
+
+fn parse_record(
+    header: &[String],
+    record: &[String],
+    timezone: &Tz,
+) -> Result<ImportedTimeEntry, String> {
+    let value = |name: &str| {
+        header
+            .iter()
+            .position(|column| column == name)
+            .and_then(|index| record.get(index))
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+    let date_time = |date: &str, time: &str| match (value(date), value(time)) {
+        (Some(date), Some(time)) => Some(format!("{} {}", date, time)),
+        (Some(date), None) => Some(date.to_string()),
+        _ => None,
+    };
+
+    let start = value("start")
+        .map(|start| start.to_string())
+        .or_else(|| date_time("start_date", "start_time"))
+        .ok_or_else(|| "The start is missing.".to_string())
+        .and_then(|start| parse_date_time(&start, timezone))?;
+
+    let stop = value("stop")
+        .or_else(|| value("end"))
+        .map(|stop| stop.to_string())
+        .or_else(|| date_time("end_date", "end_time"));
+
+    let duration = match (stop, value("duration")) {
+        (Some(stop), _) => {
+            let stop = parse_date_time(&stop, timezone)?;
+            if stop < start {
+                return Err("The time entry ends before it starts.".to_string());
+            }
+            stop.signed_duration_since(start).num_seconds() as u64
+        }
+        (None, Some(duration)) => parse_duration(duration)?,
+        (None, None) => return Err("Either the end or the duration must be given.".to_string()),
+    };
+
+    Ok(ImportedTimeEntry {
+        start,
+        duration,
+        description: value("description").unwrap_or_default().to_string(),
+        project: value("project").map(|project| project.to_string()),
+    })
+}
+
+fn parse_date_time(value: &str, timezone: &Tz) -> Result<DateTime<Utc>, String> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date_time.with_timezone(&Utc));
+    }
+
+    DATE_TIME_FORMATS
+        .iter()
+        .filter_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .filter_map(|local| timezone.from_local_datetime(&local).earliest())
+        .map(|date_time| date_time.with_timezone(&Utc))
+        .next()
+        .ok_or_else(|| format!("'{}' is not a valid date and time.", value))
+}
+
+/// Parses durations given either in seconds or as `hh:mm:ss` or `hh:mm`.
+fn parse_duration(value: &str) -> Result<u64, String> {
+    let invalid = || format!("'{}' is not a valid duration.", value);
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Ok(seconds);
+    }
+
+    let parts = value
+        .split(':')
+        .map(|part| part.parse::<u64>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (hours, minutes, seconds) = match parts[..] {
+        [hours, minutes, seconds] => (hours, minutes, seconds),
+        [hours, minutes] => (hours, minutes, 0),
+        _ => return Err(invalid()),
+    };
+
+    hours
+        .checked_mul(3600)
+        .and_then(|total| total.checked_add(minutes.checked_mul(60)?))
+        .and_then(|total| total.checked_add(seconds))
+        .ok_or_else(invalid)
+}
+
+/// Splits the text into records and fields according to RFC 4180. Quoted fields can contain
+/// commas, line breaks and escaped quotes.
+fn records(text: &str) -> Vec<Vec<String>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, parse_duration, records};
+    use chrono::{TimeZone, Utc};
+    use chrono_tz::{Europe::Prague, Tz};
+
+    #[test]
+    fn splits_quoted_fields() {
+        let text = "a,\"b, \"\"c\"\"\",\"multi\nline\"\r\n1,2,3";
+        assert_eq!(
+            records(text),
+            vec![vec!["a", "b, \"c\"", "multi\nline"], vec!["1", "2", "3"]]
+        );
+    }
+
+    #[test]
+    fn parses_own_export() {
+        let text = "start,stop,duration,project,description\r\n\
+                    2019-12-10T09:00:00Z,2019-12-10T10:00:00Z,3600,Utopia,Work\r\n";
+        let rows = parse(text, &Tz::UTC).unwrap();
+        let te = rows[0].result.as_ref().unwrap();
+
+        assert_eq!(rows[0].row, 1);
+        assert_eq!(te.start, Utc.ymd(2019, 12, 10).and_hms(9, 0, 0));
+        assert_eq!(te.duration, 3600);
+        assert_eq!(te.project, Some("Utopia".to_string()));
+    }
+
+    #[test]
+    fn parses_toggl_export_in_local_time() {
+        let text = "Project,Description,Start date,Start time,End date,End time,Duration\n\
+                    ,Work,2019-12-10,09:00:00,2019-12-10,09:30:00,00:30:00\n";
+        let rows = parse(text, &Prague).unwrap();
+        let te = rows[0].result.as_ref().unwrap();
+
+        assert_eq!(te.start, Utc.ymd(2019, 12, 10).and_hms(8, 0, 0));
+        assert_eq!(te.duration, 1800);
+        assert_eq!(te.project, None);
+    }
+
+    #[test]
+    fn reports_invalid_rows() {
+        let text = "start,duration\nyesterday,3600\n2019-12-10 09:00,1:30\n";
+        let rows = parse(text, &Tz::UTC).unwrap();
+
+        assert!(rows[0].result.is_err());
+        assert_eq!(rows[1].result.as_ref().unwrap().duration, 5400);
+    }
+
+    #[test]
+    fn requires_start_column() {
+        assert!(parse("description\nWork\n", &Tz::UTC).is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("01:02:03"), Ok(3723));
+        assert!(parse_duration("1h").is_err());
+        assert!(parse_duration("99999999999999999:00").is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use super::{ImportedTimeEntry, ParsedRow};
+
+struct Property<'a> {
+    name: String,
+    params: Vec<&'a str>,
+    value: &'a str,
+}
+
+/// Parses the VEVENTs of an iCalendar file (RFC 5545). Every event is a row of the import,
+/// the `SUMMARY` becomes the description and the first of the `CATEGORIES` the project.
+/// Times with a known `TZID` are interpreted in that timezone, floating times and times with
+/// an unknown `TZID` in the given one.
+pub fn parse(text: &str, timezone: &Tz) -> Result<Vec<ParsedRow>, String> {
+    let lines = unfold(text);
+    if lines.first().map(|line| line.trim()) != Some("BEGIN:VCALENDAR") {
+        return Err("The file isn't an iCalendar file.".to_string());
+    }
+
+    let mut rows = vec![];
+    let mut event: Option<Vec<Property>> = None;
+
+    for line in lines.iter() {
+        let property = match property(line) {
+            Some(property) => property,
+            None => continue,
+        };
+
+        match (property.name.as_str(), property.value) {
+            ("BEGIN", "VEVENT") => event = Some(vec![]),
+            ("END", "VEVENT") => {
+                if let Some(properties) = event.take() {
+                    rows.push(ParsedRow {
+                        row: rows.len() + 1,
+                        result: parse_event(&properties, timezone),
+                    });
+                }
+            }
+            _ => {
+                if let Some(properties) = event.as_mut() {
+                    properties.push(property);
+                }
+            }
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Joins the lines which were folded because they were too long.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in text.split('\n').map(|line| line.trim_end_matches('\r')) {
+        match (line.chars().next(), lines.last_mut()) {
+            (Some(' '), Some(last)) | (Some('\t'), Some(last)) => last.push_str(&line[1..]),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+}
+
+fn property(line: &str) -> Option<Property<'_>> {
+    let colon = line.find(':')?;
+    let mut name_and_params = line[..colon].split(';');
+
+    Some(Property {
+        name: name_and_params.next()?.trim().to_uppercase(),
+        params: name_and_params.collect(),
+        value: &line[colon + 1..],
+    })
+}
+
+fn parse_event(properties: &[Property], timezone: &Tz) -> Result<ImportedTimeEntry, String> {
+    let find = |name: &str| properties.iter().find(|property| property.name == name);
+
+    let start = find("DTSTART")
+        .ok_or_else(|| "The event doesn't have a start.".to_string())
+        .and_then(|property| parse_date_time(property, timezone))?;
+
+    let duration = match (find("DTEND"), find("DURATION")) {
+        (Some(end), _) => {
+            let end = parse_date_time(end, timezone)?;
+            if end < start {
+                return Err("The event ends before it starts.".to_string());
+            }
+            end.signed_duration_since(start).num_seconds() as u64
+        }
+        (None, Some(duration)) => parse_duration(duration.value)?,
+        (None, None) => return Err("The event doesn't have an end.".to_string()),
+    };
+
+    Ok(ImportedTimeEntry {
+        start,
+        duration,
+        description: find("SUMMARY")
+            .map(|summary| unescape(summary.value))
+            .unwrap_or_default(),
+        project: find("CATEGORIES")
+            .and_then(|categories| categories.value.split(',').next())
+            .map(|category| unescape(category.trim()))
+            .filter(|category| !category.is_empty()),
+    })
+}
+
+fn parse_date_time(property: &Property, timezone: &Tz) -> Result<DateTime<Utc>, String> {
+    let value = property.value.trim();
+    let is_date = property
+        .params
+        .iter()
+        .any(|param| param.eq_ignore_ascii_case("VALUE=DATE"));
+
+    if is_date || value.len() == 8 {
+        return Err("All-day events cannot be imported.".to_string());
+    }
+
+    if value.ends_with('Z') {
+        return NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ")
+            .map(|date_time| Utc.from_utc_datetime(&date_time))
+            .map_err(|_| format!("'{}' is not a valid date and time.", value));
+    }
+
+    let timezone = property
+        .params
+        .iter()
+        .filter_map(|param| param.strip_prefix("TZID="))
+        .find_map(|name| name.trim_matches('"').parse::<Tz>().ok())
+        .unwrap_or(*timezone);
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .and_then(|local| timezone.from_local_datetime(&local).earliest())
+        .map(|date_time| date_time.with_timezone(&Utc))
+        .ok_or_else(|| format!("'{}' is not a valid date and time.", value))
+}
+
+/// Parses the durations in the format of RFC 5545, e.g. `PT1H30M` or `P1DT2H`.
+fn parse_duration(value: &str) -> Result<u64, String> {
+    let invalid = || format!("'{}' is not a valid duration.", value);
+    let value = value.trim().trim_start_matches('+');
+    if !value.starts_with('P') {
+        return Err(invalid());
+    }
+
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for c in value[1..].chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        if c == 'T' {
+            continue;
+        }
+
+        let amount: u64 = number.parse().map_err(|_| invalid())?;
+        let unit = match c {
+            'W' => 7 * 24 * 3600,
+            'D' => 24 * 3600,
+            'H' => 3600,
+            'M' => 60,
+            'S' => 1,
+            _ => return Err(invalid()),
+        };
+        seconds = amount
+            .checked_mul(unit)
+            .and_then(|part| seconds.checked_add(part))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(seconds)
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) | ('\\', Some('N')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            ('\\', Some(escaped)) => {
+                unescaped.push(escaped);
+                chars.next();
+            }
+            (c, _) => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, parse_duration, unescape};
+    use chrono::{TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    fn calendar(events: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}END:VCALENDAR\r\n",
+            events
+        )
+    }
+
+    #[test]
+    fn parses_events() {
+        let text = calendar(
+            "BEGIN:VEVENT\r\nDTSTART:20191210T090000Z\r\nDTEND:20191210T100000Z\r\n\
+             SUMMARY:Planning\\, again\r\nCATEGORIES:Utopia,Meetings\r\nEND:VEVENT\r\n",
+        );
+        let rows = parse(&text, &Tz::UTC).unwrap();
+        let te = rows[0].result.as_ref().unwrap();
+
+        assert_eq!(te.start, Utc.ymd(2019, 12, 10).and_hms(9, 0, 0));
+        assert_eq!(te.duration, 3600);
+        assert_eq!(te.description, "Planning, again");
+        assert_eq!(te.project, Some("Utopia".to_string()));
+    }
+
+    #[test]
+    fn unfolds_long_lines_and_uses_the_timezone_of_the_event() {
+        let text = calendar(
+            "BEGIN:VEVENT\r\nDTSTART;TZID=Europe/Prague:20191210T090000\r\n\
+             DURATION:PT30M\r\nSUMMARY:A very\r\n  long summary\r\nEND:VEVENT\r\n",
+        );
+        let rows = parse(&text, &Tz::UTC).unwrap();
+        let te = rows[0].result.as_ref().unwrap();
+
+        assert_eq!(te.start, Utc.ymd(2019, 12, 10).and_hms(8, 0, 0));
+        assert_eq!(te.duration, 1800);
+        assert_eq!(te.description, "A very long summary");
+    }
+
+    #[test]
+    fn rejects_all_day_events() {
+        let text = calendar(
+            "BEGIN:VEVENT\r\nDTSTART;VALUE=DATE:20191210\r\nDTEND;VALUE=DATE:20191211\r\n\
+             END:VEVENT\r\n",
+        );
+        let rows = parse(&text, &Tz::UTC).unwrap();
+
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].result.is_err());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(parse("start,stop\n", &Tz::UTC).is_err());
+    }
+
+    #[test]
+    fn parses_durations_and_escapes() {
+        assert_eq!(parse_duration("P1DT2H3M4S"), Ok(93784));
+        assert_eq!(parse_duration("PT15M"), Ok(900));
+        assert!(parse_duration("15M").is_err());
+        assert!(parse_duration("P99999999999999W").is_err());
+        assert_eq!(unescape("a\\;b\\nc\\\\"), "a;b\nc\\");
+    }
+}
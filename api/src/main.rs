@@ -1,8 +1,25 @@
+mod analysis;
 mod auth;
+mod auto_stop;
+mod billing;
+mod calendar;
+mod duplicates;
 mod endpoints;
 mod error;
+mod export;
+mod favourites;
+mod goals;
+mod history;
+mod import;
 mod models;
+mod recurrence;
+mod reports;
 mod responses;
+mod rounding;
+mod search;
+mod session;
+mod stats;
+mod suggestions;
 mod sync;
 mod toggl_api;
 
@@ -11,20 +28,236 @@ fn main() -> std::io::Result<()> {
         middleware::{Compress, Logger},
         web, App, HttpServer,
     };
+    use auth::middleware::Authentication;
 
     std::env::set_var("RUST_LOG", "actix_web=info");
     env_logger::init();
 
+    /// Imported histories can be much larger than the usual requests.
+    const IMPORT_SIZE_LIMIT: usize = 10 * 1024 * 1024;
+
     let addr = "localhost:8080";
     println!("Starting the server at {}", addr);
 
-    HttpServer::new(|| {
+    let sessions = web::Data::new(session::Sessions::default());
+    let history = web::Data::new(history::History::default());
+    let auto_stop = web::Data::new(auto_stop::AutoStop::default());
+    let roundings = web::Data::new(rounding::Roundings::default());
+    let goals = web::Data::new(goals::Goals::default());
+    let favourites = web::Data::new(favourites::Favourites::default());
+    let recurrences = web::Data::new(recurrence::Recurrences::default());
+    let search = web::Data::new(search::Search::default());
+    let statistics = web::Data::new(stats::Statistics::default());
+    let billing = web::Data::new(billing::Billing::default());
+
+    let background_auto_stop = auto_stop.clone();
+    let background_roundings = roundings.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_secs(
+            auto_stop::CHECK_INTERVAL_MINUTES * 60,
+        ));
+        background_auto_stop.check_all(&background_roundings);
+    });
+
+    let background_recurrences = recurrences.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_secs(
+            recurrence::EXPANSION_INTERVAL_MINUTES * 60,
+        ));
+        background_recurrences.expand_all();
+    });
+
+    HttpServer::new(move || {
         App::new()
+            .register_data(sessions.clone())
+            .register_data(history.clone())
+            .register_data(auto_stop.clone())
+            .register_data(roundings.clone())
+            .register_data(goals.clone())
+            .register_data(favourites.clone())
+            .register_data(recurrences.clone())
+            .register_data(search.clone())
+            .register_data(statistics.clone())
+            .register_data(billing.clone())
             .wrap(Logger::default())
             .wrap(Logger::new("%a %{User-Agent}i"))
             .wrap(Compress::default())
-            .service(web::resource("/current-snapshot").route(web::get().to(endpoints::login)))
-            .service(web::resource("/sync").route(web::post().to(endpoints::sync)))
+            .service(
+                web::resource("/current-snapshot")
+                    .wrap(Authentication::basic())
+                    .route(web::get().to(endpoints::login)),
+            )
+            .service(
+                web::resource("/sync")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::sync)),
+            )
+            .service(
+                web::resource("/timer/start")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::start_timer)),
+            )
+            .service(
+                web::resource("/timer/stop")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::stop_timer)),
+            )
+            .service(
+                web::resource("/auto-stop")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::auto_stop_rules))
+                    .route(web::put().to(endpoints::set_auto_stop_rules)),
+            )
+            .service(
+                web::resource("/calendar")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::calendar)),
+            )
+            .service(
+                web::resource("/favourites/{id}/start")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::start_favourite)),
+            )
+            .service(
+                web::resource("/goals")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::goals))
+                    .route(web::put().to(endpoints::set_goals)),
+            )
+            .service(
+                web::resource("/goals/progress")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::goal_progress)),
+            )
+            .service(
+                web::resource("/recurrences")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::recurrences))
+                    .route(web::post().to(endpoints::create_recurrence)),
+            )
+            .service(
+                web::resource("/recurrences/expand")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::expand_recurrences)),
+            )
+            .service(
+                web::resource("/recurrences/{id}")
+                    .wrap(Authentication::bearer())
+                    .route(web::put().to(endpoints::update_recurrence))
+                    .route(web::delete().to(endpoints::delete_recurrence)),
+            )
+            .service(
+                web::resource("/rounding")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::rounding_rules))
+                    .route(web::put().to(endpoints::set_rounding_rules)),
+            )
+            .service(
+                web::resource("/time-entries/export")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::export_time_entries)),
+            )
+            .service(
+                web::resource("/time-entries/import")
+                    .data(web::PayloadConfig::new(IMPORT_SIZE_LIMIT))
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::import_time_entries)),
+            )
+            .service(
+                web::resource("/time-entries/analysis")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::analyze_time_entries)),
+            )
+            .service(
+                web::resource("/time-entries/duplicates")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::find_duplicates)),
+            )
+            .service(
+                web::resource("/time-entries/duplicates/delete")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::delete_duplicates)),
+            )
+            .service(
+                web::resource("/time-entries/search")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::search_time_entries)),
+            )
+            .service(
+                web::resource("/time-entries/suggestions")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::suggest_time_entries)),
+            )
+            .service(
+                web::resource("/time-entries/{id}/history")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::time_entry_history)),
+            )
+            .service(
+                web::resource("/time-entries/{id}/history/{revision}/restore")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::restore_time_entry)),
+            )
+            .service(
+                web::resource("/projects/{id}/history")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::project_history)),
+            )
+            .service(
+                web::resource("/projects/{id}/history/{revision}/restore")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::restore_project)),
+            )
+            .service(
+                web::resource("/time-entries/{id}/continue")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::continue_time_entry)),
+            )
+            .service(
+                web::resource("/reports/summary")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::report)),
+            )
+            .service(
+                web::resource("/time-entries/{id}/split")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::split_time_entry)),
+            )
+            .service(
+                web::resource("/time-entries/merge")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::merge_time_entries)),
+            )
+            .service(
+                web::resource("/time-entries/bulk-edit")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::bulk_edit_time_entries)),
+            )
+            .service(
+                web::resource("/projects/bulk-edit")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::bulk_edit_projects)),
+            )
+            .service(
+                web::resource("/session")
+                    .wrap(Authentication::bearer())
+                    .route(web::delete().to(endpoints::revoke_session)),
+            )
+            .service(
+                web::resource("/session/refresh")
+                    .wrap(Authentication::bearer())
+                    .route(web::post().to(endpoints::refresh_session)),
+            )
+            .service(
+                web::resource("/sessions")
+                    .wrap(Authentication::bearer())
+                    .route(web::get().to(endpoints::list_sessions)),
+            )
+            .service(
+                web::resource("/sessions/{id}")
+                    .wrap(Authentication::bearer())
+                    .route(web::delete().to(endpoints::revoke_other_session)),
+            )
     })
     .bind(addr)?
     .run()
@@ -1,7 +1,8 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use std::cmp::PartialEq;
 
+use crate::rounding::Rounding;
 use crate::toggl_api::models::{ApiToken, Id};
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
@@ -9,10 +10,21 @@ pub struct User {
     pub id: Id,
     pub default_workspace_id: Id,
     pub fullname: String,
+    /// The IANA name of the timezone of the user, e.g. "Europe/Prague".
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// The first day of the week (0 = Sunday, 1 = Monday, ...).
+    #[serde(default = "default_beginning_of_week")]
+    pub beginning_of_week: u32,
+    #[serde(skip_serializing, default)]
     pub api_token: ApiToken,
     pub at: DateTime<Utc>,
 }
 
+pub fn default_beginning_of_week() -> u32 {
+    1
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct Project {
     pub id: Id,
@@ -20,10 +32,57 @@ pub struct Project {
     pub name: String,
     pub color: String,
     pub active: bool,
+    #[serde(default)]
+    pub billable: bool,
+    /// The hourly rate of the billable time entries in the project.
+    #[serde(default)]
+    pub rate: Option<f64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<ProjectStats>,
     pub at: DateTime<Utc>,
     pub server_deleted_at: Option<DateTime<Utc>>,
 }
 
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Workspace {
+    pub id: Id,
+    pub name: String,
+    /// The hourly rate of the billable time entries in projects without their own rate.
+    pub default_hourly_rate: Option<f64>,
+    pub default_currency: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+/// How much time the user tracked on a project. The proxy calculates the statistics from
+/// the time entries it has seen, the value sent by the client is ignored.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
+pub struct ProjectStats {
+    pub total_seconds: u64,
+    pub this_week_seconds: u64,
+    pub last_used: Option<DateTime<Utc>>,
+    pub entry_count: usize,
+}
+
+/// How much a billable time entry earned. The proxy calculates it from the rate of the project
+/// or the workspace, the value sent by the client is ignored.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Earnings {
+    pub amount: f64,
+    pub hourly_rate: f64,
+    pub currency: Option<String>,
+}
+
+/// Marks a time entry which the proxy generated from a recurrence of the user. The mark is
+/// kept when the time entry is edited, the other occurrences aren't affected by the edit.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Occurrence {
+    pub recurrence_id: Id,
+    /// The local date of the occurrence in the timezone of the user.
+    pub date: NaiveDate,
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct TimeEntry {
     pub id: Id,
@@ -32,6 +91,30 @@ pub struct TimeEntry {
     pub project_id: Option<Id>,
     pub start: DateTime<Utc>,
     pub duration: Option<u64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub billable: bool,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub earnings: Option<Earnings>,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Occurrence>,
+    pub at: DateTime<Utc>,
+    pub server_deleted_at: Option<DateTime<Utc>>,
+}
+
+/// A template for time entries which the user starts often. Favourites are kept by the proxy,
+/// Toggl doesn't know about them.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Favourite {
+    pub id: Id,
+    pub workspace_id: Id,
+    pub description: String,
+    pub project_id: Option<Id>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub billable: bool,
     pub at: DateTime<Utc>,
     pub server_deleted_at: Option<DateTime<Utc>>,
 }
@@ -41,17 +124,33 @@ impl TimeEntry {
         self.duration.is_none()
     }
 
-    pub fn elapsed_seconds(&self) -> u64 {
-        std::cmp::max(
-            Utc::now().signed_duration_since(self.start).num_seconds(),
-            0,
-        ) as u64
+    pub fn elapsed_seconds_until(&self, now: DateTime<Utc>) -> u64 {
+        std::cmp::max(now.signed_duration_since(self.start).num_seconds(), 0) as u64
     }
 
-    pub fn stop(&self) -> TimeEntry {
+    pub fn stop(&self, rounding: Option<&Rounding>) -> TimeEntry {
+        self.stop_at(Utc::now(), rounding)
+    }
+
+    /// Stops the time entry as if it was stopped at the given moment. The duration is rounded,
+    /// but the rounded time entry is cut short so it doesn't end in the future.
+    pub fn stop_at(&self, end: DateTime<Utc>, rounding: Option<&Rounding>) -> TimeEntry {
+        let now = Utc::now();
+        let elapsed = self.elapsed_seconds_until(end);
+        let duration = rounding
+            .map(|rounding| rounding.round(elapsed))
+            .map(|rounded| {
+                if self.start + Duration::seconds(rounded as i64) > now {
+                    std::cmp::max(self.elapsed_seconds_until(now), elapsed)
+                } else {
+                    rounded
+                }
+            })
+            .unwrap_or(elapsed);
+
         TimeEntry {
-            duration: Some(self.elapsed_seconds()),
-            at: Utc::now(),
+            duration: Some(duration),
+            at: now,
             ..self.clone()
         }
     }
@@ -62,6 +161,8 @@ pub struct Delta {
     pub user: Option<User>,
     pub projects: Option<Vec<Project>>,
     pub time_entries: Option<Vec<TimeEntry>>,
+    #[serde(default)]
+    pub favourites: Option<Vec<Favourite>>,
 }
 
 pub trait Entity: Clone + Serialize + PartialEq {
@@ -114,3 +215,61 @@ impl Entity for TimeEntry {
         self.at
     }
 }
+
+impl Entity for Favourite {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.server_deleted_at.is_some()
+    }
+
+    fn last_update(&self) -> DateTime<Utc> {
+        self.at
+    }
+}
+
+/// Entities for the tests. The tests change only the fields which matter to them
+/// with the struct update syntax.
+#[cfg(test)]
+pub mod fixtures {
+    use super::{Project, TimeEntry};
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    /// A time entry without a project in the workspace 1, last updated when it started.
+    pub fn time_entry(id: Id, start: DateTime<Utc>, duration: Option<u64>) -> TimeEntry {
+        TimeEntry {
+            id,
+            workspace_id: 1,
+            description: "Work".to_string(),
+            project_id: None,
+            start,
+            duration,
+            tags: vec![],
+            billable: false,
+            earnings: None,
+            recurrence: None,
+            at: start,
+            server_deleted_at: None,
+        }
+    }
+
+    /// An active project in the workspace 1.
+    pub fn project(id: Id, name: &str) -> Project {
+        Project {
+            id,
+            workspace_id: 1,
+            name: name.to_string(),
+            color: "#ff0000".to_string(),
+            active: true,
+            billable: false,
+            rate: None,
+            currency: None,
+            stats: None,
+            at: Utc.ymd(2019, 12, 1).and_hms(0, 0, 0),
+            server_deleted_at: None,
+        }
+    }
+}
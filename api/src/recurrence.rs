@@ -0,0 +1,594 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use crate::auth::Credentials;
+use crate::calendar::Calendar;
+use crate::error::{Error, ValidationError};
+use crate::models::{Delta, Occurrence, TimeEntry};
+use crate::sync::prelude::{SyncOutcome, SyncResult};
+use crate::sync::{server, validation};
+use crate::toggl_api::{
+    models::{ApiToken, Id},
+    TogglApi,
+};
+
+/// How often the background job creates the time entries of the recurrences.
+pub const EXPANSION_INTERVAL_MINUTES: u64 = 15;
+/// The recurrences which start in the past are expanded at most this many days back.
+const MAX_BACKFILL_DAYS: i64 = 31;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Frequency {
+    Daily,
+    Weekly,
+}
+
+/// A time entry which repeats, e.g. a stand-up every weekday at 9:30 for 15 minutes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Recurrence {
+    /// Assigned by the proxy when the recurrence is created.
+    #[serde(default)]
+    pub id: Id,
+    pub workspace_id: Id,
+    pub description: String,
+    #[serde(default)]
+    pub project_id: Option<Id>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub billable: bool,
+    /// The local time at which the time entries start in the timezone of the user.
+    pub start_time: NaiveTime,
+    pub duration_minutes: u64,
+    pub frequency: Frequency,
+    /// Repeat every n-th day or week.
+    #[serde(default = "default_interval")]
+    pub interval: u32,
+    /// The days of the week (0 = Sunday, 1 = Monday, ...) of a weekly recurrence. Only
+    /// the day of the week of `starts_on` when empty.
+    #[serde(default)]
+    pub weekdays: Vec<u32>,
+    pub starts_on: NaiveDate,
+    /// The last day on which the time entry can be created.
+    #[serde(default)]
+    pub until: Option<NaiveDate>,
+}
+
+fn default_interval() -> u32 {
+    1
+}
+
+struct Series {
+    recurrence: Recurrence,
+    /// All the occurrences up to this day have been created already, except the failed ones.
+    expanded_until: Option<NaiveDate>,
+    /// The occurrences which couldn't be created. Only these are tried again.
+    failed: BTreeSet<NaiveDate>,
+}
+
+struct UserRecurrences {
+    api_token: ApiToken,
+    series: Vec<Series>,
+    /// The generated time entries by their id.
+    occurrences: HashMap<Id, Occurrence>,
+}
+
+/// The recurrences of the users together with the time entries generated from them.
+pub struct Recurrences {
+    users: Mutex<HashMap<Id, UserRecurrences>>,
+    next_id: Mutex<Id>,
+    /// Only one expansion runs at a time so no occurrence is created twice.
+    expanding: Mutex<()>,
+}
+
+impl Default for Recurrences {
+    fn default() -> Recurrences {
+        Recurrences {
+            users: Mutex::new(HashMap::new()),
+            next_id: Mutex::new(1),
+            expanding: Mutex::new(()),
+        }
+    }
+}
+
+impl Recurrence {
+    pub fn occurs_on(&self, date: NaiveDate) -> bool {
+        if date < self.starts_on || self.until.is_some_and(|until| date > until) {
+            return false;
+        }
+
+        let interval = i64::from(std::cmp::max(self.interval, 1));
+        match self.frequency {
+            Frequency::Daily => (date - self.starts_on).num_days() % interval == 0,
+            Frequency::Weekly => {
+                let weekday = date.weekday().num_days_from_sunday();
+                let on_weekday = if self.weekdays.is_empty() {
+                    weekday == self.starts_on.weekday().num_days_from_sunday()
+                } else {
+                    self.weekdays.contains(&weekday)
+                };
+                let weeks = (week_start(date) - week_start(self.starts_on)).num_days() / 7;
+
+                on_weekday && weeks % interval == 0
+            }
+        }
+    }
+
+    fn start_on<Tz: TimeZone>(&self, date: NaiveDate, calendar: &Calendar<Tz>) -> DateTime<Utc> {
+        calendar
+            .timezone
+            .from_local_datetime(&date.and_time(self.start_time))
+            .earliest()
+            .map(|start| start.with_timezone(&Utc))
+            // the clocks skipped the start time, the day starts later
+            .unwrap_or_else(|| calendar.start_of(date))
+    }
+
+    fn time_entry(&self, id: Id, start: DateTime<Utc>, now: DateTime<Utc>) -> TimeEntry {
+        TimeEntry {
+            id,
+            workspace_id: self.workspace_id,
+            description: self.description.clone(),
+            project_id: self.project_id,
+            start,
+            duration: Some(self.duration_minutes * 60),
+            tags: self.tags.clone(),
+            billable: self.billable,
+            earnings: None,
+            recurrence: None,
+            at: now,
+            server_deleted_at: None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let mut errors = vec![];
+        if self.duration_minutes == 0 || self.duration_minutes > 24 * 60 {
+            errors.push(invalid(
+                "duration_minutes",
+                "The duration must be between 1 minute and 24 hours.",
+            ));
+        }
+        if self.interval == 0 {
+            errors.push(invalid("interval", "The interval must be at least 1."));
+        }
+        if self.weekdays.iter().any(|day| *day > 6) {
+            errors.push(invalid(
+                "weekdays",
+                "The days of the week must be between 0 (Sunday) and 6.",
+            ));
+        }
+        if self.until.is_some_and(|until| until < self.starts_on) {
+            errors.push(invalid("until", "The recurrence must end after it starts."));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(errors))
+        }
+    }
+}
+
+impl Recurrences {
+    pub fn list(&self, user_id: Id) -> Vec<Recurrence> {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .map(|user| {
+                user.series
+                    .iter()
+                    .map(|series| series.recurrence.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn create(
+        &self,
+        user_id: Id,
+        api_token: ApiToken,
+        recurrence: Recurrence,
+    ) -> Result<Recurrence, Error> {
+        recurrence.validate()?;
+        let recurrence = Recurrence {
+            id: self.assign_id(),
+            ..recurrence
+        };
+
+        let mut users = self.users.lock().unwrap();
+        let user = users.entry(user_id).or_insert_with(|| UserRecurrences {
+            api_token: api_token.clone(),
+            series: vec![],
+            occurrences: HashMap::new(),
+        });
+        user.api_token = api_token;
+        user.series.push(Series {
+            recurrence: recurrence.clone(),
+            expanded_until: None,
+            failed: BTreeSet::new(),
+        });
+
+        Ok(recurrence)
+    }
+
+    /// Changes the recurrence from now on. The time entries which were created already
+    /// are left as they are.
+    pub fn update(&self, user_id: Id, id: Id, recurrence: Recurrence) -> Result<Recurrence, Error> {
+        recurrence.validate()?;
+        let recurrence = Recurrence { id, ..recurrence };
+
+        let mut users = self.users.lock().unwrap();
+        let series = users
+            .get_mut(&user_id)
+            .and_then(|user| {
+                user.series
+                    .iter_mut()
+                    .find(|series| series.recurrence.id == id)
+            })
+            .ok_or_else(|| not_found(id))?;
+        series.recurrence = recurrence.clone();
+
+        Ok(recurrence)
+    }
+
+    /// Stops the recurrence. The time entries which were created already are kept.
+    pub fn delete(&self, user_id: Id, id: Id) -> Result<(), Error> {
+        let mut users = self.users.lock().unwrap();
+        let user = users.get_mut(&user_id).ok_or_else(|| not_found(id))?;
+        let count = user.series.len();
+        user.series.retain(|series| series.recurrence.id != id);
+
+        if user.series.len() == count {
+            Err(not_found(id))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Creates the time entries of all the occurrences of the recurrences of the user which
+    /// have already ended.
+    pub fn expand(&self, user_id: Id, api: &TogglApi) -> Result<SyncOutcome, Error> {
+        let _expanding = self.expanding.lock().unwrap();
+        let now = Utc::now();
+
+        let pending: Vec<(Recurrence, Option<NaiveDate>, BTreeSet<NaiveDate>)> =
+            match self.users.lock().unwrap().get(&user_id) {
+                Some(user) => user
+                    .series
+                    .iter()
+                    .map(|series| {
+                        (
+                            series.recurrence.clone(),
+                            series.expanded_until,
+                            series.failed.clone(),
+                        )
+                    })
+                    .collect(),
+                None => return Ok(SyncOutcome::convert(Delta::default())),
+            };
+        if pending.is_empty() {
+            return Ok(SyncOutcome::convert(Delta::default()));
+        }
+
+        let calendar = Calendar::of_user(&server::fetch_user(api)?)?;
+        let mut time_entries = vec![];
+        let mut generated = HashMap::new();
+        let mut expanded = vec![];
+        for (recurrence, expanded_until, failed) in pending {
+            let (due, until) = due(&recurrence, expanded_until, &failed, &calendar, now);
+            for (date, start) in due {
+                let id = -(time_entries.len() as Id) - 1;
+                time_entries.push(recurrence.time_entry(id, start, now));
+                generated.insert(
+                    id,
+                    Occurrence {
+                        recurrence_id: recurrence.id,
+                        date,
+                    },
+                );
+            }
+            expanded.push((recurrence.id, until));
+        }
+
+        let outcome = push(time_entries, api)?;
+        self.record(user_id, &outcome, generated, expanded);
+
+        Ok(self.mark(user_id, outcome))
+    }
+
+    /// Expands the recurrences of all the users. This is what the background job does.
+    pub fn expand_all(&self) {
+        let users: Vec<(Id, ApiToken)> = self
+            .users
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, user)| !user.series.is_empty())
+            .map(|(user_id, user)| (*user_id, user.api_token.clone()))
+            .collect();
+
+        for (user_id, api_token) in users {
+            let result = TogglApi::new(Credentials::Token(api_token))
+                .ok_or_else(|| Error::Internal("Cannot create a Toggl API client.".to_string()))
+                .and_then(|api| self.expand(user_id, &api));
+
+            if let Err(err) = result {
+                log::warn!("Expanding recurrences of user {} failed: {}", user_id, err);
+            }
+        }
+    }
+
+    /// Marks the time entries which were generated from the recurrences of the user.
+    pub fn mark(&self, user_id: Id, mut outcome: SyncOutcome) -> SyncOutcome {
+        let users = self.users.lock().unwrap();
+        if let Some(user) = users.get(&user_id) {
+            for result in outcome.time_entries.iter_mut() {
+                if let Some(entity) = result.entity_mut() {
+                    entity.recurrence = user.occurrences.get(&entity.id).cloned();
+                }
+            }
+        }
+
+        outcome
+    }
+
+    /// Marks the time entries in the snapshot which were generated from the recurrences.
+    pub fn mark_delta(&self, user_id: Id, mut delta: Delta) -> Delta {
+        let users = self.users.lock().unwrap();
+        if let Some(user) = users.get(&user_id) {
+            for te in delta.time_entries.iter_mut().flatten() {
+                te.recurrence = user.occurrences.get(&te.id).cloned();
+            }
+        }
+
+        delta
+    }
+
+    /// Remembers the generated time entries. Only the occurrences which couldn't be created
+    /// are tried again during the next expansion.
+    fn record(
+        &self,
+        user_id: Id,
+        outcome: &SyncOutcome,
+        mut generated: HashMap<Id, Occurrence>,
+        expanded: Vec<(Id, Option<NaiveDate>)>,
+    ) {
+        let mut users = self.users.lock().unwrap();
+        let user = match users.get_mut(&user_id) {
+            Some(user) => user,
+            None => return,
+        };
+
+        for (id, until) in expanded {
+            if let Some(series) = user
+                .series
+                .iter_mut()
+                .find(|series| series.recurrence.id == id)
+            {
+                series.expanded_until = until;
+            }
+        }
+
+        for result in &outcome.time_entries {
+            let (occurrence, created) = match result {
+                SyncResult::Created {
+                    client_assigned_id,
+                    entity,
+                } => match generated.remove(client_assigned_id) {
+                    Some(occurrence) => {
+                        user.occurrences.insert(entity.id, occurrence.clone());
+                        (occurrence, true)
+                    }
+                    None => continue,
+                },
+                SyncResult::Failed { entity_id, .. } => match generated.remove(entity_id) {
+                    Some(occurrence) => (occurrence, false),
+                    None => continue,
+                },
+                _ => continue,
+            };
+
+            if let Some(series) = user
+                .series
+                .iter_mut()
+                .find(|series| series.recurrence.id == occurrence.recurrence_id)
+            {
+                if created {
+                    series.failed.remove(&occurrence.date);
+                } else {
+                    series.failed.insert(occurrence.date);
+                }
+            }
+        }
+    }
+
+    fn assign_id(&self) -> Id {
+        let mut next_id = self.next_id.lock().unwrap();
+        let id = *next_id;
+        *next_id += 1;
+
+        id
+    }
+}
+
+/// The occurrences of the recurrence which haven't been created yet and which have already
+/// ended, together with the last day up to which the recurrence is expanded afterwards.
+/// The occurrences which failed before are tried again as long as they still occur.
+pub fn due<Tz: TimeZone>(
+    recurrence: &Recurrence,
+    expanded_until: Option<NaiveDate>,
+    failed: &BTreeSet<NaiveDate>,
+    calendar: &Calendar<Tz>,
+    now: DateTime<Utc>,
+) -> (Vec<(NaiveDate, DateTime<Utc>)>, Option<NaiveDate>) {
+    let today = now.with_timezone(&calendar.timezone).naive_local().date();
+    let earliest = today - Duration::days(MAX_BACKFILL_DAYS);
+
+    let mut due: Vec<_> = failed
+        .iter()
+        .filter(|date| **date >= earliest && recurrence.occurs_on(**date))
+        .map(|date| (*date, recurrence.start_on(*date, calendar)))
+        .collect();
+
+    let mut date = match expanded_until {
+        Some(expanded_until) => expanded_until + Duration::days(1),
+        None => recurrence.starts_on,
+    };
+    date = std::cmp::max(date, earliest);
+
+    let mut until = expanded_until;
+    while date <= today {
+        if recurrence.occurs_on(date) {
+            let start = recurrence.start_on(date, calendar);
+            let end = start + Duration::minutes(recurrence.duration_minutes as i64);
+            if end > now {
+                break;
+            }
+            due.push((date, start));
+        }
+
+        until = Some(date);
+        date += Duration::days(1);
+    }
+
+    (due, until)
+}
+
+fn push(time_entries: Vec<TimeEntry>, api: &TogglApi) -> Result<SyncOutcome, Error> {
+    if time_entries.is_empty() {
+        return Ok(SyncOutcome::convert(Delta::default()));
+    }
+
+    let changes = Delta {
+        user: None,
+        projects: None,
+        time_entries: Some(time_entries),
+        favourites: None,
+    };
+    let known_projects = server::fetch_all_projects(api)?;
+    let (changes, rejected) = validation::validate(changes, &known_projects, Utc::now());
+
+    Ok(SyncOutcome::merge(
+        server::apply_changes(changes, api),
+        rejected,
+    ))
+}
+
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(i64::from(date.weekday().num_days_from_sunday()))
+}
+
+fn invalid(field: &str, reason: &str) -> ValidationError {
+    ValidationError {
+        field: Some(field.to_string()),
+        reason: reason.to_string(),
+    }
+}
+
+fn not_found(id: Id) -> Error {
+    Error::NotFound(format!("There is no recurrence {}.", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{due, Frequency, Recurrence};
+    use crate::calendar::Calendar;
+    use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+    use chrono_tz::Europe::Prague;
+    use std::collections::BTreeSet;
+
+    fn recurrence(frequency: Frequency, interval: u32, weekdays: Vec<u32>) -> Recurrence {
+        Recurrence {
+            id: 1,
+            workspace_id: 1,
+            description: "Stand-up".to_string(),
+            project_id: None,
+            tags: vec![],
+            billable: false,
+            start_time: NaiveTime::from_hms(9, 30, 0),
+            duration_minutes: 15,
+            frequency,
+            interval,
+            weekdays,
+            // Monday
+            starts_on: NaiveDate::from_ymd(2019, 12, 2),
+            until: Some(NaiveDate::from_ymd(2019, 12, 31)),
+        }
+    }
+
+    #[test]
+    fn occurs_on_the_given_days() {
+        let every_other_day = recurrence(Frequency::Daily, 2, vec![]);
+        assert!(every_other_day.occurs_on(NaiveDate::from_ymd(2019, 12, 4)));
+        assert!(!every_other_day.occurs_on(NaiveDate::from_ymd(2019, 12, 5)));
+        assert!(!every_other_day.occurs_on(NaiveDate::from_ymd(2019, 11, 30)));
+        assert!(!every_other_day.occurs_on(NaiveDate::from_ymd(2020, 1, 1)));
+
+        let biweekly = recurrence(Frequency::Weekly, 2, vec![1, 3]);
+        assert!(biweekly.occurs_on(NaiveDate::from_ymd(2019, 12, 4)));
+        assert!(!biweekly.occurs_on(NaiveDate::from_ymd(2019, 12, 9)));
+        assert!(biweekly.occurs_on(NaiveDate::from_ymd(2019, 12, 16)));
+        assert!(!biweekly.occurs_on(NaiveDate::from_ymd(2019, 12, 17)));
+
+        let weekly = recurrence(Frequency::Weekly, 1, vec![]);
+        assert!(weekly.occurs_on(NaiveDate::from_ymd(2019, 12, 9)));
+        assert!(!weekly.occurs_on(NaiveDate::from_ymd(2019, 12, 10)));
+    }
+
+    #[test]
+    fn creates_only_occurrences_which_have_ended() {
+        let calendar = Calendar {
+            timezone: Prague,
+            beginning_of_week: 1,
+        };
+        let weekdays = recurrence(Frequency::Weekly, 1, vec![1, 2, 3, 4, 5]);
+        // Wednesday 9:40 in Prague, the stand-up hasn't ended yet
+        let now = Utc.ymd(2019, 12, 4).and_hms(8, 40, 0);
+
+        let (due_now, until) = due(&weekdays, None, &BTreeSet::new(), &calendar, now);
+        assert_eq!(due_now.len(), 2);
+        assert_eq!(due_now[0].1, Utc.ymd(2019, 12, 2).and_hms(8, 30, 0));
+        assert_eq!(until, Some(NaiveDate::from_ymd(2019, 12, 3)));
+
+        let later = Utc.ymd(2019, 12, 4).and_hms(8, 45, 0);
+        let (due_later, until) = due(&weekdays, until, &BTreeSet::new(), &calendar, later);
+        assert_eq!(due_later.len(), 1);
+        assert_eq!(due_later[0].0, NaiveDate::from_ymd(2019, 12, 4));
+        assert_eq!(until, Some(NaiveDate::from_ymd(2019, 12, 4)));
+    }
+
+    #[test]
+    fn retries_only_the_failed_occurrences() {
+        let calendar = Calendar {
+            timezone: Prague,
+            beginning_of_week: 1,
+        };
+        let weekdays = recurrence(Frequency::Weekly, 1, vec![1, 2, 3, 4, 5]);
+        let now = Utc.ymd(2019, 12, 5).and_hms(8, 0, 0);
+        let failed: BTreeSet<_> = vec![NaiveDate::from_ymd(2019, 12, 2)].into_iter().collect();
+
+        let (due_now, until) = due(
+            &weekdays,
+            Some(NaiveDate::from_ymd(2019, 12, 3)),
+            &failed,
+            &calendar,
+            now,
+        );
+
+        let dates: Vec<_> = due_now.iter().map(|(date, _)| *date).collect();
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd(2019, 12, 2),
+                NaiveDate::from_ymd(2019, 12, 4)
+            ]
+        );
+        assert_eq!(due_now[0].1, Utc.ymd(2019, 12, 2).and_hms(8, 30, 0));
+        assert_eq!(until, Some(NaiveDate::from_ymd(2019, 12, 4)));
+    }
+}
@@ -0,0 +1,340 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::calendar::{Calendar, Period};
+use crate::error::{Error, ValidationError};
+use crate::models::{Project, TimeEntry};
+use crate::rounding::RoundingRules;
+use crate::sync::server;
+use crate::toggl_api::{models::Id, TogglApi};
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Grouping {
+    Project,
+    Day,
+    Week,
+    Description,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct ReportGroup {
+    pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<Id>,
+    pub seconds: u64,
+    pub percentage: f64,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct Report {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub group_by: Grouping,
+    pub total_seconds: u64,
+    pub groups: Vec<ReportGroup>,
+}
+
+const NO_PROJECT: &str = "No project";
+
+/// Fetches the time entries of the user in the given range and summarizes them.
+pub fn fetch_summary<Tz: TimeZone>(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    group_by: Grouping,
+    calendar: &Calendar<Tz>,
+    rounding: &RoundingRules,
+    api: &TogglApi,
+) -> Result<Report, Error> {
+    if from >= to {
+        return Err(Error::Validation(vec![ValidationError {
+            field: Some("to".to_string()),
+            reason: "The end of the range must be after its beginning.".to_string(),
+        }]));
+    }
+
+    let time_entries: Vec<_> = server::fetch_time_entries_reaching_into(from, to, api)?
+        .into_iter()
+        .map(|te| rounding.in_output(te))
+        .collect();
+    let projects = match group_by {
+        Grouping::Project => server::fetch_all_projects(api)?,
+        _ => vec![],
+    };
+
+    Ok(summarize(
+        &time_entries,
+        &projects,
+        from,
+        to,
+        group_by,
+        calendar,
+        Utc::now(),
+    ))
+}
+
+/// Sums up the durations of the time entries in the given range. Running time entries
+/// are counted until `now`. Time entries which reach out of the range are clipped, and
+/// when grouping by days or weeks, the time entries are split at the midnight.
+pub fn summarize<Tz: TimeZone>(
+    time_entries: &[TimeEntry],
+    projects: &[Project],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    group_by: Grouping,
+    calendar: &Calendar<Tz>,
+    now: DateTime<Utc>,
+) -> Report {
+    let mut totals: HashMap<(String, Option<Id>), u64> = HashMap::new();
+
+    for te in time_entries
+        .iter()
+        .filter(|te| te.server_deleted_at.is_none())
+    {
+        let end = te.start + Duration::seconds(tracked_seconds(te, now) as i64);
+        let (start, end) = (std::cmp::max(te.start, from), std::cmp::min(end, to));
+        if start >= end {
+            continue;
+        }
+
+        match group_by {
+            Grouping::Project => {
+                let key = te
+                    .project_id
+                    .and_then(|id| projects.iter().find(|project| project.id == id))
+                    .map(|project| project.name.clone())
+                    .unwrap_or_else(|| NO_PROJECT.to_string());
+                add(
+                    &mut totals,
+                    (key, te.project_id),
+                    seconds_between(start, end),
+                );
+            }
+            Grouping::Description => {
+                add(
+                    &mut totals,
+                    (te.description.clone(), None),
+                    seconds_between(start, end),
+                );
+            }
+            Grouping::Day | Grouping::Week => {
+                let period = match group_by {
+                    Grouping::Week => Period::Week,
+                    _ => Period::Day,
+                };
+                let mut cursor = start;
+                while cursor < end {
+                    let bucket = calendar.bucket_of(cursor, period);
+                    let next = std::cmp::min(calendar.next_bucket_start(bucket, period), end);
+                    add(
+                        &mut totals,
+                        (bucket.format("%Y-%m-%d").to_string(), None),
+                        seconds_between(cursor, next),
+                    );
+                    cursor = next;
+                }
+            }
+        }
+    }
+
+    let total_seconds = totals.values().sum();
+    let mut groups: Vec<_> = totals
+        .into_iter()
+        .map(|((key, project_id), seconds)| ReportGroup {
+            key,
+            project_id,
+            seconds,
+            percentage: percentage(seconds, total_seconds),
+        })
+        .collect();
+
+    match group_by {
+        Grouping::Day | Grouping::Week => groups.sort_by(|a, b| a.key.cmp(&b.key)),
+        _ => groups.sort_by(|a, b| b.seconds.cmp(&a.seconds).then(a.key.cmp(&b.key))),
+    }
+
+    Report {
+        from,
+        to,
+        group_by,
+        total_seconds,
+        groups,
+    }
+}
+
+fn tracked_seconds(te: &TimeEntry, now: DateTime<Utc>) -> u64 {
+    te.duration.unwrap_or_else(|| te.elapsed_seconds_until(now))
+}
+
+fn add(totals: &mut HashMap<(String, Option<Id>), u64>, key: (String, Option<Id>), seconds: u64) {
+    *totals.entry(key).or_insert(0) += seconds;
+}
+
+fn seconds_between(start: DateTime<Utc>, end: DateTime<Utc>) -> u64 {
+    std::cmp::max(end.signed_duration_since(start).num_seconds(), 0) as u64
+}
+
+fn percentage(seconds: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (seconds as f64 * 10000.0 / total as f64).round() / 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{summarize, Calendar, Grouping};
+    use crate::models::{fixtures, Project, TimeEntry};
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, Duration, FixedOffset, TimeZone, Utc};
+
+    fn time_entry(
+        project_id: Option<Id>,
+        start: DateTime<Utc>,
+        duration: Option<u64>,
+    ) -> TimeEntry {
+        TimeEntry {
+            project_id,
+            ..fixtures::time_entry(1, start, duration)
+        }
+    }
+
+    fn project(id: Id, name: &str) -> Project {
+        fixtures::project(id, name)
+    }
+
+    fn utc() -> Calendar<FixedOffset> {
+        Calendar {
+            timezone: FixedOffset::east(0),
+            beginning_of_week: 1,
+        }
+    }
+
+    fn from() -> DateTime<Utc> {
+        Utc.ymd(2019, 12, 1).and_hms(0, 0, 0)
+    }
+
+    fn to() -> DateTime<Utc> {
+        Utc.ymd(2019, 12, 31).and_hms(0, 0, 0)
+    }
+
+    #[test]
+    fn groups_by_project_with_percentages() {
+        let start = Utc.ymd(2019, 12, 10).and_hms(9, 0, 0);
+        let entries = vec![
+            time_entry(Some(1), start, Some(3 * 3600)),
+            time_entry(None, start, Some(3600)),
+        ];
+        let projects = vec![project(1, "Utopia")];
+
+        let report = summarize(
+            &entries,
+            &projects,
+            from(),
+            to(),
+            Grouping::Project,
+            &utc(),
+            to(),
+        );
+
+        assert_eq!(report.total_seconds, 4 * 3600);
+        assert_eq!(report.groups[0].key, "Utopia");
+        assert_eq!(report.groups[0].project_id, Some(1));
+        assert_eq!(report.groups[0].percentage, 75.0);
+        assert_eq!(report.groups[1].key, "No project");
+        assert_eq!(report.groups[1].percentage, 25.0);
+    }
+
+    #[test]
+    fn counts_running_entries_until_now() {
+        let start = Utc.ymd(2019, 12, 10).and_hms(9, 0, 0);
+        let now = start + Duration::minutes(30);
+        let entries = vec![time_entry(None, start, None)];
+
+        let report = summarize(
+            &entries,
+            &[],
+            from(),
+            to(),
+            Grouping::Description,
+            &utc(),
+            now,
+        );
+
+        assert_eq!(report.total_seconds, 1800);
+        assert_eq!(report.groups[0].key, "Work");
+    }
+
+    #[test]
+    fn splits_entries_at_local_midnight() {
+        let calendar = Calendar {
+            timezone: FixedOffset::east(3600),
+            beginning_of_week: 1,
+        };
+        // 22:00 - 01:00 in UTC+1
+        let entries = vec![time_entry(
+            None,
+            Utc.ymd(2019, 12, 10).and_hms(21, 0, 0),
+            Some(3 * 3600),
+        )];
+
+        let report = summarize(&entries, &[], from(), to(), Grouping::Day, &calendar, to());
+
+        assert_eq!(report.groups.len(), 2);
+        assert_eq!(report.groups[0].key, "2019-12-10");
+        assert_eq!(report.groups[0].seconds, 2 * 3600);
+        assert_eq!(report.groups[1].key, "2019-12-11");
+        assert_eq!(report.groups[1].seconds, 3600);
+    }
+
+    #[test]
+    fn respects_the_beginning_of_the_week() {
+        // Sunday 2019-12-15
+        let entries = vec![time_entry(
+            None,
+            Utc.ymd(2019, 12, 15).and_hms(10, 0, 0),
+            Some(3600),
+        )];
+
+        let monday = summarize(&entries, &[], from(), to(), Grouping::Week, &utc(), to());
+        let sunday_calendar = Calendar {
+            timezone: FixedOffset::east(0),
+            beginning_of_week: 0,
+        };
+        let sunday = summarize(
+            &entries,
+            &[],
+            from(),
+            to(),
+            Grouping::Week,
+            &sunday_calendar,
+            to(),
+        );
+
+        assert_eq!(monday.groups[0].key, "2019-12-09");
+        assert_eq!(sunday.groups[0].key, "2019-12-15");
+    }
+
+    #[test]
+    fn clips_entries_to_the_range() {
+        let entries = vec![time_entry(
+            None,
+            Utc.ymd(2019, 11, 30).and_hms(23, 0, 0),
+            Some(2 * 3600),
+        )];
+
+        let report = summarize(
+            &entries,
+            &[],
+            from(),
+            to(),
+            Grouping::Description,
+            &utc(),
+            to(),
+        );
+
+        assert_eq!(report.total_seconds, 3600);
+    }
+}
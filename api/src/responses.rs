@@ -1,9 +1,27 @@
-use actix_web::HttpResponse;
+use actix_web::{
+    http::{header, StatusCode},
+    HttpResponse,
+};
 use chrono::{DateTime, Utc};
 use serde::Serialize;
 
-use crate::error::Error;
+use crate::analysis::Analysis;
+use crate::auth::{AuthError, Scheme};
+use crate::auto_stop::Rules;
+use crate::calendar::Buckets;
+use crate::duplicates::DuplicateGroup;
+use crate::error::{Error, ValidationError};
+use crate::goals::{Goal, Progress};
+use crate::history::Revision;
+use crate::import::ImportReport;
 use crate::models::Delta;
+use crate::recurrence::Recurrence;
+use crate::reports::Report;
+use crate::rounding::RoundingRules;
+use crate::search::SearchResults;
+use crate::session::{SessionInfo, SessionToken};
+use crate::suggestions::Suggestion;
+use crate::sync::bulk::EditResult;
 use crate::sync::prelude::SyncOutcome;
 
 #[derive(Serialize)]
@@ -22,10 +40,22 @@ where
     payload: T,
 }
 
+#[derive(Serialize)]
+struct Snapshot {
+    session: SessionToken,
+    #[serde(flatten)]
+    delta: Delta,
+}
+
 #[derive(Serialize)]
 struct ErrorBody {
-    code: u16,
+    code: &'static str,
+    status: u16,
     msg: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<ValidationError>,
 }
 
 /// Creates a meta structure for the standard body template with the current server time.
@@ -49,23 +79,32 @@ where
 }
 
 /// Creates a body with correct meta and payload for the given error.
-fn error(err: Error, start: DateTime<Utc>) -> Body<ErrorBody> {
-    let msg = match &err {
-        Error::ApiError(_, msg) => msg.clone(),
-        Error::NetworkError(reqwest_err) => format!("{:?}", reqwest_err),
+fn error(err: &Error, start: DateTime<Utc>) -> Body<ErrorBody> {
+    let retry_after = match err {
+        Error::RateLimited { retry_after } => *retry_after,
+        _ => None,
     };
 
     Body {
         meta: meta(true, start),
         payload: ErrorBody {
             code: err.code(),
-            msg,
+            status: err.status().as_u16(),
+            msg: err.to_string(),
+            retry_after,
+            errors: err.validation_errors(),
         },
     }
 }
 
-pub fn snapshot_success(data: Delta, start: DateTime<Utc>) -> HttpResponse {
-    let body = ok(data, start);
+pub fn snapshot_success(data: Delta, session: SessionToken, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(
+        Snapshot {
+            session,
+            delta: data,
+        },
+        start,
+    );
     HttpResponse::Ok().json(body)
 }
 
@@ -74,15 +113,134 @@ pub fn sync_success(data: SyncOutcome, start: DateTime<Utc>) -> HttpResponse {
     HttpResponse::Ok().json(body)
 }
 
+pub fn session_success(session: SessionToken, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(session, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn sessions_success(sessions: Vec<SessionInfo>, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(sessions, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn session_revoked(start: DateTime<Utc>) -> HttpResponse {
+    let body = ok((), start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn report_success(report: Report, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(report, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn import_success(report: ImportReport, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(report, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn analysis_success(analysis: Analysis, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(analysis, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn suggestions_success(suggestions: Vec<Suggestion>, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(suggestions, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn history_success<T: Serialize>(
+    revisions: Vec<Revision<T>>,
+    start: DateTime<Utc>,
+) -> HttpResponse {
+    let body = ok(revisions, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn auto_stop_success(rules: Rules, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(rules, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn rounding_success(rules: RoundingRules, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(rules, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn calendar_success(buckets: Buckets, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(buckets, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn goals_success(goals: Vec<Goal>, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(goals, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn progress_success(progress: Vec<Progress>, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(progress, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn recurrences_success(recurrences: Vec<Recurrence>, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(recurrences, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn recurrence_success(recurrence: Recurrence, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(recurrence, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn recurrence_deleted(start: DateTime<Utc>) -> HttpResponse {
+    let body = ok((), start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn search_success(results: SearchResults, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(results, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn duplicates_success(groups: Vec<DuplicateGroup>, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(groups, start);
+    HttpResponse::Ok().json(body)
+}
+
+pub fn bulk_edit_success(results: Vec<EditResult>, start: DateTime<Utc>) -> HttpResponse {
+    let body = ok(results, start);
+    HttpResponse::Ok().json(body)
+}
+
 pub fn something_went_wrong(err: Error, start: DateTime<Utc>) -> HttpResponse {
-    let body = error(err, start);
-    HttpResponse::InternalServerError().json(body)
+    match err {
+        Error::Auth(auth_err) => authentication_failed(auth_err, Some(Scheme::Bearer), start),
+        _ => {
+            let mut res = HttpResponse::build(err.status());
+            if let Error::RateLimited {
+                retry_after: Some(seconds),
+            } = err
+            {
+                res.header(header::RETRY_AFTER, seconds.to_string());
+            }
+
+            res.json(error(&err, start))
+        }
+    }
 }
 
-pub fn invalid_credentials(start: DateTime<Utc>) -> HttpResponse {
-    let body = error(
-        Error::ApiError(401, "The credentials you provided are invalid.".to_string()),
-        start,
-    );
-    HttpResponse::Forbidden().json(body)
+/// Creates a response for a failed authentication. Unauthenticated clients are told which
+/// scheme they should use via the `WWW-Authenticate` header.
+pub fn authentication_failed(
+    err: AuthError,
+    scheme: Option<Scheme>,
+    start: DateTime<Utc>,
+) -> HttpResponse {
+    let mut res = HttpResponse::build(err.status());
+    if let Some(scheme) = scheme {
+        if err.status() == StatusCode::UNAUTHORIZED {
+            res.header(header::WWW_AUTHENTICATE, scheme.challenge(err));
+        }
+    }
+
+    res.json(error(&Error::Auth(err), start))
 }
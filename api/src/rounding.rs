@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::error::{Error, ValidationError};
+use crate::models::TimeEntry;
+use crate::toggl_api::models::Id;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Up,
+    Down,
+    Nearest,
+}
+
+/// Rounds the durations of time entries to multiples of the given number of minutes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Rounding {
+    pub direction: Direction,
+    pub minutes: u64,
+    /// Keep the tracked durations as they are and round them only in reports and exports.
+    #[serde(default)]
+    pub only_in_output: bool,
+}
+
+/// The rounding of the user, which can be overridden in some of their workspaces.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RoundingRules {
+    pub default: Option<Rounding>,
+    #[serde(default)]
+    pub workspaces: HashMap<Id, Rounding>,
+}
+
+#[derive(Default)]
+pub struct Roundings {
+    users: Mutex<HashMap<Id, RoundingRules>>,
+}
+
+impl Rounding {
+    pub fn round(&self, seconds: u64) -> u64 {
+        let bucket = self.minutes * 60;
+        let rounded = match self.direction {
+            Direction::Down => seconds / bucket,
+            Direction::Up => seconds.div_ceil(bucket),
+            Direction::Nearest => (seconds + bucket / 2) / bucket,
+        };
+
+        rounded * bucket
+    }
+}
+
+impl RoundingRules {
+    fn for_workspace(&self, workspace_id: Id) -> Option<&Rounding> {
+        self.workspaces.get(&workspace_id).or(self.default.as_ref())
+    }
+
+    /// The rounding which applies when a time entry in the workspace is stopped.
+    pub fn on_stop(&self, workspace_id: Id) -> Option<&Rounding> {
+        self.for_workspace(workspace_id)
+            .filter(|rounding| !rounding.only_in_output)
+    }
+
+    /// Rounds the duration of a stopped time entry for reports and exports. Running time
+    /// entries are left as they are.
+    pub fn in_output(&self, te: TimeEntry) -> TimeEntry {
+        match (te.duration, self.for_workspace(te.workspace_id)) {
+            (Some(duration), Some(rounding)) => TimeEntry {
+                duration: Some(rounding.round(duration)),
+                ..te
+            },
+            _ => te,
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let errors: Vec<_> = self
+            .default
+            .iter()
+            .map(|rounding| ("default".to_string(), rounding))
+            .chain(
+                self.workspaces
+                    .iter()
+                    .map(|(id, rounding)| (format!("workspaces.{}", id), rounding)),
+            )
+            .filter(|(_, rounding)| rounding.minutes == 0 || rounding.minutes > 24 * 60)
+            .map(|(field, _)| ValidationError {
+                field: Some(format!("{}.minutes", field)),
+                reason: "The rounding must be between 1 minute and 24 hours.".to_string(),
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(errors))
+        }
+    }
+}
+
+impl Roundings {
+    pub fn rules(&self, user_id: Id) -> RoundingRules {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set_rules(&self, user_id: Id, rules: RoundingRules) -> Result<RoundingRules, Error> {
+        rules.validate()?;
+        self.users.lock().unwrap().insert(user_id, rules.clone());
+
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Rounding, RoundingRules};
+    use crate::models::{fixtures, TimeEntry};
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn rounding(direction: Direction, only_in_output: bool) -> Rounding {
+        Rounding {
+            direction,
+            minutes: 15,
+            only_in_output,
+        }
+    }
+
+    #[test]
+    fn rounds_to_buckets() {
+        let minutes = |minutes: u64| minutes * 60;
+
+        assert_eq!(
+            rounding(Direction::Up, false).round(minutes(16)),
+            minutes(30)
+        );
+        assert_eq!(
+            rounding(Direction::Up, false).round(minutes(15)),
+            minutes(15)
+        );
+        assert_eq!(
+            rounding(Direction::Down, false).round(minutes(29)),
+            minutes(15)
+        );
+        assert_eq!(
+            rounding(Direction::Nearest, false).round(minutes(22)),
+            minutes(15)
+        );
+        assert_eq!(
+            rounding(Direction::Nearest, false).round(minutes(22) + 30),
+            minutes(30)
+        );
+        assert_eq!(rounding(Direction::Down, false).round(minutes(10)), 0);
+    }
+
+    #[test]
+    fn workspace_rules_override_the_default() {
+        let rules = RoundingRules {
+            default: Some(rounding(Direction::Up, false)),
+            workspaces: vec![(2, rounding(Direction::Down, true))]
+                .into_iter()
+                .collect(),
+        };
+
+        assert_eq!(
+            rules.on_stop(1).map(|rounding| rounding.direction),
+            Some(Direction::Up)
+        );
+        assert_eq!(rules.on_stop(2), None);
+
+        let start = Utc.ymd(2019, 12, 10).and_hms(9, 0, 0);
+        let te = TimeEntry {
+            workspace_id: 2,
+            ..fixtures::time_entry(1, start, Some(20 * 60))
+        };
+        assert_eq!(rules.in_output(te.clone()).duration, Some(15 * 60));
+
+        let running = TimeEntry {
+            duration: None,
+            ..te.clone()
+        };
+        let stop_at = start + Duration::minutes(20);
+        let stopped = running.stop_at(stop_at, rules.on_stop(1));
+        assert_eq!(stopped.duration, Some(30 * 60));
+        assert_eq!(stopped.start, start);
+        assert_eq!(
+            running.stop_at(stop_at, rules.on_stop(2)).duration,
+            Some(20 * 60)
+        );
+        assert_eq!(
+            rules
+                .in_output(TimeEntry {
+                    duration: None,
+                    ..te
+                })
+                .duration,
+            None
+        );
+    }
+
+    #[test]
+    fn cuts_short_a_time_entry_rounded_up_into_the_future() {
+        let rounding = rounding(Direction::Up, false);
+        let start = Utc::now() - Duration::minutes(16);
+        let running = fixtures::time_entry(1, start, None);
+
+        let stopped = running.stop(Some(&rounding));
+
+        assert_eq!(stopped.start, start);
+        assert!(stopped.duration >= Some(16 * 60));
+        assert!(stopped.start + Duration::seconds(stopped.duration.unwrap() as i64) <= Utc::now());
+    }
+}
@@ -0,0 +1,435 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::error::{Error, ValidationError};
+use crate::models::{Delta, Entity, Project, TimeEntry};
+use crate::sync;
+use crate::sync::prelude::SyncOutcome;
+use crate::toggl_api::{models::Id, TogglApi};
+
+const MAX_PER_PAGE: usize = 100;
+
+/// What the user is looking for. Every word of the text has to be the beginning of a word
+/// in the description of the time entry or in the name of its project.
+pub struct Query {
+    pub text: String,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub project_id: Option<Id>,
+    pub min_duration: Option<u64>,
+    pub max_duration: Option<u64>,
+    pub running: Option<bool>,
+    pub page: usize,
+    pub per_page: usize,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct SearchResults {
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub time_entries: Vec<TimeEntry>,
+}
+
+/// The words of the descriptions and the project names of the time entries of one user.
+#[derive(Default)]
+pub struct UserIndex {
+    time_entries: HashMap<Id, TimeEntry>,
+    project_names: HashMap<Id, String>,
+    descriptions: BTreeMap<String, HashSet<Id>>,
+    projects: BTreeMap<String, HashSet<Id>>,
+}
+
+/// The search indexes of the users. The index of a user is built from the snapshot and kept
+/// up to date with the changes which go through the proxy.
+#[derive(Default)]
+pub struct Search {
+    users: Mutex<HashMap<Id, UserIndex>>,
+}
+
+impl UserIndex {
+    pub fn update(&mut self, delta: &Delta) {
+        for project in delta.projects.iter().flatten() {
+            self.update_project(project);
+        }
+        for te in delta.time_entries.iter().flatten() {
+            self.update_time_entry(te);
+        }
+    }
+
+    fn update_project(&mut self, project: &Project) {
+        if let Some(name) = self.project_names.remove(&project.id) {
+            remove_words(&mut self.projects, &name, project.id);
+        }
+        if project.exists_on_server() && !project.is_deleted() {
+            add_words(&mut self.projects, &project.name, project.id);
+            self.project_names.insert(project.id, project.name.clone());
+        }
+    }
+
+    fn update_time_entry(&mut self, te: &TimeEntry) {
+        if let Some(old) = self.time_entries.remove(&te.id) {
+            remove_words(&mut self.descriptions, &old.description, te.id);
+        }
+        if te.exists_on_server() && !te.is_deleted() {
+            add_words(&mut self.descriptions, &te.description, te.id);
+            self.time_entries.insert(te.id, te.clone());
+        }
+    }
+
+    pub fn search(&self, query: &Query, now: DateTime<Utc>) -> SearchResults {
+        let mut found: Vec<&TimeEntry> = self
+            .candidates(&query.text)
+            .filter(|te| matches_filters(te, query, now))
+            .collect();
+        found.sort_by(|a, b| b.start.cmp(&a.start).then(b.id.cmp(&a.id)));
+
+        SearchResults {
+            total: found.len(),
+            page: query.page,
+            per_page: query.per_page,
+            time_entries: found
+                .into_iter()
+                .skip((query.page - 1).saturating_mul(query.per_page))
+                .take(query.per_page)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The time entries which match every word of the text, found in the index without
+    /// looking at the time entries one by one.
+    fn candidates<'a>(&'a self, text: &str) -> Box<dyn Iterator<Item = &'a TimeEntry> + 'a> {
+        let mut matching: Option<HashSet<Id>> = None;
+        for word in words(text) {
+            let mut ids: HashSet<Id> = starting_with(&self.descriptions, &word)
+                .flatten()
+                .cloned()
+                .collect();
+            let projects: HashSet<Id> = starting_with(&self.projects, &word)
+                .flatten()
+                .cloned()
+                .collect();
+            if !projects.is_empty() {
+                ids.extend(
+                    self.time_entries
+                        .values()
+                        .filter(|te| te.project_id.is_some_and(|id| projects.contains(&id)))
+                        .map(|te| te.id),
+                );
+            }
+
+            matching = Some(match matching {
+                Some(matching) => matching.intersection(&ids).cloned().collect(),
+                None => ids,
+            });
+        }
+
+        match matching {
+            Some(ids) => Box::new(
+                ids.into_iter()
+                    .filter_map(move |id| self.time_entries.get(&id)),
+            ),
+            None => Box::new(self.time_entries.values()),
+        }
+    }
+}
+
+impl Search {
+    pub fn index_delta(&self, user_id: Id, delta: &Delta) {
+        if let Some(index) = self.users.lock().unwrap().get_mut(&user_id) {
+            index.update(delta);
+        }
+    }
+
+    /// Indexes the changes from the client which Toggl accepted and the changes which were
+    /// sent back to the client.
+    pub fn index_outcome(&self, user_id: Id, client_delta: &Delta, outcome: &SyncOutcome) {
+        self.index_delta(user_id, &outcome.accepted_from(client_delta));
+        self.index_delta(user_id, &outcome.entities());
+    }
+
+    /// Searches the time entries of the user. The index is built from the whole history
+    /// of the user when the user hasn't been seen since the proxy started.
+    pub fn search(
+        &self,
+        user_id: Id,
+        query: &Query,
+        api: &TogglApi,
+    ) -> Result<SearchResults, Error> {
+        validate(query)?;
+
+        if !self.users.lock().unwrap().contains_key(&user_id) {
+            let snapshot = sync::with_history(sync::fetch_snapshot(api)?, api)?;
+            self.build(user_id, &snapshot);
+        }
+
+        Ok(self
+            .users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .map(|index| index.search(query, Utc::now()))
+            .unwrap_or(SearchResults {
+                total: 0,
+                page: query.page,
+                per_page: query.per_page,
+                time_entries: vec![],
+            }))
+    }
+
+    /// Replaces the index of the user with the index of the snapshot, which should include
+    /// the whole history of the user.
+    pub fn build(&self, user_id: Id, snapshot: &Delta) {
+        let mut index = UserIndex::default();
+        index.update(snapshot);
+        self.users.lock().unwrap().insert(user_id, index);
+    }
+
+    /// Drops the index of the user, it's built again with the next search.
+    pub fn forget(&self, user_id: Id) {
+        self.users.lock().unwrap().remove(&user_id);
+    }
+}
+
+fn matches_filters(te: &TimeEntry, query: &Query, now: DateTime<Utc>) -> bool {
+    let duration = te.duration.unwrap_or_else(|| te.elapsed_seconds_until(now));
+
+    query.from.is_none_or(|from| te.start >= from)
+        && query.to.is_none_or(|to| te.start < to)
+        && query
+            .project_id
+            .is_none_or(|project_id| te.project_id == Some(project_id))
+        && query.min_duration.is_none_or(|min| duration >= min)
+        && query.max_duration.is_none_or(|max| duration <= max)
+        && query
+            .running
+            .is_none_or(|running| te.is_running() == running)
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn add_words(index: &mut BTreeMap<String, HashSet<Id>>, text: &str, id: Id) {
+    for word in words(text) {
+        index.entry(word).or_default().insert(id);
+    }
+}
+
+fn remove_words(index: &mut BTreeMap<String, HashSet<Id>>, text: &str, id: Id) {
+    for word in words(text) {
+        if let Some(ids) = index.get_mut(&word) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                index.remove(&word);
+            }
+        }
+    }
+}
+
+fn starting_with<'a>(
+    index: &'a BTreeMap<String, HashSet<Id>>,
+    prefix: &'a str,
+) -> impl Iterator<Item = &'a HashSet<Id>> + 'a {
+    index
+        .range(prefix.to_string()..)
+        .take_while(move |(word, _)| word.starts_with(prefix))
+        .map(|(_, ids)| ids)
+}
+
+fn validate(query: &Query) -> Result<(), Error> {
+    let mut errors = vec![];
+    if query.page == 0 {
+        errors.push(ValidationError {
+            field: Some("page".to_string()),
+            reason: "The pages are numbered from 1.".to_string(),
+        });
+    }
+    if query.per_page == 0 || query.per_page > MAX_PER_PAGE {
+        errors.push(ValidationError {
+            field: Some("per_page".to_string()),
+            reason: format!("There can be 1 to {} results per page.", MAX_PER_PAGE),
+        });
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Query, Search, UserIndex};
+    use crate::error::Error;
+    use crate::models::{fixtures, Delta, Project, TimeEntry};
+    use crate::sync::prelude::{failed, SyncOutcome};
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn at(month: u32, day: u32) -> DateTime<Utc> {
+        Utc.ymd(2019, month, day).and_hms(9, 0, 0)
+    }
+
+    fn time_entry(
+        id: Id,
+        description: &str,
+        project_id: Option<Id>,
+        start: DateTime<Utc>,
+    ) -> TimeEntry {
+        TimeEntry {
+            description: description.to_string(),
+            project_id,
+            ..fixtures::time_entry(id, start, Some(3600))
+        }
+    }
+
+    fn project(id: Id, name: &str) -> Project {
+        Project {
+            at: at(1, 1),
+            ..fixtures::project(id, name)
+        }
+    }
+
+    fn query(text: &str) -> Query {
+        Query {
+            text: text.to_string(),
+            from: None,
+            to: None,
+            project_id: None,
+            min_duration: None,
+            max_duration: None,
+            running: None,
+            page: 1,
+            per_page: 20,
+        }
+    }
+
+    fn index() -> UserIndex {
+        let mut index = UserIndex::default();
+        index.update(&Delta {
+            user: None,
+            projects: Some(vec![project(10, "ACME Corp")]),
+            time_entries: Some(vec![
+                time_entry(1, "Kick-off meeting", Some(10), at(3, 12)),
+                time_entry(2, "Meeting with the team", None, at(3, 20)),
+                time_entry(3, "Meetup talk", Some(10), at(5, 2)),
+            ]),
+            favourites: None,
+        });
+
+        index
+    }
+
+    #[test]
+    fn finds_words_in_descriptions_and_project_names() {
+        let index = index();
+        let now = at(6, 1);
+
+        let ids = |query: &Query| -> Vec<Id> {
+            index
+                .search(query, now)
+                .time_entries
+                .iter()
+                .map(|te| te.id)
+                .collect()
+        };
+
+        assert_eq!(ids(&query("meet")), vec![3, 2, 1]);
+        assert_eq!(ids(&query("acme MEETING")), vec![1]);
+        assert_eq!(
+            ids(&Query {
+                from: Some(at(3, 1)),
+                to: Some(at(4, 1)),
+                ..query("meeting")
+            }),
+            vec![2, 1]
+        );
+        assert!(ids(&query("meetings")).is_empty());
+
+        let page = index.search(
+            &Query {
+                page: 2,
+                per_page: 2,
+                ..query("")
+            },
+            now,
+        );
+        assert_eq!(page.total, 3);
+        assert_eq!(page.time_entries.len(), 1);
+
+        let far_away = Query {
+            page: usize::MAX,
+            ..query("")
+        };
+        assert!(index.search(&far_away, now).time_entries.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_index_up_to_date() {
+        let mut index = index();
+        index.update(&Delta {
+            user: None,
+            projects: Some(vec![project(10, "Globex")]),
+            time_entries: Some(vec![
+                TimeEntry {
+                    server_deleted_at: Some(at(6, 1)),
+                    ..time_entry(2, "Meeting with the team", None, at(3, 20))
+                },
+                TimeEntry {
+                    duration: None,
+                    ..time_entry(4, "Support", Some(10), at(6, 1))
+                },
+            ]),
+            favourites: None,
+        });
+
+        let now = at(6, 2);
+        assert_eq!(index.search(&query("acme"), now).total, 0);
+        assert_eq!(index.search(&query("globex"), now).total, 3);
+        assert_eq!(index.search(&query("team"), now).total, 0);
+        assert_eq!(
+            index
+                .search(
+                    &Query {
+                        running: Some(true),
+                        min_duration: Some(2 * 3600),
+                        ..query("")
+                    },
+                    now,
+                )
+                .time_entries[0]
+                .id,
+            4
+        );
+    }
+
+    #[test]
+    fn indexes_only_the_changes_which_toggl_accepted() {
+        let search = Search::default();
+        search.build(7, &Delta::default());
+
+        let client_delta = Delta {
+            time_entries: Some(vec![
+                time_entry(1, "Accepted", None, at(3, 1)),
+                time_entry(2, "Rejected", None, at(3, 1)),
+            ]),
+            ..Delta::default()
+        };
+        let mut outcome = SyncOutcome::convert(Delta::default());
+        outcome.time_entries = vec![failed(2, Error::Timeout)];
+        search.index_outcome(7, &client_delta, &outcome);
+
+        let users = search.users.lock().unwrap();
+        let index = &users[&7];
+        assert_eq!(index.search(&query("accepted"), at(6, 1)).total, 1);
+        assert_eq!(index.search(&query("rejected"), at(6, 1)).total, 0);
+    }
+}
@@ -0,0 +1,280 @@
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::toggl_api::models::{ApiToken, Id};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SESSION_LIFETIME_DAYS: i64 = 30;
+
+/// A session issued by the proxy. The client only ever sees the signed token,
+/// the Toggl API token never leaves the server.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub user_id: Id,
+    pub api_token: ApiToken,
+    pub device: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SessionToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub id: String,
+    pub device: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub struct Sessions {
+    secret: Vec<u8>,
+    lifetime: Duration,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Default for Sessions {
+    fn default() -> Sessions {
+        Sessions::with_secret(random_bytes(32), Duration::days(SESSION_LIFETIME_DAYS))
+    }
+}
+
+impl Sessions {
+    pub fn with_secret(secret: Vec<u8>, lifetime: Duration) -> Sessions {
+        Sessions {
+            secret,
+            lifetime,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a new session for the given user and returns the signed token for the client.
+    pub fn issue(&self, user_id: Id, api_token: ApiToken, device: Option<String>) -> SessionToken {
+        let now = Utc::now();
+        let session = Session {
+            id: base64::encode_config(&random_bytes(24), base64::URL_SAFE_NO_PAD),
+            user_id,
+            api_token,
+            device,
+            created_at: now,
+            expires_at: now + self.lifetime,
+        };
+
+        let token = self.token_for(&session);
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.expires_at > now);
+        sessions.insert(session.id.clone(), session);
+
+        token
+    }
+
+    /// Returns the session the token belongs to if the token is genuine, it hasn't expired
+    /// and the session hasn't been revoked.
+    pub fn verify(&self, token: &str) -> Option<Session> {
+        let (id, expires_at) = self.decode(token)?;
+        let mut sessions = self.sessions.lock().unwrap();
+        if expires_at <= Utc::now() {
+            sessions.remove(id);
+            return None;
+        }
+
+        sessions
+            .get(id)
+            .filter(|session| session.expires_at.timestamp() == expires_at.timestamp())
+            .cloned()
+    }
+
+    /// Replaces the session with a new one with a fresh expiration date. The old token
+    /// stops working immediately.
+    pub fn refresh(&self, session: &Session) -> Option<SessionToken> {
+        self.sessions.lock().unwrap().remove(&session.id)?;
+
+        Some(self.issue(
+            session.user_id,
+            session.api_token.clone(),
+            session.device.clone(),
+        ))
+    }
+
+    /// Revokes a session of the user, e.g., when the user loses one of their devices.
+    pub fn revoke(&self, user_id: Id, session_id: &str) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        let belongs_to_user =
+            matches!(sessions.get(session_id), Some(session) if session.user_id == user_id);
+
+        if belongs_to_user {
+            sessions.remove(session_id);
+        }
+
+        belongs_to_user
+    }
+
+    pub fn list(&self, user_id: Id) -> Vec<SessionInfo> {
+        let now = Utc::now();
+        let mut infos: Vec<_> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|session| session.user_id == user_id && session.expires_at > now)
+            .map(|session| SessionInfo {
+                id: session.id.clone(),
+                device: session.device.clone(),
+                created_at: session.created_at,
+                expires_at: session.expires_at,
+            })
+            .collect();
+
+        infos.sort_by_key(|info| info.created_at);
+        infos
+    }
+
+    fn token_for(&self, session: &Session) -> SessionToken {
+        let payload = format!("{}.{}", session.id, session.expires_at.timestamp());
+        let signature = base64::encode_config(&self.sign(&payload), base64::URL_SAFE_NO_PAD);
+
+        SessionToken {
+            token: format!("{}.{}", payload, signature),
+            expires_at: session.expires_at,
+        }
+    }
+
+    fn decode<'a>(&self, token: &'a str) -> Option<(&'a str, DateTime<Utc>)> {
+        let (payload, signature) = match token.rfind('.') {
+            Some(index) => (&token[..index], &token[index + 1..]),
+            None => return None,
+        };
+
+        let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD).ok()?;
+        let mut mac = self.mac();
+        mac.input(payload.as_bytes());
+        mac.verify(&signature).ok()?;
+
+        match payload.split('.').collect::<Vec<_>>().as_slice() {
+            [id, expires_at] => {
+                let timestamp = expires_at.parse::<i64>().ok()?;
+                Some((
+                    id,
+                    DateTime::from_utc(chrono::NaiveDateTime::from_timestamp(timestamp, 0), Utc),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn sign(&self, payload: &str) -> Vec<u8> {
+        let mut mac = self.mac();
+        mac.input(payload.as_bytes());
+        mac.result().code().to_vec()
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_varkey(&self.secret).expect("HMAC accepts keys of any length.")
+    }
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sessions;
+    use chrono::Duration;
+
+    fn sessions() -> Sessions {
+        Sessions::with_secret(b"secret".to_vec(), Duration::days(1))
+    }
+
+    #[test]
+    fn verifies_issued_token() {
+        let sessions = sessions();
+        let token = sessions.issue(1, "api_token".to_string(), None);
+
+        let session = sessions.verify(&token.token).unwrap();
+
+        assert_eq!(session.user_id, 1);
+        assert_eq!(session.api_token, "api_token");
+    }
+
+    #[test]
+    fn rejects_tampered_token() {
+        let sessions = sessions();
+        let token = sessions.issue(1, "api_token".to_string(), None);
+        let tampered = token.token.replacen('.', "x.", 1);
+
+        assert!(sessions.verify(&tampered).is_none());
+    }
+
+    #[test]
+    fn rejects_token_signed_with_a_different_secret() {
+        let token = sessions().issue(1, "api_token".to_string(), None);
+        let other = Sessions::with_secret(b"other".to_vec(), Duration::days(1));
+
+        assert!(other.verify(&token.token).is_none());
+    }
+
+    #[test]
+    fn rejects_expired_token() {
+        let sessions = Sessions::with_secret(b"secret".to_vec(), Duration::seconds(-1));
+        let token = sessions.issue(1, "api_token".to_string(), None);
+
+        assert!(sessions.verify(&token.token).is_none());
+    }
+
+    #[test]
+    fn forgets_expired_sessions() {
+        let sessions = Sessions::with_secret(b"secret".to_vec(), Duration::seconds(-1));
+        sessions.issue(1, "api_token".to_string(), None);
+        let token = sessions.issue(1, "api_token".to_string(), None);
+        assert_eq!(sessions.sessions.lock().unwrap().len(), 1);
+
+        sessions.verify(&token.token);
+        assert!(sessions.sessions.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn refreshing_invalidates_the_old_token() {
+        let sessions = sessions();
+        let old = sessions.issue(1, "api_token".to_string(), None);
+
+        let session = sessions.verify(&old.token).unwrap();
+        let new = sessions.refresh(&session).unwrap();
+
+        assert!(sessions.verify(&old.token).is_none());
+        assert!(sessions.verify(&new.token).is_some());
+    }
+
+    #[test]
+    fn revoked_token_is_rejected() {
+        let sessions = sessions();
+        let token = sessions.issue(1, "api_token".to_string(), None);
+        let id = sessions.verify(&token.token).unwrap().id;
+
+        assert!(sessions.revoke(1, &id));
+        assert!(sessions.verify(&token.token).is_none());
+    }
+
+    #[test]
+    fn cannot_revoke_session_of_another_user() {
+        let sessions = sessions();
+        let token = sessions.issue(1, "api_token".to_string(), None);
+        let id = sessions.verify(&token.token).unwrap().id;
+
+        assert!(!sessions.revoke(2, &id));
+        assert!(sessions.verify(&token.token).is_some());
+    }
+}
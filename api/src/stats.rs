@@ -0,0 +1,361 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::calendar::{Calendar, Period};
+use crate::models::{Delta, Entity, Project, ProjectStats, TimeEntry};
+use crate::sync;
+use crate::sync::prelude::{changed, SyncOutcome, SyncResult};
+use crate::toggl_api::{models::Id, TogglApi};
+
+struct Tracked {
+    project_id: Id,
+    start: DateTime<Utc>,
+    duration: Option<u64>,
+}
+
+/// The running totals of one project. The time of the running time entries is added
+/// when the statistics are read.
+#[derive(Default)]
+struct Totals {
+    total_seconds: u64,
+    entry_count: usize,
+    last_used: Option<DateTime<Utc>>,
+    /// The time tracked in each week by the local date on which the week starts.
+    weeks: HashMap<NaiveDate, u64>,
+}
+
+/// The statistics of the projects of one user, updated with every delta.
+pub struct UserStats {
+    calendar: Calendar<Tz>,
+    projects: HashMap<Id, Project>,
+    time_entries: HashMap<Id, Tracked>,
+    totals: HashMap<Id, Totals>,
+}
+
+/// The statistics of the projects of all the users. They are built from the whole history
+/// of the user and then kept up to date with every sync.
+#[derive(Default)]
+pub struct Statistics {
+    users: Mutex<HashMap<Id, UserStats>>,
+}
+
+impl UserStats {
+    pub fn new(calendar: Calendar<Tz>) -> UserStats {
+        UserStats {
+            calendar,
+            projects: HashMap::new(),
+            time_entries: HashMap::new(),
+            totals: HashMap::new(),
+        }
+    }
+
+    /// Applies the changes and returns the ids of the projects whose statistics changed.
+    pub fn update(&mut self, delta: &Delta) -> HashSet<Id> {
+        if let Some(calendar) = delta
+            .user
+            .as_ref()
+            .and_then(|user| Calendar::of_user(user).ok())
+        {
+            self.calendar = calendar;
+        }
+
+        for project in delta.projects.iter().flatten() {
+            if project.is_deleted() {
+                self.projects.remove(&project.id);
+            } else if project.exists_on_server() {
+                self.projects.insert(project.id, project.clone());
+            }
+        }
+
+        let mut touched = HashSet::new();
+        for te in delta.time_entries.iter().flatten() {
+            if let Some(old) = self.time_entries.remove(&te.id) {
+                self.subtract(te.id, &old);
+                touched.insert(old.project_id);
+            }
+            if let (Some(project_id), true) =
+                (te.project_id, te.exists_on_server() && !te.is_deleted())
+            {
+                let tracked = Tracked {
+                    project_id,
+                    start: te.start,
+                    duration: te.duration,
+                };
+                self.add(&tracked);
+                self.time_entries.insert(te.id, tracked);
+                touched.insert(project_id);
+            }
+        }
+
+        touched
+    }
+
+    pub fn stats(&self, project_id: Id, now: DateTime<Utc>) -> ProjectStats {
+        let this_week = self.calendar.bucket_of(now, Period::Week);
+        let totals = match self.totals.get(&project_id) {
+            Some(totals) => totals,
+            None => return ProjectStats::default(),
+        };
+
+        let running: Vec<&Tracked> = self
+            .time_entries
+            .values()
+            .filter(|tracked| tracked.project_id == project_id && tracked.duration.is_none())
+            .collect();
+        let running_seconds = |in_this_week: bool| -> u64 {
+            running
+                .iter()
+                .filter(|tracked| {
+                    !in_this_week
+                        || self.calendar.bucket_of(tracked.start, Period::Week) == this_week
+                })
+                .map(|tracked| {
+                    std::cmp::max(now.signed_duration_since(tracked.start).num_seconds(), 0) as u64
+                })
+                .sum()
+        };
+
+        ProjectStats {
+            total_seconds: totals.total_seconds + running_seconds(false),
+            this_week_seconds: totals.weeks.get(&this_week).cloned().unwrap_or(0)
+                + running_seconds(true),
+            last_used: totals.last_used,
+            entry_count: totals.entry_count,
+        }
+    }
+
+    fn fill(&self, project: &mut Project, now: DateTime<Utc>) {
+        project.stats = Some(self.stats(project.id, now));
+    }
+
+    fn add(&mut self, tracked: &Tracked) {
+        let week = self.calendar.bucket_of(tracked.start, Period::Week);
+        let totals = self.totals.entry(tracked.project_id).or_default();
+
+        totals.entry_count += 1;
+        totals.last_used = std::cmp::max(totals.last_used, Some(tracked.start));
+        if let Some(duration) = tracked.duration {
+            totals.total_seconds += duration;
+            *totals.weeks.entry(week).or_insert(0) += duration;
+        }
+    }
+
+    fn subtract(&mut self, id: Id, tracked: &Tracked) {
+        let week = self.calendar.bucket_of(tracked.start, Period::Week);
+        let totals = self.totals.entry(tracked.project_id).or_default();
+
+        totals.entry_count = totals.entry_count.saturating_sub(1);
+        if let Some(duration) = tracked.duration {
+            totals.total_seconds = totals.total_seconds.saturating_sub(duration);
+            if let Some(seconds) = totals.weeks.get_mut(&week) {
+                *seconds = seconds.saturating_sub(duration);
+            }
+        }
+
+        if totals.last_used == Some(tracked.start) {
+            totals.last_used = self
+                .time_entries
+                .iter()
+                .filter(|(other_id, other)| {
+                    **other_id != id && other.project_id == tracked.project_id
+                })
+                .map(|(_, other)| other.start)
+                .max();
+        }
+    }
+}
+
+impl Statistics {
+    /// Replaces the statistics of the user with the statistics of the snapshot, which should
+    /// contain all the time entries of the user (see `sync::with_history`).
+    pub fn build(&self, user_id: Id, snapshot: &Delta) {
+        let calendar = snapshot
+            .user
+            .as_ref()
+            .and_then(|user| Calendar::of_user(user).ok())
+            .unwrap_or(Calendar {
+                timezone: Tz::UTC,
+                beginning_of_week: 1,
+            });
+
+        let mut stats = UserStats::new(calendar);
+        stats.update(snapshot);
+        self.users.lock().unwrap().insert(user_id, stats);
+    }
+
+    /// Drops the statistics of the user, they are built again with the next sync.
+    pub fn forget(&self, user_id: Id) {
+        self.users.lock().unwrap().remove(&user_id);
+    }
+
+    /// Adds the statistics to the projects in the snapshot.
+    pub fn delta_with_stats(&self, user_id: Id, mut delta: Delta) -> Delta {
+        if let Some(stats) = self.users.lock().unwrap().get(&user_id) {
+            let now = Utc::now();
+            for project in delta.projects.iter_mut().flatten() {
+                stats.fill(project, now);
+            }
+        }
+
+        delta
+    }
+
+    /// Updates the statistics with the changes from the client which Toggl accepted and with
+    /// the changes which are sent back to the client. The projects whose statistics changed
+    /// are sent to the client too. The statistics are built from the whole history when
+    /// the user hasn't been seen since the proxy started, they are left out if that fails.
+    pub fn apply(
+        &self,
+        user_id: Id,
+        client_delta: &Delta,
+        mut outcome: SyncOutcome,
+        api: &TogglApi,
+    ) -> SyncOutcome {
+        if !self.users.lock().unwrap().contains_key(&user_id) {
+            match sync::fetch_snapshot(api).and_then(|snapshot| sync::with_history(snapshot, api)) {
+                Ok(snapshot) => self.build(user_id, &snapshot),
+                Err(_) => return outcome,
+            }
+        }
+
+        let mut users = self.users.lock().unwrap();
+        let stats = match users.get_mut(&user_id) {
+            Some(stats) => stats,
+            None => return outcome,
+        };
+
+        let failed: HashSet<Id> = outcome
+            .time_entries
+            .iter()
+            .filter_map(|result| match result {
+                SyncResult::Failed { entity_id, .. } => Some(*entity_id),
+                _ => None,
+            })
+            .collect();
+        let accepted: Vec<TimeEntry> = client_delta
+            .time_entries
+            .iter()
+            .flatten()
+            .filter(|te| !failed.contains(&te.id))
+            .cloned()
+            .collect();
+
+        let mut touched = stats.update(&Delta {
+            user: None,
+            projects: client_delta.projects.clone(),
+            time_entries: Some(accepted),
+            favourites: None,
+        });
+        touched.extend(stats.update(&outcome.entities()));
+
+        let now = Utc::now();
+        for result in outcome.projects.iter_mut() {
+            if let SyncResult::Changed { entity } | SyncResult::Created { entity, .. } = result {
+                touched.remove(&entity.id);
+                stats.fill(entity, now);
+            }
+        }
+
+        let mut touched: Vec<Id> = touched.into_iter().collect();
+        touched.sort();
+        for project_id in touched {
+            if let Some(project) = stats.projects.get(&project_id) {
+                let mut project = project.clone();
+                stats.fill(&mut project, now);
+                outcome.projects.push(changed(project));
+            }
+        }
+
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UserStats;
+    use crate::calendar::Calendar;
+    use crate::models::{fixtures, Delta, Project, TimeEntry};
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.ymd(2019, 12, day).and_hms(hour, 0, 0)
+    }
+
+    fn time_entry(
+        id: Id,
+        project_id: Option<Id>,
+        start: DateTime<Utc>,
+        hours: Option<u64>,
+    ) -> TimeEntry {
+        TimeEntry {
+            project_id,
+            ..fixtures::time_entry(id, start, hours.map(|hours| hours * 3600))
+        }
+    }
+
+    fn delta(time_entries: Vec<TimeEntry>) -> Delta {
+        Delta {
+            user: None,
+            projects: Some(vec![Project {
+                at: at(1, 0),
+                ..fixtures::project(5, "Client")
+            }]),
+            time_entries: Some(time_entries),
+            favourites: None,
+        }
+    }
+
+    fn stats() -> UserStats {
+        let mut stats = UserStats::new(Calendar {
+            timezone: Tz::UTC,
+            beginning_of_week: 1,
+        });
+        stats.update(&delta(vec![
+            // the previous week
+            time_entry(1, Some(5), at(6, 9), Some(2)),
+            time_entry(2, Some(5), at(9, 9), Some(3)),
+            time_entry(3, None, at(9, 13), Some(1)),
+        ]));
+
+        stats
+    }
+
+    #[test]
+    fn sums_up_the_time_entries_of_each_project() {
+        let stats = stats().stats(5, at(10, 12));
+
+        assert_eq!(stats.total_seconds, 5 * 3600);
+        assert_eq!(stats.this_week_seconds, 3 * 3600);
+        assert_eq!(stats.last_used, Some(at(9, 9)));
+        assert_eq!(stats.entry_count, 2);
+    }
+
+    #[test]
+    fn updates_the_totals_incrementally() {
+        let mut stats = stats();
+
+        let touched = stats.update(&delta(vec![
+            TimeEntry {
+                server_deleted_at: Some(at(10, 9)),
+                ..time_entry(2, Some(5), at(9, 9), Some(3))
+            },
+            time_entry(4, Some(5), at(10, 10), None),
+        ]));
+        assert!(touched.contains(&5));
+
+        let project = stats.stats(5, at(10, 12));
+        assert_eq!(project.total_seconds, 4 * 3600);
+        assert_eq!(project.this_week_seconds, 2 * 3600);
+        assert_eq!(project.last_used, Some(at(10, 10)));
+        assert_eq!(project.entry_count, 2);
+
+        stats.update(&delta(vec![time_entry(4, None, at(10, 10), Some(1))]));
+        let project = stats.stats(5, at(10, 12));
+        assert_eq!(project.last_used, Some(at(6, 9)));
+        assert_eq!(project.entry_count, 1);
+    }
+}
@@ -0,0 +1,253 @@
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::error::Error;
+use crate::models::{Project, TimeEntry};
+use crate::sync::server;
+use crate::toggl_api::{models::Id, TogglApi};
+
+/// How far into the history the suggestions look.
+const HISTORY_DAYS: i64 = 90;
+/// The weight of a time entry halves with every this many days of its age.
+const RECENCY_HALF_LIFE_DAYS: f64 = 14.0;
+/// Time entries started within this many hours of the current time of day count more.
+const TIME_OF_DAY_WINDOW_HOURS: i64 = 1;
+const TIME_OF_DAY_BONUS: f64 = 1.0;
+const WEEKDAY_BONUS: f64 = 0.5;
+/// Matches in the middle of the description rank below the matches at its beginning.
+const WORD_MATCH_PENALTY: f64 = 0.5;
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct Suggestion {
+    pub description: String,
+    pub project_id: Option<Id>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_name: Option<String>,
+    pub workspace_id: Id,
+    pub count: usize,
+    pub last_used: DateTime<Utc>,
+    pub score: f64,
+}
+
+/// What the user is typing and when.
+pub struct Context {
+    pub prefix: Option<String>,
+    /// The local time of the user, if the suggestions should prefer what the user usually
+    /// tracks at this time of the day and on this day of the week.
+    pub local_time: Option<DateTime<Tz>>,
+    pub limit: usize,
+}
+
+/// Fetches the recent history of the user and suggests what to track next.
+pub fn fetch_suggestions(context: &Context, api: &TogglApi) -> Result<Vec<Suggestion>, Error> {
+    let now = Utc::now();
+    let time_entries =
+        server::fetch_time_entries_between(now - Duration::days(HISTORY_DAYS), now, api)?;
+    let projects = server::fetch_all_projects(api)?;
+
+    Ok(suggest(&time_entries, &projects, context, now))
+}
+
+/// Ranks the (description, project) pairs of the time entries. Each time entry which matches
+/// the prefix contributes to the score of its pair, the more recent it is, the more it counts.
+pub fn suggest(
+    time_entries: &[TimeEntry],
+    projects: &[Project],
+    context: &Context,
+    now: DateTime<Utc>,
+) -> Vec<Suggestion> {
+    let prefix = context
+        .prefix
+        .as_ref()
+        .map(|prefix| prefix.trim().to_lowercase())
+        .filter(|prefix| !prefix.is_empty());
+
+    let mut suggestions: HashMap<(String, Option<Id>), Suggestion> = HashMap::new();
+
+    for te in time_entries
+        .iter()
+        .filter(|te| te.server_deleted_at.is_none())
+    {
+        let description = te.description.trim();
+        if description.is_empty() {
+            continue;
+        }
+
+        let relevance = match &prefix {
+            Some(prefix) => match relevance(description, prefix) {
+                Some(relevance) => relevance,
+                None => continue,
+            },
+            None => 1.0,
+        };
+
+        let score = relevance * recency(te.start, now) * (1.0 + context_bonus(te, context));
+        let suggestion = suggestions
+            .entry((description.to_lowercase(), te.project_id))
+            .or_insert_with(|| Suggestion {
+                description: description.to_string(),
+                project_id: te.project_id,
+                project_name: te
+                    .project_id
+                    .and_then(|id| projects.iter().find(|project| project.id == id))
+                    .map(|project| project.name.clone()),
+                workspace_id: te.workspace_id,
+                count: 0,
+                last_used: te.start,
+                score: 0.0,
+            });
+
+        suggestion.count += 1;
+        suggestion.score += score;
+        if te.start >= suggestion.last_used {
+            suggestion.last_used = te.start;
+            suggestion.description = description.to_string();
+        }
+    }
+
+    let mut suggestions: Vec<_> = suggestions.into_values().collect();
+    suggestions.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.last_used.cmp(&a.last_used))
+    });
+    suggestions.truncate(context.limit);
+
+    for suggestion in suggestions.iter_mut() {
+        suggestion.score = (suggestion.score * 1000.0).round() / 1000.0;
+    }
+
+    suggestions
+}
+
+fn relevance(description: &str, prefix: &str) -> Option<f64> {
+    let description = description.to_lowercase();
+    if description.starts_with(prefix) {
+        Some(1.0)
+    } else if description
+        .split_whitespace()
+        .any(|word| word.starts_with(prefix))
+    {
+        Some(WORD_MATCH_PENALTY)
+    } else {
+        None
+    }
+}
+
+fn recency(start: DateTime<Utc>, now: DateTime<Utc>) -> f64 {
+    let age_days =
+        std::cmp::max(now.signed_duration_since(start).num_seconds(), 0) as f64 / 86400.0;
+    0.5f64.powf(age_days / RECENCY_HALF_LIFE_DAYS)
+}
+
+fn context_bonus(te: &TimeEntry, context: &Context) -> f64 {
+    let local_time = match &context.local_time {
+        Some(local_time) => local_time,
+        None => return 0.0,
+    };
+
+    let start = te.start.with_timezone(&local_time.timezone());
+    let mut bonus = 0.0;
+
+    let minutes_of_day = |time: &DateTime<Tz>| i64::from(time.hour() * 60 + time.minute());
+    let difference = (minutes_of_day(&start) - minutes_of_day(local_time)).abs();
+    if std::cmp::min(difference, 24 * 60 - difference) <= TIME_OF_DAY_WINDOW_HOURS * 60 {
+        bonus += TIME_OF_DAY_BONUS;
+    }
+
+    if start.weekday() == local_time.weekday() {
+        bonus += WEEKDAY_BONUS;
+    }
+
+    bonus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{suggest, Context};
+    use crate::models::{fixtures, TimeEntry};
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    fn now() -> DateTime<Utc> {
+        // Tuesday
+        Utc.ymd(2019, 12, 10).and_hms(9, 0, 0)
+    }
+
+    fn time_entry(description: &str, project_id: Option<Id>, start: DateTime<Utc>) -> TimeEntry {
+        TimeEntry {
+            description: description.to_string(),
+            project_id,
+            ..fixtures::time_entry(1, start, Some(3600))
+        }
+    }
+
+    fn context(prefix: Option<&str>) -> Context {
+        Context {
+            prefix: prefix.map(|prefix| prefix.to_string()),
+            local_time: None,
+            limit: 10,
+        }
+    }
+
+    #[test]
+    fn ranks_by_frequency_and_recency() {
+        let time_entries = vec![
+            time_entry("Code review", Some(1), now() - Duration::days(1)),
+            time_entry("Code review", Some(1), now() - Duration::days(2)),
+            time_entry("Coffee", None, now() - Duration::days(1)),
+            time_entry("Coding", None, now() - Duration::days(60)),
+            time_entry("Coding", None, now() - Duration::days(61)),
+        ];
+
+        let suggestions = suggest(&time_entries, &[], &context(Some("co")), now());
+
+        assert_eq!(suggestions.len(), 3);
+        assert_eq!(suggestions[0].description, "Code review");
+        assert_eq!(suggestions[0].count, 2);
+        assert_eq!(suggestions[1].description, "Coffee");
+        assert_eq!(suggestions[2].description, "Coding");
+    }
+
+    #[test]
+    fn distinguishes_projects_and_matches_words() {
+        let time_entries = vec![
+            time_entry("Weekly meeting", Some(1), now() - Duration::days(1)),
+            time_entry("weekly meeting", Some(2), now() - Duration::days(1)),
+            time_entry("Planning", None, now() - Duration::days(1)),
+        ];
+
+        let suggestions = suggest(&time_entries, &[], &context(Some("MEET")), now());
+
+        assert_eq!(suggestions.len(), 2);
+        assert!(suggestions
+            .iter()
+            .all(|s| s.description.ends_with("meeting")));
+    }
+
+    #[test]
+    fn prefers_entries_from_the_same_time_of_day() {
+        let time_entries = vec![
+            time_entry("Stand-up", None, now() - Duration::days(7)),
+            time_entry(
+                "Lunch",
+                None,
+                now() - Duration::days(7) + Duration::hours(3),
+            ),
+        ];
+        let context = Context {
+            prefix: None,
+            local_time: Some(now().with_timezone(&Tz::UTC)),
+            limit: 1,
+        };
+
+        let suggestions = suggest(&time_entries, &[], &context, now());
+
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].description, "Stand-up");
+    }
+}
@@ -1,25 +1,88 @@
+pub mod bulk;
 mod conflicts;
+pub mod editing;
 pub mod prelude;
-mod server;
+pub mod projects;
+pub mod server;
+pub mod timer;
+pub mod validation;
 
 use chrono::{DateTime, Utc};
+use std::collections::HashSet;
 
 use crate::error::Error;
-use crate::models::{Delta, TimeEntry};
+use crate::history::Recorder;
+use crate::models::{Delta, Project, TimeEntry};
+use crate::rounding::RoundingRules;
 use crate::toggl_api::{models::Id, TogglApi};
 use prelude::{SyncOutcome, SyncResult};
 
 pub fn fetch_snapshot(api: &TogglApi) -> Result<Delta, Error> {
-    server::fetch_changes_since(None, &api)
+    server::fetch_changes_since(None, true, &api)
+}
+
+/// Adds the older time entries from the whole history of the user to the snapshot, which
+/// contains only the recent ones.
+pub fn with_history(mut snapshot: Delta, api: &TogglApi) -> Result<Delta, Error> {
+    let known: HashSet<Id> = snapshot
+        .time_entries
+        .iter()
+        .flatten()
+        .map(|te| te.id)
+        .collect();
+    let older: Vec<TimeEntry> = server::fetch_history(api)?
+        .into_iter()
+        .filter(|te| !known.contains(&te.id))
+        .collect();
+    snapshot
+        .time_entries
+        .get_or_insert_with(Vec::new)
+        .extend(older);
+
+    Ok(snapshot)
+}
+
+/// The snapshot without the archived projects. The archived projects of the time entries
+/// in the snapshot are kept so the clients can still show those time entries.
+pub fn fetch_snapshot_without_archived(api: &TogglApi) -> Result<Delta, Error> {
+    let mut snapshot = server::fetch_changes_since(None, false, api)?;
+
+    let known: HashSet<Id> = snapshot
+        .projects
+        .iter()
+        .flatten()
+        .map(|project| project.id)
+        .collect();
+    let missing: HashSet<Id> = snapshot
+        .time_entries
+        .iter()
+        .flatten()
+        .filter_map(|te| te.project_id)
+        .filter(|project_id| !known.contains(project_id))
+        .collect();
+
+    if !missing.is_empty() {
+        let archived = server::fetch_all_projects(api)?
+            .into_iter()
+            .filter(|project| missing.contains(&project.id));
+        snapshot
+            .projects
+            .get_or_insert_with(Vec::new)
+            .extend(archived);
+    }
+
+    Ok(snapshot)
 }
 
 pub fn update_server_and_calculate_delta_for_client(
     last_sync: DateTime<Utc>,
     client_delta: Option<Delta>,
+    recorder: &Recorder,
+    rounding: &RoundingRules,
     api: &TogglApi,
 ) -> Result<SyncOutcome, Error> {
     // 1. Get the data which have changed on the server since the last update
-    let server_delta = server::fetch_changes_since(Some(last_sync), &api)?;
+    let server_delta = server::fetch_changes_since(Some(last_sync), true, &api)?;
     let client_delta = client_delta.unwrap_or_default();
 
     // Lemma:
@@ -40,12 +103,15 @@ pub fn update_server_and_calculate_delta_for_client(
     let (mut client_resolution, mut server_resolution) =
         conflicts::resolve(client_delta.clone(), server_delta.clone());
     // - we assume that the two resulting sets are distinct
+    recorder.overwritten(&client_delta, &server_resolution);
+    recorder.observed(&server_delta);
 
     // 3. Check how the running TEs were affected by the conflict resolution
     let maybe_stopped = time_entry_which_should_be_stopped(
         &client_delta,
         &client_resolution,
         &server_resolution,
+        rounding,
         &api,
     );
 
@@ -62,8 +128,13 @@ pub fn update_server_and_calculate_delta_for_client(
         ));
     }
 
-    // 4. Push the changes to the server
-    let server_update_outcome = server::apply_changes(server_resolution, &api);
+    // 4. Push the valid changes to the server, the invalid ones fail without reaching Toggl
+    let known_projects = known_projects(&client_delta, &server_delta);
+    let (server_resolution, rejected) =
+        validation::validate(server_resolution, &known_projects, Utc::now());
+    let server_update_outcome =
+        SyncOutcome::merge(server::apply_changes(server_resolution, &api), rejected);
+    recorder.pushed(&server_update_outcome);
 
     // 5. Check if we tried stopping a TE and if it hasn't failed, push the change to the user
     if let Some(stopped) = maybe_stopped {
@@ -99,10 +170,23 @@ pub fn update_server_and_calculate_delta_for_client(
     Ok(resolution.without_unchanged(client_delta))
 }
 
+/// Collects the projects from both deltas which the time entries in the resolution can refer
+/// to. Fetching all the projects on every sync would be too slow, so the workspaces of the
+/// other projects are left for Toggl to check.
+fn known_projects(client_delta: &Delta, server_delta: &Delta) -> Vec<Project> {
+    [&client_delta.projects, &server_delta.projects]
+        .iter()
+        .filter_map(|projects| projects.as_ref())
+        .flatten()
+        .cloned()
+        .collect()
+}
+
 fn time_entry_which_should_be_stopped(
     client_delta: &Delta,
     client_resolution: &Delta,
     server_resolution: &Delta,
+    rounding: &RoundingRules,
     api: &TogglApi,
 ) -> Option<TimeEntry> {
     let running_on_server = server::currently_running_time_entry(&api)
@@ -115,7 +199,8 @@ fn time_entry_which_should_be_stopped(
         .and_then(|time_entries| time_entries.iter().find(|te| te.is_running()).cloned())
         .map(|time_entry| effect_of_conflict_resolution(time_entry, &client_resolution));
 
-    should_stop(running_on_client, running_on_server).map(|te| te.stop())
+    should_stop(running_on_client, running_on_server)
+        .map(|te| te.stop(rounding.on_stop(te.workspace_id)))
 }
 
 fn push_and_maybe_replace(entries: Option<Vec<TimeEntry>>, stopped: TimeEntry) -> Vec<TimeEntry> {
@@ -0,0 +1,303 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{Error, ValidationError};
+use crate::history::Recorder;
+use crate::models::{Delta, TimeEntry};
+use crate::sync::prelude::SyncOutcome;
+use crate::sync::{server, validation};
+use crate::toggl_api::{
+    endpoints::{self, time_entries::BulkPatchResponse, PatchOperation},
+    models::Id,
+    TogglApi,
+};
+
+/// Toggl doesn't accept more ids in one bulk request.
+const MAX_IDS_PER_REQUEST: usize = 100;
+
+/// The changes which are applied to all the edited time entries. Only the specified
+/// fields are changed.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct TimeEntryPatch {
+    pub project_id: Option<Id>,
+    pub description: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub billable: Option<bool>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum EditResult {
+    Updated {
+        entity_id: Id,
+    },
+    Failed {
+        entity_id: Id,
+        code: &'static str,
+        message: String,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        errors: Vec<ValidationError>,
+    },
+}
+
+impl TimeEntryPatch {
+    fn is_empty(&self) -> bool {
+        self.project_id.is_none()
+            && self.description.is_none()
+            && self.tags.is_none()
+            && self.billable.is_none()
+    }
+
+    fn operations(&self) -> Vec<PatchOperation> {
+        let replace = |path: &str, value: serde_json::Value| PatchOperation {
+            op: "replace".to_string(),
+            path: path.to_string(),
+            value,
+        };
+
+        let mut operations = vec![];
+        if let Some(project_id) = self.project_id {
+            operations.push(replace("/project_id", project_id.into()));
+        }
+        if let Some(description) = &self.description {
+            operations.push(replace("/description", description.clone().into()));
+        }
+        if let Some(tags) = &self.tags {
+            operations.push(replace("/tags", tags.clone().into()));
+        }
+        if let Some(billable) = self.billable {
+            operations.push(replace("/billable", billable.into()));
+        }
+
+        operations
+    }
+
+    fn apply(&self, te: TimeEntry) -> TimeEntry {
+        TimeEntry {
+            project_id: self.project_id.or(te.project_id),
+            description: self.description.clone().unwrap_or(te.description),
+            tags: self.tags.clone().unwrap_or(te.tags),
+            billable: self.billable.unwrap_or(te.billable),
+            at: Utc::now(),
+            ..te
+        }
+    }
+}
+
+/// Applies the patch to all the time entries with the given ids in the workspace (the default
+/// workspace of the user if not specified). Toggl's bulk endpoint is used when it's available,
+/// otherwise the time entries are updated one by one.
+pub fn edit(
+    ids: Vec<Id>,
+    patch: TimeEntryPatch,
+    workspace_id: Option<Id>,
+    recorder: &Recorder,
+    api: &TogglApi,
+) -> Result<Vec<EditResult>, Error> {
+    let started_at = Utc::now();
+    let mut seen = HashSet::new();
+    let ids: Vec<Id> = ids.into_iter().filter(|id| seen.insert(*id)).collect();
+
+    if ids.is_empty() {
+        return Err(invalid("ids", "At least one time entry must be selected."));
+    }
+    if patch.is_empty() {
+        return Err(invalid("patch", "There is nothing to change."));
+    }
+
+    let workspace_id = match workspace_id {
+        Some(workspace_id) => workspace_id,
+        None => server::fetch_user(api)?.default_workspace_id,
+    };
+    check(&patch, workspace_id, api)?;
+
+    let mut results = vec![];
+    for chunk in ids.chunks(MAX_IDS_PER_REQUEST) {
+        let endpoint = endpoints::time_entries::patch(workspace_id, chunk, patch.operations());
+        match api.patch(endpoint) {
+            Ok(response) => results.extend(bulk_results(chunk, response)),
+            Err(Error::NotFound(_)) | Err(Error::Upstream(_)) => {
+                results.extend(chunk.iter().map(|id| update_one(*id, &patch, api)))
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    record_updated(&results, started_at, recorder, api);
+    Ok(results)
+}
+
+/// Records the updated versions of the time entries in the history. Toggl's bulk endpoint
+/// doesn't return them, so they are fetched in one request afterwards.
+fn record_updated(
+    results: &[EditResult],
+    started_at: DateTime<Utc>,
+    recorder: &Recorder,
+    api: &TogglApi,
+) {
+    let updated: HashSet<Id> = results
+        .iter()
+        .filter_map(|result| match result {
+            EditResult::Updated { entity_id } => Some(*entity_id),
+            EditResult::Failed { .. } => None,
+        })
+        .collect();
+    if updated.is_empty() {
+        return;
+    }
+
+    // Toggl compares the last update with a precision of seconds
+    let since = started_at - Duration::seconds(1);
+    if let Ok(time_entries) = server::fetch_time_entries_since(Some(since), api) {
+        recorder.pushed(&SyncOutcome::convert(Delta {
+            time_entries: Some(
+                time_entries
+                    .into_iter()
+                    .filter(|te| updated.contains(&te.id))
+                    .collect(),
+            ),
+            ..Delta::default()
+        }));
+    }
+}
+
+/// Makes sure the patch can be applied to time entries in the workspace before any of them
+/// is touched.
+fn check(patch: &TimeEntryPatch, workspace_id: Id, api: &TogglApi) -> Result<(), Error> {
+    let probe = TimeEntry {
+        id: 0,
+        workspace_id,
+        description: patch.description.clone().unwrap_or_default(),
+        project_id: patch.project_id,
+        start: Utc::now(),
+        duration: Some(0),
+        tags: vec![],
+        billable: false,
+        earnings: None,
+        recurrence: None,
+        at: Utc::now(),
+        server_deleted_at: None,
+    };
+
+    let project_workspaces: HashMap<Id, Id> = match patch.project_id {
+        Some(project_id) => {
+            let project = server::fetch_all_projects(api)?
+                .into_iter()
+                .find(|project| project.id == project_id)
+                .ok_or_else(|| invalid("project_id", "The project doesn't exist."))?;
+            vec![(project.id, project.workspace_id)]
+                .into_iter()
+                .collect()
+        }
+        None => HashMap::new(),
+    };
+
+    let errors =
+        validation::validate_time_entry(&probe, &project_workspaces, &HashSet::new(), Utc::now());
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+fn bulk_results(ids: &[Id], response: BulkPatchResponse) -> Vec<EditResult> {
+    let succeeded: HashSet<Id> = response.success.into_iter().collect();
+    let failures: HashMap<Id, String> = response
+        .failure
+        .into_iter()
+        .map(|failure| (failure.id, failure.message))
+        .collect();
+
+    ids.iter()
+        .map(|id| match failures.get(id) {
+            Some(message) => failed(*id, Error::from_upstream(400, message.clone(), None)),
+            None if succeeded.contains(id) => EditResult::Updated { entity_id: *id },
+            None => failed(
+                *id,
+                Error::Upstream("Toggl didn't report the result of the update.".to_string()),
+            ),
+        })
+        .collect()
+}
+
+fn update_one(id: Id, patch: &TimeEntryPatch, api: &TogglApi) -> EditResult {
+    let result = server::fetch_time_entry(id, api)
+        .and_then(|te| server::update_time_entry(patch.apply(te), api));
+
+    match result {
+        Ok(_) => EditResult::Updated { entity_id: id },
+        Err(err) => failed(id, err),
+    }
+}
+
+fn failed(entity_id: Id, err: Error) -> EditResult {
+    EditResult::Failed {
+        entity_id,
+        code: err.code(),
+        message: err.to_string(),
+        errors: err.validation_errors(),
+    }
+}
+
+fn invalid(field: &str, reason: &str) -> Error {
+    Error::Validation(vec![ValidationError {
+        field: Some(field.to_string()),
+        reason: reason.to_string(),
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bulk_results, EditResult, TimeEntryPatch};
+    use crate::toggl_api::endpoints::time_entries::{BulkPatchFailure, BulkPatchResponse};
+
+    #[test]
+    fn creates_operations_only_for_specified_fields() {
+        let patch = TimeEntryPatch {
+            project_id: Some(42),
+            billable: Some(true),
+            ..TimeEntryPatch::default()
+        };
+
+        let operations = serde_json::to_value(patch.operations()).unwrap();
+
+        assert_eq!(
+            operations,
+            serde_json::json!([
+                { "op": "replace", "path": "/project_id", "value": 42 },
+                { "op": "replace", "path": "/billable", "value": true }
+            ])
+        );
+        assert!(TimeEntryPatch::default().is_empty());
+    }
+
+    #[test]
+    fn reports_result_for_every_id() {
+        let response = BulkPatchResponse {
+            success: vec![1],
+            failure: vec![BulkPatchFailure {
+                id: 2,
+                message: "Project not found".to_string(),
+            }],
+        };
+
+        let results = bulk_results(&[1, 2, 3], response);
+
+        assert_eq!(results[0], EditResult::Updated { entity_id: 1 });
+        match &results[1] {
+            EditResult::Failed {
+                entity_id, code, ..
+            } => {
+                assert_eq!(*entity_id, 2);
+                assert_eq!(*code, "upstream_validation_failed");
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match &results[2] {
+            EditResult::Failed { code, .. } => assert_eq!(*code, "upstream_error"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
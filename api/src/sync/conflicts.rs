@@ -76,11 +76,13 @@ pub fn resolve(client: Delta, server: Delta) -> Pair<Delta> {
             user: client_user,
             projects: Some(client_projects),
             time_entries: Some(client_time_entries),
+            favourites: None,
         },
         Delta {
             user: server_user,
             projects: Some(server_projects),
             time_entries: Some(server_time_entries),
+            favourites: None,
         },
     )
 }
@@ -98,6 +100,10 @@ mod tests {
             name: "ABC".to_string(),
             color: "#ff0000".to_string(),
             active: true,
+            billable: false,
+            rate: None,
+            currency: None,
+            stats: None,
             at,
             server_deleted_at: deleted_at,
         }
@@ -225,6 +231,10 @@ mod tests {
                 name: "ABC".to_string(),
                 color: "#ff0000".to_string(),
                 active: true,
+                billable: false,
+                rate: None,
+                currency: None,
+                stats: None,
                 at: Utc.ymd(2019, 12, 09).and_hms(12, 00, 00),
                 server_deleted_at: None,
             }
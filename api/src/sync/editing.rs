@@ -0,0 +1,281 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{Error, ValidationError};
+use crate::models::TimeEntry;
+use crate::sync::prelude::{changed, created, deleted, SyncOutcome};
+use crate::sync::timer::NEW_TIME_ENTRY_ID;
+use crate::sync::{server, validation};
+use crate::toggl_api::{models::Id, TogglApi};
+
+/// Entries which are further apart than this aren't adjacent and cannot be merged.
+const MAX_MERGE_GAP_MINUTES: i64 = 5;
+
+/// Splits the time entry at the given moment. The original time entry ends at that moment
+/// and a new time entry with the same description and project covers the rest. If the
+/// original time entry is running, the new one keeps running.
+pub fn split(id: Id, at: DateTime<Utc>, api: &TogglApi) -> Result<SyncOutcome, Error> {
+    let original = server::fetch_time_entry(id, api)?;
+    let now = Utc::now();
+    let (shortened, rest) = plan_split(&original, at, now)?;
+
+    // Create the second part first, so no tracked time is lost if anything fails.
+    let rest = server::create_time_entry(rest, api)?;
+    let shortened = match server::update_time_entry(shortened, api) {
+        Ok(shortened) => shortened,
+        Err(err) => {
+            let _ = server::delete_time_entry(&rest, api);
+            return Err(err);
+        }
+    };
+
+    Ok(SyncOutcome {
+        user: None,
+        projects: vec![],
+        time_entries: vec![changed(shortened), created(NEW_TIME_ENTRY_ID, rest)],
+        favourites: vec![],
+        warnings: vec![],
+        goals: vec![],
+    })
+}
+
+/// Merges two adjacent time entries with the same description into the one which started
+/// earlier. The other time entry is deleted.
+pub fn merge(first_id: Id, second_id: Id, api: &TogglApi) -> Result<SyncOutcome, Error> {
+    let first = server::fetch_time_entry(first_id, api)?;
+    let second = server::fetch_time_entry(second_id, api)?;
+    let now = Utc::now();
+    let (merged, removed) = plan_merge(first.clone(), second.clone(), now)?;
+
+    let original = if merged.id == first.id { first } else { second };
+    let merged = server::update_time_entry(merged, api)?;
+    if let Err(err) = server::delete_time_entry(&removed, api) {
+        let _ = server::update_time_entry(original, api);
+        return Err(err);
+    }
+
+    Ok(SyncOutcome {
+        user: None,
+        projects: vec![],
+        time_entries: vec![changed(merged), deleted(removed)],
+        favourites: vec![],
+        warnings: vec![],
+        goals: vec![],
+    })
+}
+
+fn plan_split(
+    original: &TimeEntry,
+    at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<(TimeEntry, TimeEntry), Error> {
+    let end = original.start
+        + Duration::seconds(
+            original
+                .duration
+                .unwrap_or_else(|| original.elapsed_seconds_until(now)) as i64,
+        );
+
+    if at <= original.start || at >= end {
+        return Err(invalid(
+            "at",
+            "The time entry can be split only at a moment between its start and end.",
+        ));
+    }
+
+    let shortened = TimeEntry {
+        duration: Some(at.signed_duration_since(original.start).num_seconds() as u64),
+        at: now,
+        ..original.clone()
+    };
+    let rest = TimeEntry {
+        id: NEW_TIME_ENTRY_ID,
+        start: at,
+        duration: original
+            .duration
+            .map(|_| end.signed_duration_since(at).num_seconds() as u64),
+        at: now,
+        ..original.clone()
+    };
+
+    check(&shortened, now)?;
+    check(&rest, now)?;
+
+    Ok((shortened, rest))
+}
+
+fn plan_merge(
+    a: TimeEntry,
+    b: TimeEntry,
+    now: DateTime<Utc>,
+) -> Result<(TimeEntry, TimeEntry), Error> {
+    if a.id == b.id {
+        return Err(invalid("ids", "A time entry cannot be merged with itself."));
+    }
+
+    let (first, second) = if a.start <= b.start { (a, b) } else { (b, a) };
+
+    if first.description.trim() != second.description.trim() {
+        return Err(invalid(
+            "description",
+            "Only time entries with the same description can be merged.",
+        ));
+    }
+    if first.workspace_id != second.workspace_id || first.project_id != second.project_id {
+        return Err(invalid(
+            "project_id",
+            "Only time entries in the same project can be merged.",
+        ));
+    }
+
+    let end = |te: &TimeEntry| {
+        te.start
+            + Duration::seconds(te.duration.unwrap_or_else(|| te.elapsed_seconds_until(now)) as i64)
+    };
+    let (first_end, second_end) = (end(&first), end(&second));
+
+    if second.start - first_end > Duration::minutes(MAX_MERGE_GAP_MINUTES) {
+        return Err(invalid(
+            "ids",
+            &format!(
+                "The time entries must be at most {} minutes apart.",
+                MAX_MERGE_GAP_MINUTES
+            ),
+        ));
+    }
+
+    let duration = if second_end >= first_end {
+        second.duration.map(|_| second_end)
+    } else {
+        first.duration.map(|_| first_end)
+    }
+    .map(|end| end.signed_duration_since(first.start).num_seconds() as u64);
+
+    let merged = TimeEntry {
+        duration,
+        at: now,
+        ..first
+    };
+    check(&merged, now)?;
+
+    Ok((merged, second))
+}
+
+fn check(te: &TimeEntry, now: DateTime<Utc>) -> Result<(), Error> {
+    let errors = validation::validate_time_entry(te, &HashMap::new(), &HashSet::new(), now);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+fn invalid(field: &str, reason: &str) -> Error {
+    Error::Validation(vec![ValidationError {
+        field: Some(field.to_string()),
+        reason: reason.to_string(),
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan_merge, plan_split};
+    use crate::models::{fixtures, TimeEntry};
+    use crate::sync::timer::NEW_TIME_ENTRY_ID;
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2019, 12, 10).and_hms(hour, minute, 0)
+    }
+
+    fn now() -> DateTime<Utc> {
+        at(18, 0)
+    }
+
+    fn time_entry(id: Id, start: DateTime<Utc>, minutes: Option<i64>) -> TimeEntry {
+        TimeEntry {
+            project_id: Some(3),
+            ..fixtures::time_entry(
+                id,
+                start,
+                minutes.map(|minutes| Duration::minutes(minutes).num_seconds() as u64),
+            )
+        }
+    }
+
+    #[test]
+    fn splits_stopped_entry() {
+        let (shortened, rest) =
+            plan_split(&time_entry(1, at(9, 0), Some(120)), at(9, 30), now()).unwrap();
+
+        assert_eq!(shortened.id, 1);
+        assert_eq!(shortened.duration, Some(1800));
+        assert_eq!(rest.id, NEW_TIME_ENTRY_ID);
+        assert_eq!(rest.start, at(9, 30));
+        assert_eq!(rest.duration, Some(5400));
+        assert_eq!(rest.project_id, Some(3));
+    }
+
+    #[test]
+    fn splitting_running_entry_keeps_the_rest_running() {
+        let (shortened, rest) =
+            plan_split(&time_entry(1, at(9, 0), None), at(12, 0), now()).unwrap();
+
+        assert_eq!(shortened.duration, Some(3 * 3600));
+        assert!(rest.is_running());
+    }
+
+    #[test]
+    fn cannot_split_outside_of_the_entry() {
+        let te = time_entry(1, at(9, 0), Some(60));
+        assert!(plan_split(&te, at(9, 0), now()).is_err());
+        assert!(plan_split(&te, at(10, 30), now()).is_err());
+    }
+
+    #[test]
+    fn merges_adjacent_entries() {
+        let (merged, removed) = plan_merge(
+            time_entry(2, at(10, 2), Some(60)),
+            time_entry(1, at(9, 0), Some(60)),
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(merged.id, 1);
+        assert_eq!(merged.start, at(9, 0));
+        assert_eq!(
+            merged.duration,
+            Some(Duration::minutes(122).num_seconds() as u64)
+        );
+        assert_eq!(removed.id, 2);
+    }
+
+    #[test]
+    fn merging_with_running_entry_keeps_it_running() {
+        let (merged, _) = plan_merge(
+            time_entry(1, at(9, 0), Some(60)),
+            time_entry(2, at(10, 0), None),
+            now(),
+        )
+        .unwrap();
+
+        assert!(merged.is_running());
+        assert_eq!(merged.start, at(9, 0));
+    }
+
+    #[test]
+    fn refuses_to_merge_different_or_distant_entries() {
+        let other = TimeEntry {
+            description: "Meeting".to_string(),
+            ..time_entry(2, at(10, 0), Some(60))
+        };
+        assert!(plan_merge(time_entry(1, at(9, 0), Some(60)), other, now()).is_err());
+        assert!(plan_merge(
+            time_entry(1, at(9, 0), Some(60)),
+            time_entry(2, at(11, 0), Some(60)),
+            now()
+        )
+        .is_err());
+    }
+}
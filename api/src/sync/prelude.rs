@@ -1,10 +1,20 @@
 use serde::Serialize;
 
-use crate::error::Error;
-use crate::models::{Delta, Entity, Project, TimeEntry, User};
+use crate::analysis::Warning;
+use crate::error::{Error, ValidationError};
+use crate::goals::Progress;
+use crate::models::{Delta, Entity, Favourite, Project, TimeEntry, User};
 use crate::toggl_api::models::Id;
 use std::cmp::PartialEq;
 
+#[derive(Serialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    MaxDuration,
+    TimeOfDay,
+    NewerEntry,
+}
+
 #[derive(Serialize, PartialEq, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum SyncResult<T: Entity> {
@@ -15,10 +25,20 @@ pub enum SyncResult<T: Entity> {
         client_assigned_id: Id,
         entity: T,
     },
+    Deleted {
+        entity: T,
+    },
+    /// The running time entry was stopped by one of the auto-stop rules of the user.
+    AutoStopped {
+        entity: T,
+        reason: StopReason,
+    },
     Failed {
         entity_id: Id,
-        code: u16,
+        code: &'static str,
         message: String,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        errors: Vec<ValidationError>,
     },
 }
 
@@ -33,16 +53,16 @@ pub fn created<T: Entity>(client_assigned_id: Id, entity: T) -> SyncResult<T> {
     }
 }
 
-pub fn failed<T: Entity>(entity_id: Id, err: Error) -> SyncResult<T> {
-    let (code, message) = match err {
-        Error::ApiError(code, message) => (code, message),
-        Error::NetworkError(inner) => (1, format!("{:#?}", inner)),
-    };
+pub fn deleted<T: Entity>(entity: T) -> SyncResult<T> {
+    SyncResult::<T>::Deleted { entity }
+}
 
+pub fn failed<T: Entity>(entity_id: Id, err: Error) -> SyncResult<T> {
     SyncResult::<T>::Failed {
         entity_id,
-        code,
-        message,
+        code: err.code(),
+        message: err.to_string(),
+        errors: err.validation_errors(),
     }
 }
 
@@ -50,6 +70,27 @@ impl<T: Entity> SyncResult<T> {
     pub fn from(entity: T) -> SyncResult<T> {
         SyncResult::<T>::Changed { entity }
     }
+
+    /// The entity which is sent to the client, failures don't have any.
+    pub fn entity(&self) -> Option<&T> {
+        match self {
+            SyncResult::Changed { entity }
+            | SyncResult::Created { entity, .. }
+            | SyncResult::Deleted { entity }
+            | SyncResult::AutoStopped { entity, .. } => Some(entity),
+            SyncResult::Failed { .. } => None,
+        }
+    }
+
+    pub fn entity_mut(&mut self) -> Option<&mut T> {
+        match self {
+            SyncResult::Changed { entity }
+            | SyncResult::Created { entity, .. }
+            | SyncResult::Deleted { entity }
+            | SyncResult::AutoStopped { entity, .. } => Some(entity),
+            SyncResult::Failed { .. } => None,
+        }
+    }
 }
 
 #[derive(Serialize, PartialEq, Debug, Clone)]
@@ -57,6 +98,13 @@ pub struct SyncOutcome {
     pub user: Option<SyncResult<User>>,
     pub projects: Vec<SyncResult<Project>>,
     pub time_entries: Vec<SyncResult<TimeEntry>>,
+    pub favourites: Vec<SyncResult<Favourite>>,
+    /// Problems which the client should ask the user to fix, e.g. overlapping time entries.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+    /// The progress of the goals of the user in the current day or week.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub goals: Vec<Progress>,
 }
 
 impl SyncOutcome {
@@ -75,6 +123,14 @@ impl SyncOutcome {
                 .into_iter()
                 .map(SyncResult::<TimeEntry>::from)
                 .collect(),
+            favourites: delta
+                .favourites
+                .unwrap_or_default()
+                .into_iter()
+                .map(SyncResult::<Favourite>::from)
+                .collect(),
+            warnings: vec![],
+            goals: vec![],
         }
     }
 
@@ -83,6 +139,51 @@ impl SyncOutcome {
             user: a.user.or(b.user),
             projects: [&a.projects[..], &b.projects[..]].concat(),
             time_entries: [&a.time_entries[..], &b.time_entries[..]].concat(),
+            favourites: [&a.favourites[..], &b.favourites[..]].concat(),
+            warnings: [&a.warnings[..], &b.warnings[..]].concat(),
+            goals: [&a.goals[..], &b.goals[..]].concat(),
+        }
+    }
+
+    /// The entities which are sent to the client, without the failures.
+    pub fn entities(&self) -> Delta {
+        fn entities<T: Entity>(results: &[SyncResult<T>]) -> Vec<T> {
+            results
+                .iter()
+                .filter_map(SyncResult::entity)
+                .cloned()
+                .collect()
+        }
+
+        Delta {
+            user: self.user.as_ref().and_then(SyncResult::entity).cloned(),
+            projects: Some(entities(&self.projects)),
+            time_entries: Some(entities(&self.time_entries)),
+            favourites: None,
+        }
+    }
+
+    /// The changes from the client which weren't rejected by the validation or by Toggl.
+    pub fn accepted_from(&self, client_delta: &Delta) -> Delta {
+        fn accepted<T: Entity>(changes: &Option<Vec<T>>, results: &[SyncResult<T>]) -> Vec<T> {
+            changes
+                .iter()
+                .flatten()
+                .filter(|change| {
+                    !results.iter().any(|result| match result {
+                        SyncResult::Failed { entity_id, .. } => *entity_id == change.id(),
+                        _ => false,
+                    })
+                })
+                .cloned()
+                .collect()
+        }
+
+        Delta {
+            user: None,
+            projects: Some(accepted(&client_delta.projects, &self.projects)),
+            time_entries: Some(accepted(&client_delta.time_entries, &self.time_entries)),
+            favourites: None,
         }
     }
 
@@ -101,6 +202,14 @@ impl SyncOutcome {
                     SyncOutcome::remove_unchanged_in_list(&self.time_entries, known_time_entries)
                 })
                 .unwrap_or_else(|| self.time_entries.clone()),
+            favourites: known_changes
+                .favourites
+                .map(|known_favourites| {
+                    SyncOutcome::remove_unchanged_in_list(&self.favourites, known_favourites)
+                })
+                .unwrap_or_else(|| self.favourites.clone()),
+            warnings: self.warnings.clone(),
+            goals: self.goals.clone(),
         }
     }
 
@@ -130,6 +239,9 @@ mod tests {
                 user: None,
                 projects: vec![],
                 time_entries: vec![],
+                favourites: vec![],
+                warnings: vec![],
+                goals: vec![],
             }
         }
 
@@ -155,6 +267,8 @@ mod tests {
                         id: 1,
                         default_workspace_id: 0,
                         fullname: "user".to_string(),
+                        timezone: None,
+                        beginning_of_week: 1,
                         api_token: "token".to_string(),
                         at: Utc::now(),
                     },
@@ -166,15 +280,23 @@ mod tests {
                         name: "project".to_string(),
                         color: "#ff0000".to_string(),
                         active: true,
+                        billable: false,
+                        rate: None,
+                        currency: None,
+                        stats: None,
                         at: Utc::now(),
                         server_deleted_at: None,
                     },
                 }],
                 time_entries: vec![SyncResult::<TimeEntry>::Failed {
                     entity_id: 3,
-                    code: 1,
+                    code: "upstream_unreachable",
                     message: "msg".to_string(),
+                    errors: vec![],
                 }],
+                favourites: vec![],
+                warnings: vec![],
+                goals: vec![],
             };
 
             let merged = SyncOutcome::merge(a, b.clone());
@@ -191,6 +313,8 @@ mod tests {
                         id: 1,
                         default_workspace_id: 0,
                         fullname: "user".to_string(),
+                        timezone: None,
+                        beginning_of_week: 1,
                         api_token: "token".to_string(),
                         at: Utc::now(),
                     },
@@ -202,15 +326,23 @@ mod tests {
                         name: "project A".to_string(),
                         color: "#ff0000".to_string(),
                         active: true,
+                        billable: false,
+                        rate: None,
+                        currency: None,
+                        stats: None,
                         at: Utc::now(),
                         server_deleted_at: None,
                     },
                 }],
                 time_entries: vec![SyncResult::<TimeEntry>::Failed {
                     entity_id: 3,
-                    code: 1,
+                    code: "upstream_unreachable",
                     message: "error".to_string(),
+                    errors: vec![],
                 }],
+                favourites: vec![],
+                warnings: vec![],
+                goals: vec![],
             };
             let b = SyncOutcome {
                 user: None,
@@ -221,15 +353,23 @@ mod tests {
                         name: "project B".to_string(),
                         color: "#ff0000".to_string(),
                         active: true,
+                        billable: false,
+                        rate: None,
+                        currency: None,
+                        stats: None,
                         at: Utc::now(),
                         server_deleted_at: None,
                     },
                 }],
                 time_entries: vec![SyncResult::<TimeEntry>::Failed {
                     entity_id: 5,
-                    code: 3,
+                    code: "upstream_validation_failed",
                     message: "error".to_string(),
+                    errors: vec![],
                 }],
+                favourites: vec![],
+                warnings: vec![],
+                goals: vec![],
             };
 
             let merged = SyncOutcome::merge(a, b);
@@ -0,0 +1,251 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashSet;
+
+use crate::error::{Error, ValidationError};
+use crate::models::{Delta, Project, TimeEntry};
+use crate::rounding::RoundingRules;
+use crate::sync::prelude::{failed, SyncOutcome, SyncResult};
+use crate::sync::{server, validation};
+use crate::toggl_api::{models::Id, TogglApi};
+
+const MAX_PROJECTS_AT_ONCE: usize = 100;
+
+/// The changes which are applied to all the edited projects. Only the specified fields
+/// are changed, `active: false` archives the projects and `active: true` restores them.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ProjectPatch {
+    pub active: Option<bool>,
+    pub color: Option<String>,
+}
+
+/// What happens to the running time entry when its project is archived.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunningEntryRule {
+    /// The time entry keeps running on the archived project.
+    Keep,
+    /// The time entry is stopped, rounded just like when the timer is stopped.
+    #[default]
+    Stop,
+    /// The time entry keeps running without a project.
+    Unassign,
+}
+
+impl ProjectPatch {
+    fn is_empty(&self) -> bool {
+        self.active.is_none() && self.color.is_none()
+    }
+
+    fn apply(&self, project: Project, now: DateTime<Utc>) -> Project {
+        Project {
+            active: self.active.unwrap_or(project.active),
+            color: self.color.clone().unwrap_or(project.color),
+            at: now,
+            ..project
+        }
+    }
+
+    fn archives(&self) -> bool {
+        self.active == Some(false)
+    }
+}
+
+/// Applies the patch to all the projects with the given ids. When projects are archived,
+/// the rule decides what happens to the time entry which is running on one of them.
+pub fn edit(
+    ids: Vec<Id>,
+    patch: ProjectPatch,
+    running_entry: RunningEntryRule,
+    rounding: &RoundingRules,
+    api: &TogglApi,
+) -> Result<SyncOutcome, Error> {
+    let mut seen = HashSet::new();
+    let ids: Vec<Id> = ids.into_iter().filter(|id| seen.insert(*id)).collect();
+
+    if ids.is_empty() || ids.len() > MAX_PROJECTS_AT_ONCE {
+        return Err(invalid(
+            "ids",
+            &format!(
+                "Between 1 and {} projects can be edited at once.",
+                MAX_PROJECTS_AT_ONCE
+            ),
+        ));
+    }
+    if patch.is_empty() {
+        return Err(invalid("patch", "There is nothing to change."));
+    }
+
+    let now = Utc::now();
+    let known_projects = server::fetch_all_projects(api)?;
+    let (patched, missing) = patch_projects(&ids, &patch, &known_projects, now);
+
+    let (changes, rejected) = validation::validate(
+        Delta {
+            projects: Some(patched),
+            ..Delta::default()
+        },
+        &known_projects,
+        now,
+    );
+    let mut outcome = SyncOutcome::merge(server::apply_changes(changes, api), rejected);
+    outcome.projects.extend(missing);
+
+    if patch.archives() && running_entry != RunningEntryRule::Keep {
+        let archived: HashSet<Id> = outcome
+            .projects
+            .iter()
+            .filter_map(|result| match result {
+                SyncResult::Changed { entity } if !entity.active => Some(entity.id),
+                _ => None,
+            })
+            .collect();
+
+        let change = server::currently_running_time_entry(api)?.and_then(|running| {
+            running_entry_change(running, &archived, running_entry, rounding, now)
+        });
+        if let Some(change) = change {
+            let (changes, rejected) = validation::validate(
+                Delta {
+                    time_entries: Some(vec![change]),
+                    ..Delta::default()
+                },
+                &known_projects,
+                now,
+            );
+            outcome = SyncOutcome::merge(
+                outcome,
+                SyncOutcome::merge(server::apply_changes(changes, api), rejected),
+            );
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// Applies the patch to the known projects with the given ids. The ids which don't belong
+/// to any known project are reported as failures.
+fn patch_projects(
+    ids: &[Id],
+    patch: &ProjectPatch,
+    known_projects: &[Project],
+    now: DateTime<Utc>,
+) -> (Vec<Project>, Vec<SyncResult<Project>>) {
+    let mut patched = vec![];
+    let mut missing = vec![];
+    for id in ids {
+        match known_projects.iter().find(|project| project.id == *id) {
+            Some(project) => patched.push(patch.apply(project.clone(), now)),
+            None => missing.push(failed(
+                *id,
+                Error::NotFound(format!("There is no project {}.", id)),
+            )),
+        }
+    }
+
+    (patched, missing)
+}
+
+/// The change of the running time entry which the rule requires when its project
+/// has just been archived.
+fn running_entry_change(
+    running: TimeEntry,
+    archived: &HashSet<Id>,
+    rule: RunningEntryRule,
+    rounding: &RoundingRules,
+    now: DateTime<Utc>,
+) -> Option<TimeEntry> {
+    if !running.is_running()
+        || !running
+            .project_id
+            .is_some_and(|project_id| archived.contains(&project_id))
+    {
+        return None;
+    }
+
+    match rule {
+        RunningEntryRule::Keep => None,
+        RunningEntryRule::Stop => {
+            Some(running.stop_at(now, rounding.on_stop(running.workspace_id)))
+        }
+        RunningEntryRule::Unassign => Some(TimeEntry {
+            project_id: None,
+            at: now,
+            ..running
+        }),
+    }
+}
+
+fn invalid(field: &str, reason: &str) -> Error {
+    Error::Validation(vec![ValidationError {
+        field: Some(field.to_string()),
+        reason: reason.to_string(),
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{patch_projects, running_entry_change, ProjectPatch, RunningEntryRule};
+    use crate::models::{fixtures, Project, TimeEntry};
+    use crate::rounding::RoundingRules;
+    use crate::sync::prelude::SyncResult;
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use std::collections::HashSet;
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2019, 12, 10).and_hms(9, 0, 0)
+    }
+
+    fn project(id: Id) -> Project {
+        Project {
+            at: now() - Duration::days(30),
+            ..fixtures::project(id, "Client")
+        }
+    }
+
+    fn running(project_id: Option<Id>) -> TimeEntry {
+        TimeEntry {
+            project_id,
+            ..fixtures::time_entry(1, now() - Duration::hours(1), None)
+        }
+    }
+
+    #[test]
+    fn patches_only_the_given_fields_of_known_projects() {
+        let patch = ProjectPatch {
+            active: Some(false),
+            color: None,
+        };
+
+        let (patched, missing) = patch_projects(&[5, 6], &patch, &[project(5), project(7)], now());
+
+        assert_eq!(patched.len(), 1);
+        assert!(!patched[0].active);
+        assert_eq!(patched[0].color, "#ff0000");
+        assert_eq!(patched[0].at, now());
+        match &missing[..] {
+            [SyncResult::Failed { entity_id, .. }] => assert_eq!(*entity_id, 6),
+            other => panic!("Unexpected results {:?}", other),
+        }
+    }
+
+    #[test]
+    fn applies_the_rule_only_to_time_entries_of_archived_projects() {
+        let archived: HashSet<Id> = vec![5].into_iter().collect();
+        let rounding = RoundingRules::default();
+        let change = |te: TimeEntry, rule: RunningEntryRule| {
+            running_entry_change(te, &archived, rule, &rounding, now())
+        };
+
+        let stopped = change(running(Some(5)), RunningEntryRule::Stop).unwrap();
+        assert_eq!(stopped.duration, Some(3600));
+        let unassigned = change(running(Some(5)), RunningEntryRule::Unassign).unwrap();
+        assert_eq!(unassigned.project_id, None);
+        assert!(unassigned.is_running());
+
+        assert!(change(running(Some(5)), RunningEntryRule::Keep).is_none());
+        assert!(change(running(Some(6)), RunningEntryRule::Stop).is_none());
+        assert!(change(running(None), RunningEntryRule::Stop).is_none());
+    }
+}
@@ -1,39 +1,148 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 
 use crate::error::Error;
-use crate::models::{Delta, Entity, Project, TimeEntry, User};
+use crate::models::{Delta, Entity, Project, TimeEntry, User, Workspace};
 use crate::sync::prelude::{changed, created, failed, SyncOutcome, SyncResult};
 use crate::toggl_api::{
     endpoints,
     endpoints::CreateOrUpdate,
-    models::{Project as TogglProject, TimeEntry as TogglTimeEntry},
+    models::{Id, Project as TogglProject, TimeEntry as TogglTimeEntry},
     TogglApi,
 };
 
-pub fn fetch_changes_since(since: Option<DateTime<Utc>>, api: &TogglApi) -> Result<Delta, Error> {
+/// The history of the user is fetched in windows of this many days.
+const HISTORY_WINDOW_DAYS: i64 = 90;
+/// The history is considered complete after this many windows without any time entries.
+const MAX_EMPTY_HISTORY_WINDOWS: usize = 2;
+/// The history is never fetched further back than this.
+const MAX_HISTORY_YEARS: i64 = 10;
+/// Toggl filters the time entries by their start, so the entries which began this many
+/// hours before a range are fetched too, in case they still reach into it.
+const LOOKBACK_HOURS: i64 = 24;
+
+/// Fetches everything which changed since the given moment. The archived projects are left out
+/// only when asked to, a project which has just been archived must still reach the clients.
+pub fn fetch_changes_since(
+    since: Option<DateTime<Utc>>,
+    include_archived: bool,
+    api: &TogglApi,
+) -> Result<Delta, Error> {
     let user: User = api.fetch(endpoints::user::get())?.into();
 
     let projects: Vec<Project> = api
-        .fetch(endpoints::projects::get(since))?
+        .fetch(endpoints::projects::get(since, include_archived))?
         .into_iter()
         .filter(|project| since.unwrap_or(project.at) <= project.at) // remove false positives
         .map(|p| p.into())
         .collect();
 
-    let time_entries: Vec<TimeEntry> = api
-        .fetch(endpoints::time_entries::get(since))?
-        .into_iter()
-        .filter(|te| since.unwrap_or(te.at) <= te.at) // remove false positives
-        .map(|te| te.into())
-        .collect();
+    let time_entries = fetch_time_entries_since(since, api)?;
 
     Ok(Delta {
         user: Some(user),
         projects: Some(projects),
         time_entries: Some(time_entries),
+        favourites: None,
     })
 }
 
+pub fn fetch_time_entries_since(
+    since: Option<DateTime<Utc>>,
+    api: &TogglApi,
+) -> Result<Vec<TimeEntry>, Error> {
+    Ok(api
+        .fetch(endpoints::time_entries::get(since))?
+        .into_iter()
+        .filter(|te| since.unwrap_or(te.at) <= te.at) // remove false positives
+        .map(|te| te.into())
+        .collect())
+}
+
+/// Fetches the time entries of the whole history of the user. Toggl returns only the recent
+/// time entries unless a range is given, so the history is fetched window by window going
+/// back in time until no time entries are found for a while.
+pub fn fetch_history(api: &TogglApi) -> Result<Vec<TimeEntry>, Error> {
+    let now = Utc::now();
+    let earliest = now - Duration::days(365 * MAX_HISTORY_YEARS);
+
+    let mut time_entries = vec![];
+    let mut empty_windows = 0;
+    let mut to = now + Duration::days(1);
+    while empty_windows < MAX_EMPTY_HISTORY_WINDOWS && to > earliest {
+        let from = to - Duration::days(HISTORY_WINDOW_DAYS);
+        let window = fetch_time_entries_between(from, to, api)?;
+        if window.is_empty() {
+            empty_windows += 1;
+        } else {
+            empty_windows = 0;
+            time_entries.extend(window);
+        }
+        to = from;
+    }
+
+    Ok(time_entries)
+}
+
+pub fn fetch_user(api: &TogglApi) -> Result<User, Error> {
+    Ok(api.fetch(endpoints::user::get())?.into())
+}
+
+pub fn fetch_time_entry(id: Id, api: &TogglApi) -> Result<TimeEntry, Error> {
+    Ok(api.fetch(endpoints::time_entries::get_by_id(id))?.into())
+}
+
+pub fn fetch_time_entries_between(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    api: &TogglApi,
+) -> Result<Vec<TimeEntry>, Error> {
+    Ok(api
+        .fetch(endpoints::time_entries::get_between(from, to))?
+        .into_iter()
+        .map(|te| te.into())
+        .collect())
+}
+
+/// Fetches the time entries which may overlap the given range, including those which
+/// started shortly before it. The callers must filter out the ones outside of the range.
+pub fn fetch_time_entries_reaching_into(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    api: &TogglApi,
+) -> Result<Vec<TimeEntry>, Error> {
+    fetch_time_entries_between(from - Duration::hours(LOOKBACK_HOURS), to, api)
+}
+
+pub fn create_time_entry(te: TimeEntry, api: &TogglApi) -> Result<TimeEntry, Error> {
+    let te: TogglTimeEntry = te.into();
+    Ok(api.create(te)?.into())
+}
+
+pub fn update_time_entry(te: TimeEntry, api: &TogglApi) -> Result<TimeEntry, Error> {
+    let te: TogglTimeEntry = te.into();
+    Ok(api.update(te)?.into())
+}
+
+pub fn delete_time_entry(te: &TimeEntry, api: &TogglApi) -> Result<(), Error> {
+    api.delete(endpoints::time_entries::delete(te.workspace_id, te.id))
+}
+
+pub fn fetch_all_projects(api: &TogglApi) -> Result<Vec<Project>, Error> {
+    Ok(api
+        .fetch(endpoints::projects::get(None, true))?
+        .into_iter()
+        .map(|p| p.into())
+        .collect())
+}
+
+pub fn fetch_workspaces(api: &TogglApi) -> Result<Vec<Workspace>, Error> {
+    Ok(api
+        .fetch(endpoints::workspaces::get())?
+        .into_iter()
+        .map(|workspace| workspace.into())
+        .collect())
+}
+
 pub fn currently_running_time_entry(api: &TogglApi) -> Result<Option<TimeEntry>, Error> {
     let maybe_te = api.fetch_current_running_time_entry()?;
     Ok(maybe_te.map(|te| te.into()))
@@ -87,6 +196,9 @@ pub fn apply_changes(delta: Delta, api: &TogglApi) -> SyncOutcome {
         user: None,
         projects,
         time_entries,
+        favourites: vec![],
+        warnings: vec![],
+        goals: vec![],
     }
 }
 
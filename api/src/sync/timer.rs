@@ -0,0 +1,262 @@
+use chrono::{DateTime, Utc};
+
+use crate::error::{Error, ValidationError};
+use crate::models::{Delta, Entity, Favourite, Project, TimeEntry};
+use crate::rounding::RoundingRules;
+use crate::sync::prelude::SyncOutcome;
+use crate::sync::{server, validation};
+use crate::toggl_api::{models::Id, TogglApi};
+
+/// The client assigned id of the time entries which are created by the timer commands.
+pub const NEW_TIME_ENTRY_ID: Id = -1;
+
+/// Starts a new time entry and stops the one which is currently running, if there's any.
+/// When the workspace isn't specified, the workspace of the project or the default
+/// workspace of the user is used.
+pub fn start(
+    description: String,
+    project_id: Option<Id>,
+    workspace_id: Option<Id>,
+    rounding: &RoundingRules,
+    api: &TogglApi,
+) -> Result<SyncOutcome, Error> {
+    let mut known_projects = None;
+    let workspace_id = match (workspace_id, project_id) {
+        (Some(workspace_id), _) => workspace_id,
+        (None, Some(project_id)) => {
+            let projects = server::fetch_all_projects(api)?;
+            let workspace_id = projects
+                .iter()
+                .find(|project| project.id == project_id)
+                .map(|project| project.workspace_id)
+                .ok_or_else(|| Error::NotFound(format!("There is no project {}.", project_id)))?;
+            known_projects = Some(projects);
+            workspace_id
+        }
+        (None, None) => server::fetch_user(api)?.default_workspace_id,
+    };
+
+    let now = Utc::now();
+    let new_time_entry = TimeEntry {
+        id: NEW_TIME_ENTRY_ID,
+        workspace_id,
+        description,
+        project_id,
+        start: now,
+        duration: None,
+        tags: vec![],
+        billable: false,
+        earnings: None,
+        recurrence: None,
+        at: now,
+        server_deleted_at: None,
+    };
+
+    start_time_entry(new_time_entry, known_projects, now, rounding, api)
+}
+
+/// Stops the currently running time entry.
+pub fn stop(rounding: &RoundingRules, api: &TogglApi) -> Result<SyncOutcome, Error> {
+    let stopped = plan_stop(server::currently_running_time_entry(api)?, rounding)?;
+    push(vec![stopped], None, Utc::now(), api)
+}
+
+/// Starts a new time entry with the same description, project and workspace as
+/// the given one.
+pub fn continue_time_entry(
+    id: Id,
+    rounding: &RoundingRules,
+    api: &TogglApi,
+) -> Result<SyncOutcome, Error> {
+    let now = Utc::now();
+    let new_time_entry = plan_continue(server::fetch_time_entry(id, api)?, now)?;
+
+    start_time_entry(new_time_entry, None, now, rounding, api)
+}
+
+/// Starts a new time entry with the description, project, tags and workspace of
+/// the favourite.
+pub fn start_favourite(
+    favourite: Favourite,
+    rounding: &RoundingRules,
+    api: &TogglApi,
+) -> Result<SyncOutcome, Error> {
+    let now = Utc::now();
+    let new_time_entry = TimeEntry {
+        id: NEW_TIME_ENTRY_ID,
+        workspace_id: favourite.workspace_id,
+        description: favourite.description,
+        project_id: favourite.project_id,
+        start: now,
+        duration: None,
+        tags: favourite.tags,
+        billable: favourite.billable,
+        earnings: None,
+        recurrence: None,
+        at: now,
+        server_deleted_at: None,
+    };
+
+    start_time_entry(new_time_entry, None, now, rounding, api)
+}
+
+fn start_time_entry(
+    new_time_entry: TimeEntry,
+    known_projects: Option<Vec<Project>>,
+    now: DateTime<Utc>,
+    rounding: &RoundingRules,
+    api: &TogglApi,
+) -> Result<SyncOutcome, Error> {
+    let running = server::currently_running_time_entry(api)?;
+    push(
+        plan_start(new_time_entry, running, rounding),
+        known_projects,
+        now,
+        api,
+    )
+}
+
+/// The running time entry is stopped when a new one starts, there can be only one running
+/// time entry at a time.
+fn plan_start(
+    new_time_entry: TimeEntry,
+    running: Option<TimeEntry>,
+    rounding: &RoundingRules,
+) -> Vec<TimeEntry> {
+    running
+        .filter(|running| running.is_running() && running.id != new_time_entry.id)
+        .map(|running| running.stop(rounding.on_stop(running.workspace_id)))
+        .into_iter()
+        .chain(std::iter::once(new_time_entry))
+        .collect()
+}
+
+fn plan_stop(running: Option<TimeEntry>, rounding: &RoundingRules) -> Result<TimeEntry, Error> {
+    running
+        .filter(|running| running.is_running())
+        .map(|running| running.stop(rounding.on_stop(running.workspace_id)))
+        .ok_or_else(|| Error::NotFound("There is no running time entry.".to_string()))
+}
+
+/// A deleted time entry cannot be continued.
+fn plan_continue(previous: TimeEntry, now: DateTime<Utc>) -> Result<TimeEntry, Error> {
+    if previous.is_deleted() {
+        return Err(Error::Validation(vec![ValidationError {
+            field: Some("id".to_string()),
+            reason: "A deleted time entry cannot be continued.".to_string(),
+        }]));
+    }
+
+    Ok(TimeEntry {
+        id: NEW_TIME_ENTRY_ID,
+        start: now,
+        duration: None,
+        at: now,
+        server_deleted_at: None,
+        ..previous
+    })
+}
+
+/// The projects are fetched for the validation only if they aren't known yet and some
+/// of the time entries have a project.
+fn push(
+    time_entries: Vec<TimeEntry>,
+    known_projects: Option<Vec<Project>>,
+    now: DateTime<Utc>,
+    api: &TogglApi,
+) -> Result<SyncOutcome, Error> {
+    let changes = Delta {
+        user: None,
+        projects: None,
+        time_entries: Some(time_entries),
+        favourites: None,
+    };
+
+    let has_projects = changes
+        .time_entries
+        .iter()
+        .flatten()
+        .any(|te| te.project_id.is_some());
+    let known_projects = match known_projects {
+        Some(known_projects) => known_projects,
+        None if has_projects => server::fetch_all_projects(api)?,
+        None => vec![],
+    };
+
+    let (changes, rejected) = validation::validate(changes, &known_projects, now);
+    Ok(SyncOutcome::merge(
+        server::apply_changes(changes, api),
+        rejected,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan_continue, plan_start, plan_stop, NEW_TIME_ENTRY_ID};
+    use crate::error::Error;
+    use crate::models::{fixtures, TimeEntry};
+    use crate::rounding::{Direction, Rounding, RoundingRules};
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn time_entry(id: Id, start: DateTime<Utc>, duration: Option<u64>) -> TimeEntry {
+        TimeEntry {
+            project_id: Some(5),
+            tags: vec!["meeting".to_string()],
+            billable: true,
+            ..fixtures::time_entry(id, start, duration)
+        }
+    }
+
+    #[test]
+    fn starting_stops_the_running_time_entry() {
+        let now = Utc::now();
+        let running = time_entry(1, now - Duration::minutes(16), None);
+        let rules = RoundingRules {
+            default: Some(Rounding {
+                direction: Direction::Down,
+                minutes: 15,
+                only_in_output: false,
+            }),
+            ..RoundingRules::default()
+        };
+
+        let time_entries = plan_start(
+            time_entry(NEW_TIME_ENTRY_ID, now, None),
+            Some(running),
+            &rules,
+        );
+
+        assert_eq!(time_entries.len(), 2);
+        assert_eq!(time_entries[0].id, 1);
+        assert_eq!(time_entries[0].duration, Some(15 * 60));
+        assert!(time_entries[1].is_running());
+
+        let alone = plan_start(time_entry(NEW_TIME_ENTRY_ID, now, None), None, &rules);
+        assert_eq!(alone.len(), 1);
+        assert!(plan_stop(None, &rules).is_err());
+        assert!(plan_stop(Some(time_entry(2, now, Some(60))), &rules).is_err());
+    }
+
+    #[test]
+    fn continues_only_time_entries_which_exist() {
+        let now = Utc::now();
+        let previous = time_entry(3, now - Duration::hours(2), Some(3600));
+
+        let continued = plan_continue(previous.clone(), now).unwrap();
+        assert_eq!(continued.id, NEW_TIME_ENTRY_ID);
+        assert_eq!(continued.start, now);
+        assert!(continued.is_running());
+        assert_eq!(continued.project_id, Some(5));
+        assert_eq!(continued.tags, vec!["meeting".to_string()]);
+
+        let deleted = TimeEntry {
+            server_deleted_at: Some(now),
+            ..previous
+        };
+        match plan_continue(deleted, now) {
+            Err(Error::Validation(errors)) => assert_eq!(errors.len(), 1),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{Error, ValidationError};
+use crate::models::{Delta, Entity, Project, TimeEntry};
+use crate::sync::prelude::{failed, SyncOutcome, SyncResult};
+use crate::toggl_api::models::Id;
+
+/// Toggl doesn't accept longer time entries.
+const MAX_DURATION_HOURS: i64 = 999;
+const MAX_DESCRIPTION_LENGTH: usize = 3000;
+const MAX_PROJECT_NAME_LENGTH: usize = 255;
+/// Client clocks are never perfectly in sync with the server.
+const CLOCK_SKEW_TOLERANCE_SECONDS: i64 = 60;
+
+/// Splits the changes which should be pushed to Toggl into those which satisfy the invariants
+/// of the entities and those which don't. The invalid ones are reported as failures right
+/// away without ever reaching Toggl. `known_projects` are used to check that time entries
+/// are assigned to projects in the same workspace.
+pub fn validate(
+    delta: Delta,
+    known_projects: &[Project],
+    now: DateTime<Utc>,
+) -> (Delta, SyncOutcome) {
+    let (projects, rejected_projects) = partition(delta.projects, validate_project);
+
+    let rejected_project_ids: HashSet<Id> = rejected_projects
+        .iter()
+        .filter_map(|result| match result {
+            SyncResult::Failed { entity_id, .. } => Some(*entity_id),
+            _ => None,
+        })
+        .collect();
+
+    let mut workspaces: HashMap<Id, Id> = known_projects
+        .iter()
+        .map(|project| (project.id, project.workspace_id))
+        .collect();
+    for project in projects.iter().flatten() {
+        workspaces.insert(project.id, project.workspace_id);
+    }
+
+    let (time_entries, rejected_time_entries) = partition(delta.time_entries, |te| {
+        validate_time_entry(te, &workspaces, &rejected_project_ids, now)
+    });
+
+    (
+        Delta {
+            user: delta.user,
+            projects,
+            time_entries,
+            favourites: delta.favourites,
+        },
+        SyncOutcome {
+            user: None,
+            projects: rejected_projects,
+            time_entries: rejected_time_entries,
+            favourites: vec![],
+            warnings: vec![],
+            goals: vec![],
+        },
+    )
+}
+
+fn partition<T, F>(entities: Option<Vec<T>>, validate: F) -> (Option<Vec<T>>, Vec<SyncResult<T>>)
+where
+    T: Entity,
+    F: Fn(&T) -> Vec<ValidationError>,
+{
+    let entities = match entities {
+        Some(entities) => entities,
+        None => return (None, vec![]),
+    };
+
+    let mut valid = vec![];
+    let mut rejected = vec![];
+
+    for entity in entities {
+        let errors = if entity.is_deleted() {
+            vec![] // deleting an invalid entity is fine
+        } else {
+            validate(&entity)
+        };
+
+        if errors.is_empty() {
+            valid.push(entity);
+        } else {
+            rejected.push(failed(entity.id(), Error::Validation(errors)));
+        }
+    }
+
+    (Some(valid), rejected)
+}
+
+fn invalid(field: &str, reason: &str) -> ValidationError {
+    ValidationError {
+        field: Some(field.to_string()),
+        reason: reason.to_string(),
+    }
+}
+
+pub fn validate_time_entry(
+    te: &TimeEntry,
+    project_workspaces: &HashMap<Id, Id>,
+    invalid_projects: &HashSet<Id>,
+    now: DateTime<Utc>,
+) -> Vec<ValidationError> {
+    let mut errors = vec![];
+    let latest_allowed = now + Duration::seconds(CLOCK_SKEW_TOLERANCE_SECONDS);
+
+    if te.workspace_id <= 0 {
+        errors.push(invalid("workspace_id", "The workspace is missing."));
+    }
+
+    if te.start > latest_allowed {
+        errors.push(invalid(
+            "start",
+            "The time entry cannot start in the future.",
+        ));
+    }
+
+    if let Some(duration) = te.duration {
+        if duration > Duration::hours(MAX_DURATION_HOURS).num_seconds() as u64 {
+            errors.push(invalid(
+                "duration",
+                &format!("The duration cannot exceed {} hours.", MAX_DURATION_HOURS),
+            ));
+        } else if te.start + Duration::seconds(duration as i64) > latest_allowed {
+            errors.push(invalid(
+                "duration",
+                "The time entry cannot end in the future.",
+            ));
+        }
+    }
+
+    if te.description.chars().count() > MAX_DESCRIPTION_LENGTH {
+        errors.push(invalid(
+            "description",
+            &format!(
+                "The description cannot be longer than {} characters.",
+                MAX_DESCRIPTION_LENGTH
+            ),
+        ));
+    }
+
+    if let Some(project_id) = te.project_id {
+        if invalid_projects.contains(&project_id) {
+            errors.push(invalid("project_id", "The project is invalid."));
+        } else {
+            match project_workspaces.get(&project_id) {
+                Some(workspace_id) if *workspace_id != te.workspace_id => errors.push(invalid(
+                    "project_id",
+                    "The project belongs to a different workspace.",
+                )),
+                None if project_id < 0 => {
+                    errors.push(invalid("project_id", "The project doesn't exist."))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    errors
+}
+
+pub fn validate_project(project: &Project) -> Vec<ValidationError> {
+    let mut errors = vec![];
+
+    if project.workspace_id <= 0 {
+        errors.push(invalid("workspace_id", "The workspace is missing."));
+    }
+
+    let name_length = project.name.trim().chars().count();
+    if name_length == 0 {
+        errors.push(invalid("name", "The name cannot be empty."));
+    } else if name_length > MAX_PROJECT_NAME_LENGTH {
+        errors.push(invalid(
+            "name",
+            &format!(
+                "The name cannot be longer than {} characters.",
+                MAX_PROJECT_NAME_LENGTH
+            ),
+        ));
+    }
+
+    if !is_hex_color(&project.color) {
+        errors.push(invalid("color", "The color must be in the #rrggbb format."));
+    }
+
+    errors
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, validate_project, validate_time_entry};
+    use crate::models::{fixtures, Delta, Project, TimeEntry};
+    use crate::sync::prelude::SyncResult;
+    use crate::toggl_api::models::Id;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use std::collections::{HashMap, HashSet};
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2019, 12, 10).and_hms(12, 0, 0)
+    }
+
+    fn project(id: Id, workspace_id: Id) -> Project {
+        Project {
+            workspace_id,
+            at: now(),
+            ..fixtures::project(id, "ABC")
+        }
+    }
+
+    fn time_entry(id: Id, project_id: Option<Id>) -> TimeEntry {
+        TimeEntry {
+            project_id,
+            at: now(),
+            ..fixtures::time_entry(id, now() - Duration::hours(2), Some(3600))
+        }
+    }
+
+    fn fields(errors: Vec<crate::error::ValidationError>) -> Vec<String> {
+        errors.into_iter().filter_map(|err| err.field).collect()
+    }
+
+    fn check(te: &TimeEntry) -> Vec<String> {
+        let mut workspaces = HashMap::new();
+        workspaces.insert(1, 1);
+        workspaces.insert(2, 2);
+        fields(validate_time_entry(te, &workspaces, &HashSet::new(), now()))
+    }
+
+    #[test]
+    fn accepts_valid_time_entry() {
+        assert!(check(&time_entry(1, Some(1))).is_empty());
+    }
+
+    #[test]
+    fn rejects_time_entry_starting_in_the_future() {
+        let te = TimeEntry {
+            start: now() + Duration::hours(1),
+            duration: None,
+            ..time_entry(1, None)
+        };
+        assert_eq!(check(&te), vec!["start"]);
+    }
+
+    #[test]
+    fn rejects_absurd_duration() {
+        let te = TimeEntry {
+            start: now() - Duration::days(365),
+            duration: Some(Duration::days(60).num_seconds() as u64),
+            ..time_entry(1, None)
+        };
+        assert_eq!(check(&te), vec!["duration"]);
+
+        let te = TimeEntry {
+            duration: Some(u64::MAX),
+            ..time_entry(1, None)
+        };
+        assert_eq!(check(&te), vec!["duration"]);
+    }
+
+    #[test]
+    fn rejects_time_entry_ending_in_the_future() {
+        let te = TimeEntry {
+            duration: Some(Duration::hours(5).num_seconds() as u64),
+            ..time_entry(1, None)
+        };
+        assert_eq!(check(&te), vec!["duration"]);
+    }
+
+    #[test]
+    fn rejects_missing_workspace() {
+        let te = TimeEntry {
+            workspace_id: 0,
+            ..time_entry(1, None)
+        };
+        assert_eq!(check(&te), vec!["workspace_id"]);
+    }
+
+    #[test]
+    fn rejects_project_from_another_workspace() {
+        assert_eq!(check(&time_entry(1, Some(2))), vec!["project_id"]);
+    }
+
+    #[test]
+    fn rejects_invalid_color_and_empty_name() {
+        let project = Project {
+            name: "  ".to_string(),
+            color: "red".to_string(),
+            ..project(1, 1)
+        };
+        assert_eq!(fields(validate_project(&project)), vec!["name", "color"]);
+    }
+
+    #[test]
+    fn rejects_time_entries_of_rejected_projects() {
+        let delta = Delta {
+            user: None,
+            projects: Some(vec![Project {
+                color: "#xyz".to_string(),
+                ..project(-1, 1)
+            }]),
+            time_entries: Some(vec![time_entry(-2, Some(-1)), time_entry(3, None)]),
+            favourites: None,
+        };
+
+        let (valid, rejected) = validate(delta, &[], now());
+
+        assert_eq!(valid.projects, Some(vec![]));
+        assert_eq!(valid.time_entries.unwrap().len(), 1);
+        assert_eq!(rejected.projects.len(), 1);
+        match &rejected.time_entries[..] {
+            [SyncResult::Failed {
+                entity_id, code, ..
+            }] => {
+                assert_eq!(*entity_id, -2);
+                assert_eq!(*code, "validation_failed");
+            }
+            other => panic!("Unexpected results {:?}", other),
+        }
+    }
+
+    #[test]
+    fn does_not_validate_deleted_entities() {
+        let delta = Delta {
+            user: None,
+            projects: None,
+            time_entries: Some(vec![TimeEntry {
+                workspace_id: 0,
+                server_deleted_at: Some(now()),
+                ..time_entry(1, None)
+            }]),
+            favourites: None,
+        };
+
+        let (valid, rejected) = validate(delta, &[], now());
+
+        assert_eq!(valid.time_entries.unwrap().len(), 1);
+        assert!(rejected.time_entries.is_empty());
+    }
+}
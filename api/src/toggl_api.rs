@@ -1,4 +1,5 @@
 pub mod endpoints;
+pub mod errors;
 pub mod models;
 pub mod time_entries;
 
@@ -59,6 +60,27 @@ impl TogglApi {
         self.make_request(endpoint)
     }
 
+    pub fn patch<T>(&self, endpoint: Endpoint<T>) -> Result<T, Error>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        match endpoint {
+            Endpoint::<T>::Patch(..) => self.make_request(endpoint),
+            _ => panic!("Patch requires a PATCH endpoint."),
+        }
+    }
+
+    /// Toggl answers the deletions with an empty body, so there's nothing to deserialize.
+    pub fn delete(&self, endpoint: Endpoint<()>) -> Result<(), Error> {
+        match endpoint {
+            Endpoint::<()>::Delete(url) => {
+                let mut res = self.client.delete(&url).send()?;
+                TogglApi::validate(&mut res)
+            }
+            _ => panic!("Delete requires a DELETE endpoint."),
+        }
+    }
+
     fn make_request<T>(&self, endpoint: Endpoint<T>) -> Result<T, Error>
     where
         T: Serialize + DeserializeOwned,
@@ -67,6 +89,8 @@ impl TogglApi {
             Endpoint::<T>::Get(url) => self.client.get(&url),
             Endpoint::<T>::Post(url, entity) => self.client.post(&url).json(&entity),
             Endpoint::<T>::Put(url, entity) => self.client.put(&url).json(&entity),
+            Endpoint::<T>::Patch(url, operations) => self.client.patch(&url).json(&operations),
+            Endpoint::<T>::Delete(url) => self.client.delete(&url),
         };
         let mut res = req.send()?;
 
@@ -79,9 +103,16 @@ impl TogglApi {
         if res.status().is_success() {
             Ok(())
         } else {
-            Err(Error::ApiError(
+            let retry_after = res
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok());
+
+            Err(Error::from_upstream(
                 res.status().as_u16(),
                 res.text().unwrap_or_else(|_| "Unknown error.".to_string()),
+                retry_after,
             ))
         }
     }
@@ -1,5 +1,5 @@
 use super::models::{Project, TimeEntry};
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 const BASE_URL: &str = "https://mobile.toggl.space/api";
 
@@ -10,6 +10,16 @@ pub enum Endpoint<T: Serialize + DeserializeOwned> {
     Get(Url),
     Post(Url, T),
     Put(Url, T),
+    Patch(Url, Vec<PatchOperation>),
+    Delete(Url),
+}
+
+/// A JSON Patch (RFC 6902) operation as accepted by the bulk endpoints of Toggl.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PatchOperation {
+    pub op: String,
+    pub path: String,
+    pub value: serde_json::Value,
 }
 
 pub trait CreateOrUpdate: Serialize + DeserializeOwned {
@@ -63,24 +73,37 @@ pub mod projects {
     use super::{Endpoint, BASE_URL};
     use chrono::{DateTime, Utc};
 
-    pub fn get(since: Option<DateTime<Utc>>) -> Endpoint<Vec<Project>> {
+    pub fn get(since: Option<DateTime<Utc>>, include_archived: bool) -> Endpoint<Vec<Project>> {
         let url = match since {
             Some(date) => format!(
-                "{}/v9/me/projects?since={}&include_archived=true",
+                "{}/v9/me/projects?since={}&include_archived={}",
                 BASE_URL,
-                date.timestamp()
+                date.timestamp(),
+                include_archived
+            ),
+            None => format!(
+                "{}/v9/me/projects?include_archived={}",
+                BASE_URL, include_archived
             ),
-            None => format!("{}/v9/me/projects?include_archived=true", BASE_URL),
         };
 
         Endpoint::<Vec<Project>>::Get(url)
     }
 }
 
+pub mod workspaces {
+    use super::super::models::Workspace;
+    use super::{Endpoint, BASE_URL};
+
+    pub fn get() -> Endpoint<Vec<Workspace>> {
+        Endpoint::<Vec<Workspace>>::Get(format!("{}/v9/me/workspaces", BASE_URL))
+    }
+}
+
 pub mod time_entries {
     use super::super::models::{Id, TimeEntry};
-    use super::{Endpoint, BASE_URL};
-    use chrono::{DateTime, Utc};
+    use super::{Endpoint, PatchOperation, BASE_URL};
+    use chrono::{DateTime, SecondsFormat, Utc};
     use serde::{Deserialize, Serialize};
 
     #[derive(Serialize, Deserialize, Debug)]
@@ -96,6 +119,9 @@ pub mod time_entries {
         start: DateTime<Utc>,
         duration: i64,
         description: String,
+        tags: Option<Vec<String>>,
+        #[serde(default)]
+        billable: bool,
         at: DateTime<Utc>,
     }
 
@@ -108,6 +134,8 @@ pub mod time_entries {
                 start: te.start,
                 duration: te.duration,
                 description: te.description.clone(),
+                tags: te.tags.clone(),
+                billable: te.billable,
                 at: te.at,
                 server_deleted_at: None,
                 created_with: None,
@@ -124,6 +152,58 @@ pub mod time_entries {
         Endpoint::<Vec<TimeEntry>>::Get(url)
     }
 
+    pub fn get_between(from: DateTime<Utc>, to: DateTime<Utc>) -> Endpoint<Vec<TimeEntry>> {
+        Endpoint::<Vec<TimeEntry>>::Get(format!(
+            "{}/v9/me/time_entries?start_date={}&end_date={}",
+            BASE_URL,
+            from.to_rfc3339_opts(SecondsFormat::Secs, true),
+            to.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ))
+    }
+
+    pub fn delete(workspace_id: Id, id: Id) -> Endpoint<()> {
+        Endpoint::<()>::Delete(format!(
+            "{}/v9/workspaces/{}/time_entries/{}",
+            BASE_URL, workspace_id, id
+        ))
+    }
+
+    /// The ids of the time entries which were and which weren't updated by a bulk patch.
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    pub struct BulkPatchResponse {
+        #[serde(default)]
+        pub success: Vec<Id>,
+        #[serde(default)]
+        pub failure: Vec<BulkPatchFailure>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    pub struct BulkPatchFailure {
+        pub id: Id,
+        pub message: String,
+    }
+
+    pub fn patch(
+        workspace_id: Id,
+        ids: &[Id],
+        operations: Vec<PatchOperation>,
+    ) -> Endpoint<BulkPatchResponse> {
+        let ids: Vec<_> = ids.iter().map(|id| id.to_string()).collect();
+        Endpoint::<BulkPatchResponse>::Patch(
+            format!(
+                "{}/v9/workspaces/{}/time_entries/{}",
+                BASE_URL,
+                workspace_id,
+                ids.join(",")
+            ),
+            operations,
+        )
+    }
+
+    pub fn get_by_id(id: Id) -> Endpoint<TimeEntry> {
+        Endpoint::<TimeEntry>::Get(format!("{}/v9/me/time_entries/{}", BASE_URL, id))
+    }
+
     pub fn current_running() -> Endpoint<RunningTimeEntryResponseData> {
         Endpoint::<RunningTimeEntryResponseData>::Get(format!(
             "{}/v8/time_entries/current",
@@ -0,0 +1,155 @@
+use serde_json::Value;
+
+use crate::error::ValidationError;
+
+/// Known phrases in Toggl error messages and the fields they refer to. The more specific
+/// phrases must come before the more generic ones.
+const FIELDS: &[(&str, &str)] = &[
+    ("project name", "name"),
+    ("project_id", "project_id"),
+    ("project", "project_id"),
+    ("workspace", "workspace_id"),
+    ("wid", "workspace_id"),
+    ("duration", "duration"),
+    ("start", "start"),
+    ("stop", "start"),
+    ("color", "color"),
+    ("colour", "color"),
+    ("description", "description"),
+    ("tag", "tags"),
+    ("name", "name"),
+];
+
+/// Parses the body of an unsuccessful Toggl response into a list of validation errors.
+/// Toggl answers with a JSON string, a JSON array of strings, an object with an error
+/// message (API v8) or an object mapping fields to messages, or just plain text.
+pub fn parse(body: &str) -> Vec<ValidationError> {
+    let body = body.trim();
+    if body.is_empty() {
+        return vec![];
+    }
+
+    match serde_json::from_str::<Value>(body) {
+        Ok(value) => from_json(&value, None),
+        Err(_) => vec![from_message(None, body)],
+    }
+}
+
+fn from_json(value: &Value, field: Option<&str>) -> Vec<ValidationError> {
+    match value {
+        Value::String(message) => vec![from_message(field, message)],
+        Value::Array(values) => values
+            .iter()
+            .flat_map(|value| from_json(value, field))
+            .collect(),
+        Value::Object(map) => {
+            if let Some(message) = map.get("message").and_then(Value::as_str) {
+                return vec![from_message(field, message)];
+            }
+
+            map.iter()
+                .flat_map(|(key, value)| match key.as_str() {
+                    "error" | "errors" => from_json(value, field),
+                    "code" | "tip" => vec![],
+                    key => from_json(value, Some(key)),
+                })
+                .collect()
+        }
+        Value::Null => vec![],
+        other => vec![from_message(field, &other.to_string())],
+    }
+}
+
+fn from_message(field: Option<&str>, message: &str) -> ValidationError {
+    let field = field.map(normalize_field).or_else(|| guess_field(message));
+
+    ValidationError {
+        field,
+        reason: message.trim().to_string(),
+    }
+}
+
+fn normalize_field(field: &str) -> String {
+    match field {
+        "wid" => "workspace_id".to_string(),
+        "pid" => "project_id".to_string(),
+        field => field.to_string(),
+    }
+}
+
+fn guess_field(message: &str) -> Option<String> {
+    let message = message.to_lowercase();
+    FIELDS
+        .iter()
+        .find(|(phrase, _)| message.contains(phrase))
+        .map(|(_, field)| field.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use crate::error::ValidationError;
+
+    fn error(field: Option<&str>, reason: &str) -> ValidationError {
+        ValidationError {
+            field: field.map(|f| f.to_string()),
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_json_string() {
+        let errors = parse("\"project name already exists\"");
+        assert_eq!(
+            errors,
+            vec![error(Some("name"), "project name already exists")]
+        );
+    }
+
+    #[test]
+    fn parses_plain_text() {
+        let errors = parse("Invalid project_id\n");
+        assert_eq!(
+            errors,
+            vec![error(Some("project_id"), "Invalid project_id")]
+        );
+    }
+
+    #[test]
+    fn parses_array_of_messages() {
+        let errors = parse("[\"Workspace not found\", \"Something odd\"]");
+        assert_eq!(
+            errors,
+            vec![
+                error(Some("workspace_id"), "Workspace not found"),
+                error(None, "Something odd")
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_v8_error_object() {
+        let errors = parse("{\"error\":{\"message\":\"Max duration exceeded\",\"code\":400}}");
+        assert_eq!(
+            errors,
+            vec![error(Some("duration"), "Max duration exceeded")]
+        );
+    }
+
+    #[test]
+    fn parses_field_map() {
+        let errors = parse("{\"pid\":[\"does not belong to the workspace\"]}");
+        assert_eq!(
+            errors,
+            vec![error(
+                Some("project_id"),
+                "does not belong to the workspace"
+            )]
+        );
+    }
+
+    #[test]
+    fn ignores_empty_body() {
+        assert!(parse("  ").is_empty());
+    }
+}
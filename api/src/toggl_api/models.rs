@@ -2,7 +2,10 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::convert::Into;
 
-use crate::models::{Project as UtopiaProject, TimeEntry as UtopiaTimeEntry, User as UtopiaUser};
+use crate::models::{
+    Project as UtopiaProject, TimeEntry as UtopiaTimeEntry, User as UtopiaUser,
+    Workspace as UtopiaWorkspace,
+};
 
 pub type Id = i64;
 pub type ApiToken = String;
@@ -12,6 +15,10 @@ pub struct User {
     pub id: Id,
     pub default_workspace_id: Id,
     pub fullname: String,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default = "crate::models::default_beginning_of_week")]
+    pub beginning_of_week: u32,
     pub api_token: ApiToken,
     pub at: DateTime<Utc>,
 }
@@ -23,10 +30,25 @@ pub struct Project {
     pub name: String,
     pub color: String,
     pub active: bool,
+    #[serde(default)]
+    pub billable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
     pub at: DateTime<Utc>,
     pub server_deleted_at: Option<DateTime<Utc>>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Workspace {
+    pub id: Id,
+    pub name: String,
+    pub default_hourly_rate: Option<f64>,
+    pub default_currency: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct TimeEntry {
     pub id: Id,
@@ -35,6 +57,9 @@ pub struct TimeEntry {
     pub project_id: Option<Id>,
     pub start: DateTime<Utc>,
     pub duration: i64,
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub billable: bool,
     pub at: DateTime<Utc>,
     pub server_deleted_at: Option<DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -47,6 +72,8 @@ impl Into<UtopiaUser> for User {
             id: self.id,
             default_workspace_id: self.default_workspace_id,
             fullname: self.fullname.clone(),
+            timezone: self.timezone.clone(),
+            beginning_of_week: self.beginning_of_week,
             api_token: self.api_token.clone(),
             at: self.at,
         }
@@ -61,6 +88,10 @@ impl Into<UtopiaProject> for Project {
             name: self.name.clone(),
             color: self.color,
             active: self.active,
+            billable: self.billable,
+            rate: self.rate,
+            currency: self.currency,
+            stats: None,
             at: self.at,
             server_deleted_at: self.server_deleted_at,
         }
@@ -80,18 +111,36 @@ impl Into<UtopiaTimeEntry> for TimeEntry {
             } else {
                 None
             },
+            tags: self.tags.unwrap_or_default(),
+            billable: self.billable,
+            earnings: None,
+            recurrence: None,
             at: self.at,
             server_deleted_at: self.server_deleted_at,
         }
     }
 }
 
+impl From<Workspace> for UtopiaWorkspace {
+    fn from(workspace: Workspace) -> UtopiaWorkspace {
+        UtopiaWorkspace {
+            id: workspace.id,
+            name: workspace.name,
+            default_hourly_rate: workspace.default_hourly_rate,
+            default_currency: workspace.default_currency,
+            at: workspace.at,
+        }
+    }
+}
+
 impl Into<User> for UtopiaUser {
     fn into(self) -> User {
         User {
             id: self.id,
             default_workspace_id: self.default_workspace_id,
             fullname: self.fullname.clone(),
+            timezone: self.timezone.clone(),
+            beginning_of_week: self.beginning_of_week,
             api_token: self.api_token.clone(),
             at: self.at,
         }
@@ -106,6 +155,9 @@ impl Into<Project> for UtopiaProject {
             name: self.name.clone(),
             color: self.color.clone(),
             active: self.active,
+            billable: self.billable,
+            rate: self.rate,
+            currency: self.currency.clone(),
             at: self.at,
             server_deleted_at: self.server_deleted_at,
         }
@@ -124,6 +176,8 @@ impl Into<TimeEntry> for UtopiaTimeEntry {
                 .duration
                 .map(|d| d as i64)
                 .unwrap_or(-self.start.timestamp()) as i64,
+            tags: Some(self.tags),
+            billable: self.billable,
             at: self.at,
             server_deleted_at: self.server_deleted_at,
             created_with: None,